@@ -3,132 +3,894 @@
 //! This module transforms a CDP-style DOM tree into the Markdown AST
 //! defined in turndown-core.
 
+use std::cell::RefCell;
+
 use crate::node::{Node, NodeType};
-use turndown_core::{Block, Inline, ListItem, Options};
+use turndown_core::{
+    expand_table_spans, Alignment, Block, CollapsedText, Content, Element, Filter, Inline,
+    LimitCounter, Limits, ListItem, MarkupStyle, Options, Replacement, Result, Rule, Rules,
+    SpannedCell, SpannedRow, TableFallback, TableSpans, WhitespaceCollapser, WhitespaceKind,
+    BLOCK_ELEMENTS, VOID_ELEMENTS,
+};
+
+/// Table cell content and alignment, before span expansion
+type TableCell = SpannedCell<(Vec<Inline>, Alignment)>;
+
+/// Table row of [`TableCell`]s, before span expansion
+type TableRow = SpannedRow<(Vec<Inline>, Alignment)>;
 
 /// Convert a CDP Node tree to a Markdown AST Block
-pub fn convert(node: &Node, options: &Options) -> Block {
-    let ctx = Context::default();
+///
+/// Custom `rules` take precedence over built-in conversion. `keep_filters` and
+/// `remove_filters` select elements by tag name or predicate; like turndown.js,
+/// they only apply to elements that no rule converts.
+///
+/// The tree is checked against `options.limits` first; when truncating, only
+/// the nodes in front of the first node over a limit are converted.
+pub fn convert(
+    node: &Node,
+    options: &Options,
+    rules: &Rules,
+    keep_filters: &[Filter],
+    remove_filters: &[Filter],
+) -> Result<Block> {
+    let Some(path) = find_limit(node, &options.limits)? else {
+        return Ok(convert_tree(node, options, rules, keep_filters, remove_filters));
+    };
+    let Some(tree) = truncate_tree(node, &path) else {
+        return Ok(Block::Document(vec![]));
+    };
+    let block = convert_tree(&tree, options, rules, keep_filters, remove_filters);
+    tree.drop_iteratively();
+    Ok(block)
+}
+
+/// Convert a tree that is within the limits
+fn convert_tree(
+    node: &Node,
+    options: &Options,
+    rules: &Rules,
+    keep_filters: &[Filter],
+    remove_filters: &[Filter],
+) -> Block {
+    let (whitespace, subtree_ends) = collapse_whitespace(node);
+    let ctx = Context {
+        in_pre: false,
+        rules,
+        keep_filters,
+        remove_filters,
+        whitespace: &whitespace,
+        subtree_ends: &subtree_ends,
+        ancestors: RefCell::new(Vec::new()),
+    };
 
     // If the root node is itself an element, convert it directly
     if node.is_element() {
-        if let Some(block) = convert_element(node, options, &ctx) {
+        let block = match convert_element(node, 0, options, &ctx) {
+            Step::Done(block) => block,
+            Step::Visit(frame) => run(frame, options, &ctx).into_block(),
+        };
+        if let Some(block) = block {
             return flatten_document(block);
         }
     }
 
     // Otherwise, convert children
-    let blocks = convert_children(node, options, &ctx);
-    Block::Document(blocks)
+    let children = Children::new(node, 0, false, Finish::Document);
+    let root = Frame::new(&ctx, &[], Work::Children(children));
+    run(root, options, &ctx)
+        .into_block()
+        .unwrap_or(Block::Document(vec![]))
 }
 
-/// Flatten nested documents
-fn flatten_document(block: Block) -> Block {
-    match block {
-        Block::Document(blocks) if blocks.len() == 1 => {
-            flatten_document(blocks.into_iter().next().unwrap())
+/// Walk the tree in document order and return the child index path to the
+/// first node over a limit, or the limit error when not truncating
+fn find_limit(root: &Node, limits: &Limits) -> Result<Option<Vec<usize>>> {
+    if !limits.limits_tree() {
+        return Ok(None);
+    }
+    let mut counter = LimitCounter::new(limits);
+
+    // A document root is not a node of the converted content
+    let root_depth = match root.node_type {
+        NodeType::Document | NodeType::DocumentFragment => 0,
+        _ => 1,
+    };
+    if root_depth == 1 {
+        if let Some(err) = counter.visit(1, node_bytes(root)) {
+            limits.exceeded(err)?;
+            return Ok(Some(Vec::new()));
         }
-        other => other,
     }
+
+    let mut path = Vec::new();
+    // Nodes to visit with their depth and index among their siblings
+    let mut stack: Vec<(&Node, usize, usize)> = Vec::new();
+    let push_children = |stack: &mut Vec<_>, node, depth| {
+        let children = child_nodes(node).iter().enumerate().rev();
+        stack.extend(children.map(|(i, child)| (child, depth, i)));
+    };
+    push_children(&mut stack, root, root_depth + 1);
+    while let Some((node, depth, index)) = stack.pop() {
+        path.truncate(depth - root_depth - 1);
+        path.push(index);
+        if let Some(err) = counter.visit(depth, node_bytes(node)) {
+            limits.exceeded(err)?;
+            return Ok(Some(path));
+        }
+        push_children(&mut stack, node, depth + 1);
+    }
+    Ok(None)
 }
 
-/// Context for conversion (tracks parent elements)
-#[derive(Default, Clone)]
-struct Context {
-    in_pre: bool,
+/// Input bytes of a node: its text and attributes
+fn node_bytes(node: &Node) -> usize {
+    let value = node.node_value.as_ref().map_or(0, String::len);
+    value + node.attributes.iter().flatten().map(String::len).sum::<usize>()
 }
 
-/// Convert children of a node to blocks
-fn convert_children(node: &Node, options: &Options, ctx: &Context) -> Vec<Block> {
-    let mut blocks = Vec::new();
+/// Copy of `node` holding only the nodes in front of the one at `path`,
+/// or `None` when `path` points at `node` itself
+fn truncate_tree(node: &Node, path: &[usize]) -> Option<Node> {
+    // Nodes along the path, copied from the innermost one outwards
+    let mut ancestors = Vec::with_capacity(path.len());
+    let mut node = node;
+    for &index in path {
+        ancestors.push((node, index));
+        node = &child_nodes(node)[index];
+    }
 
-    for child in node.children() {
-        match child.node_type {
-            NodeType::Text => {
-                // Text at block level gets wrapped in paragraph if non-empty
-                let text = child.node_value.as_deref().unwrap_or("");
-                if !text.trim().is_empty() && !ctx.in_pre {
-                    let inlines = vec![Inline::Text(escape_markdown(&collapse_whitespace(text)))];
-                    blocks.push(Block::Paragraph(inlines));
+    let mut tree = None;
+    for (node, index) in ancestors.into_iter().rev() {
+        let mut kept = child_nodes(node)[..index].to_vec();
+        kept.extend(tree);
+        tree = Some(Node {
+            node_type: node.node_type,
+            node_name: node.node_name.clone(),
+            node_value: node.node_value.clone(),
+            attributes: node.attributes.clone(),
+            children: Some(kept),
+        });
+    }
+    tree
+}
+
+/// Collapse whitespace across text nodes in document order, like turndown's
+/// `collapseWhitespace` (see [`WhitespaceCollapser`])
+///
+/// Nodes are numbered in document order, the root being 0. Along with the
+/// collapsed text, this returns the index following the subtree of each node.
+fn collapse_whitespace(root: &Node) -> (CollapsedText, Vec<usize>) {
+    /// Step of the walk: a node to enter, whether it sits in `pre`, or the
+    /// node to leave at an index
+    enum Walk<'n> {
+        Enter(&'n Node, bool),
+        Leave(usize, Option<WhitespaceKind>),
+    }
+
+    let mut collapser = WhitespaceCollapser::new();
+    let mut subtree_ends = Vec::new();
+    let mut stack = vec![Walk::Enter(root, false)];
+
+    while let Some(walk) = stack.pop() {
+        let (node, in_pre) = match walk {
+            Walk::Enter(node, in_pre) => (node, in_pre),
+            Walk::Leave(index, kind) => {
+                subtree_ends[index] = subtree_ends.len();
+                // Elements are visited again when leaving them
+                if let Some(kind) = kind {
+                    collapser.element(kind);
                 }
+                continue;
             }
-            NodeType::Element => {
-                if let Some(block) = convert_element(child, options, ctx) {
-                    blocks.push(block);
-                }
+        };
+
+        let index = subtree_ends.len();
+        subtree_ends.push(index + 1);
+        let mut kind = None;
+        let mut children_in_pre = in_pre;
+        match node.node_type {
+            NodeType::Text if !in_pre => {
+                collapser.text(index, node.node_value.as_deref().unwrap_or(""));
+            }
+            NodeType::Element if !in_pre => {
+                let tag = node.tag_name();
+                let element_kind = WhitespaceKind::of(&tag);
+                collapser.element(element_kind);
+                kind = Some(element_kind);
+                children_in_pre = tag == "pre";
             }
             _ => {}
         }
+        stack.push(Walk::Leave(index, kind));
+        let children = child_nodes(node).iter().rev();
+        stack.extend(children.map(|child| Walk::Enter(child, children_in_pre)));
     }
 
-    blocks
+    (collapser.finish(), subtree_ends)
 }
 
-/// Convert an element node to a Block
-fn convert_element(node: &Node, options: &Options, ctx: &Context) -> Option<Block> {
-    let tag = node.tag_name();
+fn child_nodes(node: &Node) -> &[Node] {
+    node.children.as_deref().unwrap_or_default()
+}
 
-    match tag.as_str() {
-        // Block elements
-        "p" => {
-            let inlines = collect_inlines(node, options, ctx);
-            if inlines_are_blank(&inlines) {
-                None
-            } else {
-                Some(Block::Paragraph(inlines))
+/// Flatten nested documents
+fn flatten_document(mut block: Block) -> Block {
+    while let Block::Document(blocks) = &mut block {
+        match blocks.len() {
+            1 => block = blocks.remove(0),
+            _ => break,
+        }
+    }
+    block
+}
+
+/// Context for conversion (tracks parent elements, rules and element filters)
+#[derive(Clone)]
+struct Context<'a> {
+    in_pre: bool,
+    rules: &'a Rules,
+    keep_filters: &'a [Filter],
+    remove_filters: &'a [Filter],
+    /// Text nodes after cross-node whitespace collapsing, by node index
+    whitespace: &'a CollapsedText,
+    /// Index following the subtree of each node (see [`collapse_whitespace`])
+    subtree_ends: &'a [usize],
+    /// Tag names of the elements being converted (only tracked for custom
+    /// rules and predicate filters)
+    ancestors: RefCell<Vec<String>>,
+}
+
+impl Context<'_> {
+    /// Whether custom rules or predicate filters look at elements
+    fn has_rules(&self) -> bool {
+        !self.rules.is_empty()
+            || self
+                .keep_filters
+                .iter()
+                .chain(self.remove_filters)
+                .any(|filter| matches!(filter, Filter::Predicate(_)))
+    }
+
+    /// Content of the text node at `index`: collapsed, or verbatim inside `pre`
+    fn text<'n>(&'n self, node: &'n Node, index: usize) -> &'n str {
+        self.whitespace
+            .get(index)
+            .unwrap_or_else(|| node.node_value.as_deref().unwrap_or(""))
+    }
+
+    /// Text content of the element at `index` with collapsed whitespace
+    fn text_content(&self, node: &Node, index: usize) -> String {
+        let mut text = String::new();
+        // Nodes are popped in document order, so their indexes follow
+        let mut stack = vec![node];
+        let mut index = index;
+        while let Some(node) = stack.pop() {
+            match node.node_type {
+                NodeType::Text => text.push_str(self.text(node, index)),
+                _ => stack.extend(child_nodes(node).iter().rev()),
             }
+            index += 1;
         }
+        text
+    }
 
-        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-            let level = tag.chars().nth(1)?.to_digit(10)? as u8;
-            let inlines = collect_inlines(node, options, ctx);
-            if inlines_are_blank(&inlines) {
+    /// Children of the node at `index`, with their indexes
+    fn children<'n>(
+        &'n self,
+        node: &'n Node,
+        index: usize,
+    ) -> impl Iterator<Item = (usize, &'n Node)> {
+        let mut next = index + 1;
+        child_nodes(node).iter().map(move |child| {
+            let index = next;
+            next = self.subtree_ends[index];
+            (index, child)
+        })
+    }
+
+    /// Element children of the node at `index`, with their indexes
+    fn element_children<'n>(
+        &'n self,
+        node: &'n Node,
+        index: usize,
+    ) -> impl Iterator<Item = (usize, &'n Node)> {
+        self.children(node, index).filter(|(_, child)| child.is_element())
+    }
+}
+
+/// Conversion of a node, possibly waiting for children to be converted
+///
+/// Rather than recursing into the children, a conversion returns a
+/// [`Step::Visit`] with a [`Frame`] that converts them and builds the result.
+/// [`run`] keeps the frames on an explicit stack, so the nesting depth of a
+/// tree is not limited by the call stack.
+enum Step<'c, T> {
+    Done(T),
+    Visit(Frame<'c>),
+}
+
+/// Result of a frame, handed to the frame below it
+enum Converted {
+    Block(Option<Block>),
+    Inline(Option<Inline>),
+    Item(ListItem),
+    Cell(TableCell),
+    /// Cells of a pipe table row, and whether one of them is a th
+    Row(Vec<TableCell>, bool),
+    Caption(Option<Vec<Inline>>),
+}
+
+impl Converted {
+    /// The block converted for the root; an inline element as the root is
+    /// wrapped in a paragraph
+    fn into_block(self) -> Option<Block> {
+        match self {
+            Converted::Block(block) => block,
+            Converted::Inline(inline) => inline.map(|inline| Block::Paragraph(vec![inline])),
+            _ => None,
+        }
+    }
+}
+
+/// Conversion waiting for children on the stack of [`run`]
+struct Frame<'c> {
+    work: Work<'c>,
+    /// Tags to record as ancestors while the frame is on the stack (only
+    /// tracked for custom rules and predicate filters)
+    ancestors: Vec<String>,
+    /// Length of the ancestor stack below the frame
+    depth: usize,
+}
+
+impl<'c> Frame<'c> {
+    /// A frame within the elements of `scope`, which are ancestors for the
+    /// rules matched while it is converted
+    fn new(ctx: &Context, scope: &[&str], work: Work<'c>) -> Self {
+        let ancestors = if ctx.has_rules() {
+            scope.iter().map(|tag| tag.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+        Frame {
+            work,
+            ancestors,
+            depth: 0,
+        }
+    }
+
+    /// Record the ancestors of the frame as it goes on the stack
+    fn enter(mut self, ctx: &Context) -> Self {
+        let mut ancestors = ctx.ancestors.borrow_mut();
+        self.depth = ancestors.len();
+        ancestors.append(&mut self.ancestors);
+        self
+    }
+}
+
+/// What a frame converts
+enum Work<'c> {
+    Children(Children<'c>),
+    List(List<'c>),
+    Table(Table<'c>),
+    Row(Row<'c>),
+}
+
+impl<'c> Work<'c> {
+    /// Convert until a child has to be visited, or return `None` when done
+    fn advance(&mut self, options: &'c Options, ctx: &'c Context<'c>) -> Option<Frame<'c>> {
+        match self {
+            Work::Children(children) => children.advance(options, ctx),
+            Work::List(list) => list.advance(ctx),
+            Work::Table(table) => table.advance(ctx),
+            Work::Row(row) => row.advance(ctx),
+        }
+    }
+
+    /// Take the result of a visited child
+    fn accept(&mut self, converted: Converted) {
+        match (self, converted) {
+            (Work::Children(children), Converted::Block(block)) => children.blocks.extend(block),
+            (Work::Children(children), Converted::Inline(inline)) => {
+                children.inlines.extend(inline)
+            }
+            (Work::List(list), Converted::Item(item)) => list.items.push(item),
+            (Work::Table(table), Converted::Caption(caption)) => table.converted_caption = caption,
+            (Work::Table(table), Converted::Row(cells, has_th)) => {
+                table.pipe_rows.push((cells, has_th))
+            }
+            (Work::Table(table), Converted::Item(row)) => table.list_rows.push(row),
+            (Work::Row(row), Converted::Cell(cell)) => row.pipe_cells.push(cell),
+            (Work::Row(row), Converted::Item(cell)) => row.list_cells.push(cell),
+            _ => unreachable!("frames only visit children whose results they take"),
+        }
+    }
+
+    fn finish(self, options: &Options) -> Converted {
+        match self {
+            Work::Children(children) => children.finish(options),
+            Work::List(list) => list.finish(),
+            Work::Table(table) => table.finish(options),
+            Work::Row(row) => row.finish(),
+        }
+    }
+}
+
+/// Children of a node, converted to blocks or, in inline context, inlines
+struct Children<'c> {
+    children: &'c [Node],
+    /// Index of the node
+    index: usize,
+    inline: bool,
+    next: usize,
+    /// Index of the next child
+    next_index: usize,
+    blocks: Vec<Block>,
+    /// Inlines in inline context, else those of the paragraph in progress
+    inlines: Vec<Inline>,
+    finish: Finish<'c>,
+}
+
+/// What a node builds from its converted children
+enum Finish<'c> {
+    /// The children of the root as a document
+    Document,
+    Paragraph,
+    Heading(u8),
+    BlockQuote,
+    /// An element holding blocks and paragraphs
+    Container,
+    /// An unknown element, converted like a container or, when that yields
+    /// nothing, like a paragraph
+    Unknown,
+    /// A list item, with the checked state of a leading checkbox
+    ListItem(Option<bool>),
+    /// A list item without blocks, as a single paragraph
+    ListItemParagraph(Option<bool>),
+    Strong,
+    Emphasis,
+    Link {
+        href: &'c str,
+        title: Option<String>,
+    },
+    /// A del/s/strike/mark/sup/sub element
+    Markup(String),
+    /// A pass-through inline container
+    Flatten,
+    Cell {
+        alignment: Alignment,
+        colspan: usize,
+        rowspan: usize,
+    },
+    /// Cell of a table laid out as a list
+    CellItem,
+    Caption,
+    Rule {
+        rule: &'c Rule,
+        element: Box<Element>,
+        target: Target,
+    },
+}
+
+/// Where the replacement of a rule goes
+#[derive(Clone, Copy)]
+enum Target {
+    Block,
+    Inline,
+    ListItem,
+}
+
+impl<'c> Children<'c> {
+    /// Convert the children of the node at `index`
+    fn new(node: &'c Node, index: usize, inline: bool, finish: Finish<'c>) -> Self {
+        Children {
+            children: child_nodes(node),
+            index,
+            inline,
+            next: 0,
+            next_index: index + 1,
+            blocks: Vec::new(),
+            inlines: Vec::new(),
+            finish,
+        }
+    }
+
+    /// Convert children until one has to visit children of its own
+    ///
+    /// In block context, consecutive text and phrasing elements are collected
+    /// into one paragraph, which ends at the next block.
+    fn advance(&mut self, options: &'c Options, ctx: &'c Context<'c>) -> Option<Frame<'c>> {
+        loop {
+            while let Some(child) = self.children.get(self.next) {
+                let index = self.next_index;
+                self.next += 1;
+                self.next_index = ctx.subtree_ends[index];
+                if self.inline || is_inline_node(child) {
+                    match convert_inline_node(child, index, options, ctx) {
+                        Step::Done(inline) => self.inlines.extend(inline),
+                        Step::Visit(frame) => return Some(frame),
+                    }
+                } else {
+                    self.end_paragraph();
+                    match convert_node(child, index, options, ctx) {
+                        Step::Done(block) => self.blocks.extend(block),
+                        Step::Visit(frame) => return Some(frame),
+                    }
+                }
+            }
+            if !self.retry_inline() {
+                return None;
+            }
+        }
+    }
+
+    /// Start over in inline context when the children of an unknown element
+    /// or list item yield no blocks
+    fn retry_inline(&mut self) -> bool {
+        if self.inline {
+            return false;
+        }
+        self.end_paragraph();
+        if !self.blocks.is_empty() {
+            return false;
+        }
+        self.finish = match self.finish {
+            Finish::Unknown => Finish::Paragraph,
+            Finish::ListItem(checked) => Finish::ListItemParagraph(checked),
+            _ => return false,
+        };
+        self.inline = true;
+        self.next = 0;
+        self.next_index = self.index + 1;
+        true
+    }
+
+    /// Add the inlines collected in block context as a paragraph
+    fn end_paragraph(&mut self) {
+        self.blocks.extend(paragraph(std::mem::take(&mut self.inlines)));
+    }
+
+    fn finish(mut self, options: &Options) -> Converted {
+        if !self.inline {
+            self.end_paragraph();
+        }
+        let Children {
+            inline,
+            mut blocks,
+            mut inlines,
+            finish,
+            ..
+        } = self;
+
+        match finish {
+            Finish::Document => Converted::Block(Some(Block::Document(blocks))),
+            Finish::Paragraph => Converted::Block(paragraph(inlines)),
+            Finish::Heading(level) => Converted::Block(if inlines_are_blank(&inlines) {
                 None
             } else {
                 Some(Block::Heading {
                     level,
                     content: inlines,
                 })
-            }
-        }
-
-        "blockquote" => {
-            let blocks = convert_children(node, options, ctx);
-            if blocks.is_empty() {
+            }),
+            Finish::BlockQuote => Converted::Block(if blocks.is_empty() {
                 None
             } else {
                 Some(Block::BlockQuote(blocks))
+            }),
+            // Return as document fragment (will be flattened)
+            Finish::Container | Finish::Unknown => Converted::Block(if blocks.len() == 1 {
+                blocks.pop()
+            } else if blocks.is_empty() {
+                None
+            } else {
+                Some(Block::Document(blocks))
+            }),
+            Finish::ListItem(checked) => {
+                Converted::Item(ListItem::new(blocks).with_checked(checked))
             }
-        }
+            Finish::ListItemParagraph(checked) => {
+                let item = ListItem::new(vec![Block::Paragraph(inlines)]);
+                Converted::Item(item.with_checked(checked))
+            }
+            Finish::Strong => Converted::Inline(if markup_is_blank(&inlines) {
+                None
+            } else {
+                Some(Inline::Strong(inlines))
+            }),
+            Finish::Emphasis => Converted::Inline(if markup_is_blank(&inlines) {
+                None
+            } else {
+                Some(Inline::Emphasis(inlines))
+            }),
+            Finish::Link { href, title } => {
+                if href.is_empty() && title.is_none() {
+                    // No link target, just return content
+                    if inlines.len() == 1 {
+                        return Converted::Inline(inlines.pop());
+                    }
+                    return Converted::Inline(None);
+                }
 
-        "ul" => {
-            let items = collect_list_items(node, options, ctx);
-            if items.is_empty() {
+                Converted::Inline(Some(Inline::Link {
+                    content: inlines,
+                    url: href.to_string(),
+                    title,
+                }))
+            }
+            Finish::Markup(tag) => Converted::Inline(if inlines.is_empty() {
                 None
             } else {
-                Some(Block::List {
-                    ordered: false,
-                    start: 1,
-                    items,
-                })
+                Some(markup_inline(&tag, inlines))
+            }),
+            Finish::Flatten => Converted::Inline(flatten_inlines(inlines)),
+            Finish::Cell { alignment, colspan, rowspan } => Converted::Cell(SpannedCell {
+                value: (inlines, alignment),
+                colspan,
+                rowspan,
+            }),
+            Finish::CellItem => Converted::Item(ListItem::new(blocks)),
+            Finish::Caption => Converted::Caption((!inlines.is_empty()).then_some(inlines)),
+            Finish::Rule { rule, element, target } => {
+                let content = if inline {
+                    Content::Inlines(inlines)
+                } else {
+                    Content::Blocks(blocks)
+                };
+                let replacement = rule.replace(&element, content);
+                match target {
+                    Target::Block => Converted::Block(replacement.into_block()),
+                    Target::Inline => Converted::Inline(replacement_inline(replacement, options)),
+                    Target::ListItem => Converted::Item(ListItem::new(replacement.into_blocks())),
+                }
+            }
+        }
+    }
+}
+
+/// Items of a list, converted one after another
+struct List<'c> {
+    /// The li children with their indexes
+    lis: Vec<(usize, &'c Node)>,
+    next: usize,
+    ordered: bool,
+    start: u32,
+    items: Vec<ListItem>,
+}
+
+impl<'c> List<'c> {
+    fn advance(&mut self, ctx: &'c Context<'c>) -> Option<Frame<'c>> {
+        let (index, li) = *self.lis.get(self.next)?;
+        self.next += 1;
+        Some(list_item(li, index, ctx))
+    }
+
+    fn finish(self) -> Converted {
+        Converted::Block(if self.items.is_empty() {
+            None
+        } else {
+            Some(Block::List {
+                ordered: self.ordered,
+                start: self.start,
+                items: self.items,
+            })
+        })
+    }
+}
+
+/// Rows and caption of a table, converted as a pipe table or laid out as a
+/// list
+struct Table<'c> {
+    as_list: bool,
+    /// Rows with their indexes, the sections holding them and the indexes
+    /// of their row groups
+    rows: Vec<(usize, &'c Node, Option<String>, usize)>,
+    next: usize,
+    /// Caption element still to convert, with its index
+    caption: Option<(usize, &'c Node)>,
+    converted_caption: Option<Vec<Inline>>,
+    /// Caption taken from the `summary` attribute when there is no caption
+    summary: Option<Vec<Inline>>,
+    pipe_rows: Vec<(Vec<TableCell>, bool)>,
+    list_rows: Vec<ListItem>,
+}
+
+impl<'c> Table<'c> {
+    /// Convert the rows, then the caption
+    fn advance(&mut self, ctx: &'c Context<'c>) -> Option<Frame<'c>> {
+        if let Some(&(index, tr, _, _)) = self.rows.get(self.next) {
+            self.next += 1;
+            return Some(row_frame(tr, index, self.as_list, ctx));
+        }
+        let (index, caption) = self.caption.take()?;
+        let caption = Children::new(caption, index, true, Finish::Caption);
+        Some(Frame::new(ctx, &["caption"], Work::Children(caption)))
+    }
+
+    fn finish(self, options: &Options) -> Converted {
+        let caption = self.converted_caption.or(self.summary);
+        if !self.as_list {
+            // The header is the first thead row, or else the first direct tr
+            // holding a th cell, or else the first row
+            let mut rows = Vec::new();
+            let (mut thead_row, mut th_row) = (None, None);
+            let converted = self.rows.into_iter().zip(self.pipe_rows);
+            for ((_, _, section, group), (cells, has_th)) in converted {
+                if cells.is_empty() {
+                    continue;
+                }
+                if section.as_deref() == Some("thead") {
+                    thead_row.get_or_insert(rows.len());
+                } else if section.is_none() && has_th {
+                    th_row.get_or_insert(rows.len());
+                }
+                rows.push(SpannedRow { cells, group });
             }
+            let header = thead_row.or(th_row).unwrap_or(0);
+            return Converted::Block(pipe_table(rows, header, caption, options));
         }
 
+        let list = Block::List {
+            ordered: false,
+            start: 1,
+            items: self.list_rows,
+        };
+        Converted::Block(Some(match caption {
+            Some(caption) => Block::Document(vec![Block::Paragraph(caption), list]),
+            None => list,
+        }))
+    }
+}
+
+/// Cells of a table row
+struct Row<'c> {
+    as_list: bool,
+    /// The th/td cells with their indexes
+    cells: Vec<(usize, &'c Node)>,
+    next: usize,
+    pipe_cells: Vec<TableCell>,
+    list_cells: Vec<ListItem>,
+}
+
+impl<'c> Row<'c> {
+    fn advance(&mut self, ctx: &'c Context<'c>) -> Option<Frame<'c>> {
+        let (index, cell) = *self.cells.get(self.next)?;
+        self.next += 1;
+        if self.as_list {
+            let tag = cell.tag_name();
+            return Some(visit_children(ctx, &tag, cell, index, false, Finish::CellItem));
+        }
+        let finish = Finish::Cell {
+            alignment: cell_alignment(cell),
+            colspan: cell_span(cell, "colspan"),
+            rowspan: cell_span(cell, "rowspan"),
+        };
+        Some(Frame::new(ctx, &[], Work::Children(Children::new(cell, index, true, finish))))
+    }
+
+    fn finish(self) -> Converted {
+        if !self.as_list {
+            let has_th = self.cells.iter().any(|(_, cell)| cell.tag_name() == "th");
+            return Converted::Row(self.pipe_cells, has_th);
+        }
+        Converted::Item(ListItem::new(vec![Block::List {
+            ordered: false,
+            start: 1,
+            items: self.list_cells,
+        }]))
+    }
+}
+
+/// Drive a conversion to completion
+///
+/// Frames go on an explicit stack. The top frame converts its children until
+/// one has to visit children of its own, which pushes a frame for them. Once
+/// done, a frame builds its result and hands it to the frame below, or
+/// returns it for the root.
+fn run<'c>(root: Frame<'c>, options: &'c Options, ctx: &'c Context<'c>) -> Converted {
+    let mut stack = vec![root.enter(ctx)];
+
+    loop {
+        let frame = stack.last_mut().unwrap();
+        if let Some(child) = frame.work.advance(options, ctx) {
+            stack.push(child.enter(ctx));
+            continue;
+        }
+
+        let frame = stack.pop().unwrap();
+        ctx.ancestors.borrow_mut().truncate(frame.depth);
+        let converted = frame.work.finish(options);
+        match stack.last_mut() {
+            Some(parent) => parent.work.accept(converted),
+            None => return converted,
+        }
+    }
+}
+
+/// Convert the children of the element at `index`, which is an ancestor for
+/// the rules matched meanwhile
+fn visit_children<'c>(
+    ctx: &Context,
+    tag: &str,
+    node: &'c Node,
+    index: usize,
+    inline: bool,
+    finish: Finish<'c>,
+) -> Frame<'c> {
+    Frame::new(ctx, &[tag], Work::Children(Children::new(node, index, inline, finish)))
+}
+
+/// Convert a child node at block level (see [`is_inline_node`] for the
+/// nodes converted as part of a paragraph instead)
+fn convert_node<'c>(
+    child: &'c Node,
+    index: usize,
+    options: &'c Options,
+    ctx: &'c Context<'c>,
+) -> Step<'c, Option<Block>> {
+    match child.node_type {
+        NodeType::Element => convert_element(child, index, options, ctx),
+        _ => Step::Done(None),
+    }
+}
+
+/// Whether a node at block level belongs to a paragraph with its
+/// neighbouring text and phrasing elements
+fn is_inline_node(node: &Node) -> bool {
+    match node.node_type {
+        NodeType::Text => true,
+        NodeType::Element => matches!(
+            node.tag_name().as_str(),
+            "a" | "strong" | "b" | "em" | "i" | "code" | "span" | "img" | "br" | "del" | "s"
+                | "strike" | "mark" | "sup" | "sub"
+        ),
+        _ => false,
+    }
+}
+
+/// Convert the element node at `index` to a Block
+fn convert_element<'c>(
+    node: &'c Node,
+    index: usize,
+    options: &'c Options,
+    ctx: &'c Context<'c>,
+) -> Step<'c, Option<Block>> {
+    let tag = node.tag_name();
+
+    if let Some(frame) = apply_rule(node, index, &tag, ctx, Target::Block) {
+        return Step::Visit(frame);
+    }
+
+    match filter_action(node, &tag, options, ctx) {
+        Some(FilterAction::Keep) => return Step::Done(Some(Block::HtmlBlock(node.outer_html()))),
+        Some(FilterAction::Remove) => return Step::Done(None),
+        None => {}
+    }
+
+    let children =
+        |inline, finish| Step::Visit(visit_children(ctx, &tag, node, index, inline, finish));
+
+    match tag.as_str() {
+        // Block elements
+        "p" => children(true, Finish::Paragraph),
+
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let Some(level) = tag.chars().nth(1).and_then(|c| c.to_digit(10)) else {
+                return Step::Done(None);
+            };
+            children(true, Finish::Heading(level as u8))
+        }
+
+        "blockquote" => children(false, Finish::BlockQuote),
+
+        "ul" => Step::Visit(list_frame(node, index, false, 1, ctx)),
+
         "ol" => {
             let start = node
                 .attr("start")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(1);
-            let items = collect_list_items(node, options, ctx);
-            if items.is_empty() {
-                None
-            } else {
-                Some(Block::List {
-                    ordered: true,
-                    start,
-                    items,
-                })
-            }
+            Step::Visit(list_frame(node, index, true, start, ctx))
         }
 
         "pre" => {
@@ -150,332 +912,639 @@ fn convert_element(node: &Node, options: &Options, ctx: &Context) -> Option<Bloc
                     turndown_core::CodeBlockStyle::Fenced
                 );
 
-                Some(Block::CodeBlock {
+                Step::Done(Some(Block::CodeBlock {
                     language,
                     code: code_text,
                     fenced,
-                })
+                }))
             } else {
                 // Pre without code
                 let text = node.text_content();
-                Some(Block::CodeBlock {
+                Step::Done(Some(Block::CodeBlock {
                     language: None,
                     code: text,
                     fenced: false,
-                })
+                }))
             }
         }
 
-        "hr" => Some(Block::ThematicBreak),
+        "hr" => Step::Done(Some(Block::ThematicBreak)),
 
-        "table" => convert_table(node, options, ctx),
+        "table" => convert_table(node, index, options, ctx),
 
         // Container elements - just process children
         "div" | "section" | "article" | "main" | "aside" | "header" | "footer" | "nav"
         | "figure" | "figcaption" | "address" | "form" | "fieldset" => {
-            let blocks = convert_children(node, options, ctx);
-            // Return as document fragment (will be flattened)
-            if blocks.len() == 1 {
-                Some(blocks.into_iter().next().unwrap())
-            } else if blocks.is_empty() {
-                None
-            } else {
-                Some(Block::Document(blocks))
-            }
+            children(false, Finish::Container)
         }
 
-        // Inline-only elements at block level - convert as inline and wrap in paragraph
-        "a" | "strong" | "b" | "em" | "i" | "code" | "span" | "img" | "br" => {
-            if let Some(inline) = convert_inline_element(node, options, ctx) {
-                Some(Block::Paragraph(vec![inline]))
-            } else {
-                None
+        // Inline-only elements as the root - convert as inline and wrap in
+        // paragraph (see [`Converted::into_block`])
+        "a" | "strong" | "b" | "em" | "i" | "code" | "span" | "img" | "br" | "del" | "s"
+        | "strike" | "mark" | "sup" | "sub" => {
+            match convert_inline_element(node, index, options, ctx) {
+                Step::Done(inline) => {
+                    Step::Done(inline.map(|inline| Block::Paragraph(vec![inline])))
+                }
+                Step::Visit(frame) => Step::Visit(frame),
             }
         }
 
         // Skip these elements
-        "script" | "style" | "noscript" | "template" => None,
+        "script" | "style" | "noscript" | "template" => Step::Done(None),
 
         // Unknown elements - try to get content
-        _ => {
-            let blocks = convert_children(node, options, ctx);
-            if blocks.is_empty() {
-                // Try as inline
-                let inlines = collect_inlines(node, options, ctx);
-                if inlines_are_blank(&inlines) {
-                    None
-                } else {
-                    Some(Block::Paragraph(inlines))
-                }
-            } else if blocks.len() == 1 {
-                Some(blocks.into_iter().next().unwrap())
-            } else {
-                Some(Block::Document(blocks))
-            }
-        }
+        _ => children(false, Finish::Unknown),
     }
 }
 
-/// Collect list items from ul/ol
-fn collect_list_items(node: &Node, options: &Options, ctx: &Context) -> Vec<ListItem> {
-    let mut items = Vec::new();
-
-    for child in node.children() {
-        if child.is_element() && child.tag_name() == "li" {
-            let blocks = convert_children(child, options, ctx);
-            items.push(ListItem::new(if blocks.is_empty() {
-                // Try getting inline content
-                let inlines = collect_inlines(child, options, ctx);
-                vec![Block::Paragraph(inlines)]
-            } else {
-                blocks
-            }));
-        }
+/// A paragraph of the inlines, unless they are blank
+fn paragraph(inlines: Vec<Inline>) -> Option<Block> {
+    if inlines_are_blank(&inlines) {
+        None
+    } else {
+        Some(Block::Paragraph(inlines))
     }
+}
 
-    items
+/// Convert the li children of the ul/ol at `index` to a list
+fn list_frame<'c>(
+    node: &'c Node,
+    index: usize,
+    ordered: bool,
+    start: u32,
+    ctx: &'c Context<'c>,
+) -> Frame<'c> {
+    let list = List {
+        lis: ctx
+            .element_children(node, index)
+            .filter(|(_, child)| child.tag_name() == "li")
+            .collect(),
+        next: 0,
+        ordered,
+        start,
+        items: Vec::new(),
+    };
+    Frame::new(ctx, &[node.tag_name().as_str()], Work::List(list))
 }
 
-/// Convert a table element
-fn convert_table(node: &Node, options: &Options, ctx: &Context) -> Option<Block> {
-    let mut headers: Vec<Vec<Inline>> = Vec::new();
-    let mut rows: Vec<Vec<Vec<Inline>>> = Vec::new();
+fn list_item<'c>(node: &'c Node, index: usize, ctx: &'c Context<'c>) -> Frame<'c> {
+    if let Some(frame) = apply_rule(node, index, "li", ctx, Target::ListItem) {
+        return frame;
+    }
 
-    // Find thead and tbody
-    for child in node.children() {
-        if !child.is_element() {
-            continue;
-        }
+    let checked = leading_checkbox(node);
+    visit_children(ctx, "li", node, index, false, Finish::ListItem(checked))
+}
 
-        match child.tag_name().as_str() {
-            "thead" => {
-                // Get header row
-                for tr in child.element_children() {
-                    if tr.tag_name() == "tr" {
-                        for th in tr.element_children() {
-                            if th.tag_name() == "th" || th.tag_name() == "td" {
-                                headers.push(collect_inlines(th, options, ctx));
-                            }
-                        }
-                        break; // Only first row as headers
-                    }
-                }
-            }
-            "tbody" => {
-                for tr in child.element_children() {
-                    if tr.tag_name() == "tr" {
-                        let mut row = Vec::new();
-                        for td in tr.element_children() {
-                            if td.tag_name() == "td" || td.tag_name() == "th" {
-                                row.push(collect_inlines(td, options, ctx));
-                            }
-                        }
-                        if !row.is_empty() {
-                            rows.push(row);
-                        }
-                    }
-                }
-            }
-            "tr" => {
-                // Direct tr children (no thead/tbody)
-                let mut row = Vec::new();
-                let mut is_header = false;
-
-                for cell in child.element_children() {
-                    let tag = cell.tag_name();
-                    if tag == "th" {
-                        is_header = true;
-                        row.push(collect_inlines(cell, options, ctx));
-                    } else if tag == "td" {
-                        row.push(collect_inlines(cell, options, ctx));
-                    }
-                }
+/// Checked state of a checkbox input leading a list item
+///
+/// The input may be wrapped in a leading `<p>` or `<label>`, as produced
+/// for loose task lists and by many Markdown renderers.
+fn leading_checkbox(mut node: &Node) -> Option<bool> {
+    loop {
+        let first = node.children().find(|child| match child.node_type {
+            NodeType::Text => !child.node_value.as_deref().unwrap_or("").trim().is_empty(),
+            NodeType::Element => true,
+            _ => false,
+        })?;
+        if !first.is_element() {
+            return None;
+        }
 
-                if !row.is_empty() {
-                    if is_header && headers.is_empty() {
-                        headers = row;
-                    } else {
-                        rows.push(row);
-                    }
-                }
+        match first.tag_name().as_str() {
+            "input" => {
+                return first
+                    .attr("type")
+                    .is_some_and(|t| t.eq_ignore_ascii_case("checkbox"))
+                    .then(|| first.has_attr("checked"))
             }
-            _ => {}
+            "p" | "label" => node = first,
+            _ => return None,
         }
     }
+}
 
-    if headers.is_empty() && rows.is_empty() {
-        return None;
+/// Convert the table element at `index`
+fn convert_table<'c>(
+    node: &'c Node,
+    index: usize,
+    options: &'c Options,
+    ctx: &'c Context<'c>,
+) -> Step<'c, Option<Block>> {
+    let as_list = is_complex_table(node, index, options, ctx);
+    if as_list && options.table_fallback == TableFallback::Html {
+        return Step::Done(Some(Block::HtmlBlock(node.outer_html())));
     }
+    let rows = table_rows(ctx, node, index);
+    Step::Visit(table_frame(node, index, as_list, rows, ctx))
+}
+
+/// Convert the rows and caption of the table at `index`
+fn table_frame<'c>(
+    node: &'c Node,
+    index: usize,
+    as_list: bool,
+    rows: Vec<(usize, &'c Node, Option<String>, usize)>,
+    ctx: &'c Context<'c>,
+) -> Frame<'c> {
+    let table = Table {
+        as_list,
+        rows,
+        next: 0,
+        caption: table_caption(ctx, node, index),
+        converted_caption: None,
+        summary: table_summary(node),
+        pipe_rows: Vec::new(),
+        list_rows: Vec::new(),
+    };
+    Frame::new(ctx, &["table"], Work::Table(table))
+}
 
-    // If no headers, use first row as headers
-    if headers.is_empty() && !rows.is_empty() {
-        headers = rows.remove(0);
+/// Assemble a pipe table from its rows in source order, the index of its
+/// header row and its caption
+fn pipe_table(
+    rows: Vec<TableRow>,
+    header: usize,
+    caption: Option<Vec<Inline>>,
+    options: &Options,
+) -> Option<Block> {
+    if rows.is_empty() {
+        return caption.map(Block::Paragraph);
     }
 
-    Some(Block::Table { headers, rows })
+    let duplicate = options.table_spans == TableSpans::Duplicate;
+    let mut grid = expand_table_spans(rows, duplicate);
+    let header = grid.remove(header);
+    grid.insert(0, header);
+
+    let mut headers = Vec::new();
+    let mut body = Vec::new();
+    let mut cell_alignments = Vec::new();
+    for (i, row) in grid.into_iter().enumerate() {
+        let (cells, alignments): (Vec<_>, Vec<_>) = row.into_iter().unzip();
+        cell_alignments.push(alignments);
+        if i == 0 {
+            headers = cells;
+        } else {
+            body.push(cells);
+        }
+    }
+
+    Some(Block::Table {
+        headers,
+        rows: body,
+        alignments: Alignment::columns(&cell_alignments),
+        caption,
+    })
 }
 
-/// Collect inline content from a node
-fn collect_inlines(node: &Node, options: &Options, ctx: &Context) -> Vec<Inline> {
-    let mut inlines = Vec::new();
+/// The first `caption` of the table at `index`, with its index
+fn table_caption<'n>(ctx: &'n Context, table: &'n Node, index: usize) -> Option<(usize, &'n Node)> {
+    ctx.element_children(table, index)
+        .find(|(_, child)| child.tag_name() == "caption")
+}
 
-    for child in node.children() {
-        match child.node_type {
-            NodeType::Text => {
-                let text = child.node_value.as_deref().unwrap_or("");
-                if ctx.in_pre {
-                    inlines.push(Inline::Text(text.to_string()));
-                } else {
-                    let collapsed = collapse_whitespace(text);
-                    if !collapsed.is_empty() {
-                        inlines.push(Inline::Text(escape_markdown(&collapsed)));
-                    }
-                }
+/// Caption text from the obsolete `summary` attribute of a table, with its
+/// whitespace collapsed
+fn table_summary(table: &Node) -> Option<Vec<Inline>> {
+    let summary = table.attr("summary")?.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!summary.is_empty()).then(|| vec![Inline::Text(summary)])
+}
+
+/// Convert the cells of the table row at `index`, laid out as a list or for
+/// a pipe table
+fn row_frame<'c>(tr: &'c Node, index: usize, as_list: bool, ctx: &'c Context<'c>) -> Frame<'c> {
+    let row = Row {
+        as_list,
+        cells: row_cells(ctx, tr, index).collect(),
+        next: 0,
+        pipe_cells: Vec::new(),
+        list_cells: Vec::new(),
+    };
+    let scope: &[&str] = if as_list { &["tr"] } else { &[] };
+    Frame::new(ctx, scope, Work::Row(row))
+}
+
+/// Rows of the table at `index`, from its sections and direct `tr` children,
+/// with their indexes, the sections holding them and the indexes of their
+/// row groups (a section, or a run of direct `tr` children)
+fn table_rows<'n>(
+    ctx: &'n Context,
+    table: &'n Node,
+    index: usize,
+) -> Vec<(usize, &'n Node, Option<String>, usize)> {
+    let mut rows = Vec::new();
+    let mut group = 0;
+    let mut in_direct_rows = false;
+    for (index, child) in ctx.element_children(table, index) {
+        let name = child.tag_name();
+        match name.as_str() {
+            "thead" | "tbody" | "tfoot" => {
+                group += 1;
+                in_direct_rows = false;
+                rows.extend(
+                    ctx.element_children(child, index)
+                        .filter(|(_, tr)| tr.tag_name() == "tr")
+                        .map(|(index, tr)| (index, tr, Some(name.clone()), group)),
+                );
             }
-            NodeType::Element => {
-                if let Some(inline) = convert_inline_element(child, options, ctx) {
-                    inlines.push(inline);
+            "tr" => {
+                if !in_direct_rows {
+                    group += 1;
+                    in_direct_rows = true;
                 }
+                rows.push((index, child, None, group));
             }
             _ => {}
         }
     }
+    rows
+}
 
-    inlines
+/// The th/td cells of the table row at `index`
+fn row_cells<'n>(
+    ctx: &'n Context,
+    tr: &'n Node,
+    index: usize,
+) -> impl Iterator<Item = (usize, &'n Node)> {
+    ctx.element_children(tr, index)
+        .filter(|(_, cell)| matches!(cell.tag_name().as_str(), "th" | "td"))
 }
 
-/// Convert an inline element to an Inline node
-fn convert_inline_element(node: &Node, options: &Options, ctx: &Context) -> Option<Inline> {
-    let tag = node.tag_name();
+/// Whether a table cannot be a pipe table: a cell holds block content beyond
+/// a single paragraph, or spans several rows or columns while span expansion
+/// is off
+fn is_complex_table(table: &Node, index: usize, options: &Options, ctx: &Context) -> bool {
+    let check_spans = options.table_spans == TableSpans::Fallback;
+    let mut cells = table_rows(ctx, table, index)
+        .into_iter()
+        .flat_map(|(index, tr, _, _)| row_cells(ctx, tr, index));
+    cells.any(|(_, cell)| {
+        let spans = cell_span(cell, "colspan") > 1 || cell_span(cell, "rowspan") != 1;
+        (check_spans && spans) || has_block_content(cell)
+    })
+}
 
-    match tag.as_str() {
-        "strong" | "b" => {
-            let inner = collect_inlines(node, options, ctx);
-            if inlines_are_blank(&inner) {
-                None
-            } else {
-                Some(Inline::Strong(inner))
+/// Value of a `colspan` or `rowspan` attribute, 1 when missing or invalid
+fn cell_span(cell: &Node, name: &str) -> usize {
+    cell.attr(name)
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// Whether a cell holds block elements other than one paragraph or div
+fn has_block_content(cell: &Node) -> bool {
+    let mut paragraphs = 0;
+    let mut stack: Vec<&Node> = cell.element_children().collect();
+    while let Some(node) = stack.pop() {
+        let tag = node.tag_name();
+        if tag == "p" || tag == "div" {
+            paragraphs += 1;
+            if paragraphs > 1 {
+                return true;
             }
+        } else if BLOCK_ELEMENTS.contains(&tag.as_str()) {
+            return true;
         }
+        stack.extend(node.element_children());
+    }
+    false
+}
 
-        "em" | "i" => {
-            let inner = collect_inlines(node, options, ctx);
-            if inlines_are_blank(&inner) {
-                None
+/// Alignment of a th/td cell from its `align` and `style` attributes
+fn cell_alignment(cell: &Node) -> Alignment {
+    Alignment::from_html(cell.attr("align"), cell.attr("style"))
+}
+
+/// Convert a child node in inline context
+fn convert_inline_node<'c>(
+    child: &'c Node,
+    index: usize,
+    options: &'c Options,
+    ctx: &'c Context<'c>,
+) -> Step<'c, Option<Inline>> {
+    match child.node_type {
+        NodeType::Text => {
+            let text = ctx.text(child, index);
+            Step::Done(if ctx.in_pre {
+                Some(Inline::Text(text.to_string()))
+            } else if !text.is_empty() {
+                Some(Inline::Text(escape_markdown(text)))
             } else {
-                Some(Inline::Emphasis(inner))
-            }
+                None
+            })
         }
+        NodeType::Element => convert_inline_element(child, index, options, ctx),
+        _ => Step::Done(None),
+    }
+}
+
+/// Convert the inline element at `index` to an Inline node
+fn convert_inline_element<'c>(
+    node: &'c Node,
+    index: usize,
+    options: &'c Options,
+    ctx: &'c Context<'c>,
+) -> Step<'c, Option<Inline>> {
+    let tag = node.tag_name();
+
+    if let Some(frame) = apply_rule(node, index, &tag, ctx, Target::Inline) {
+        return Step::Visit(frame);
+    }
+
+    match filter_action(node, &tag, options, ctx) {
+        Some(FilterAction::Keep) => return Step::Done(Some(Inline::HtmlInline(node.outer_html()))),
+        Some(FilterAction::Remove) => return Step::Done(None),
+        None => {}
+    }
+
+    let children = |finish| Step::Visit(visit_children(ctx, &tag, node, index, true, finish));
+
+    match tag.as_str() {
+        "strong" | "b" => children(Finish::Strong),
+
+        "em" | "i" => children(Finish::Emphasis),
 
         "code" => {
-            let text = node.text_content();
-            if text.is_empty() {
+            let text = ctx.text_content(node, index);
+            Step::Done(if text.is_empty() {
                 None
             } else {
                 Some(Inline::Code(text))
-            }
+            })
         }
 
         "a" => {
             let href = node.attr("href").unwrap_or("");
             let title = node.attr("title").map(|s| s.to_string());
-            let content = collect_inlines(node, options, ctx);
-
-            if href.is_empty() && title.is_none() {
-                // No link target, just return content
-                if content.len() == 1 {
-                    return Some(content.into_iter().next().unwrap());
-                }
-                return None;
-            }
-
-            Some(Inline::Link {
-                content,
-                url: href.to_string(),
-                title,
-            })
+            children(Finish::Link { href, title })
         }
 
         "img" => {
             let src = node.attr("src").unwrap_or("");
             if src.is_empty() {
-                return None;
+                return Step::Done(None);
             }
 
             let alt = node.attr("alt").unwrap_or("").to_string();
             let title = node.attr("title").map(|s| s.to_string());
 
-            Some(Inline::Image {
+            Step::Done(Some(Inline::Image {
                 alt,
                 url: src.to_string(),
                 title,
-            })
+            }))
         }
 
-        "br" => Some(Inline::LineBreak),
+        "br" => Step::Done(Some(Inline::LineBreak)),
 
-        "span" | "small" | "mark" | "abbr" | "cite" | "q" | "sub" | "sup" | "time" => {
-            // Pass-through inline containers
-            let inner = collect_inlines(node, options, ctx);
-            if inner.len() == 1 {
-                Some(inner.into_iter().next().unwrap())
-            } else if inner.is_empty() {
-                None
-            } else {
-                // Flatten multiple inlines
-                Some(Inline::Text(
-                    inner
-                        .iter()
-                        .map(|i| inline_to_text(i))
-                        .collect::<Vec<_>>()
-                        .join(""),
-                ))
-            }
-        }
+        "del" | "s" | "strike" | "mark" | "sup" | "sub" => children(Finish::Markup(tag.clone())),
+
+        // Pass-through inline containers
+        "span" | "small" | "abbr" | "cite" | "q" | "time" => children(Finish::Flatten),
 
         // Nested block elements inside inline context - extract text
         "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-            let text = node.text_content();
-            if text.trim().is_empty() {
+            let text = ctx.text_content(node, index);
+            Step::Done(if text.trim().is_empty() {
                 None
             } else {
-                Some(Inline::Text(escape_markdown(&collapse_whitespace(&text))))
-            }
+                Some(Inline::Text(escape_markdown(&text)))
+            })
         }
 
+        // Unknown inline - try to get content
+        _ => children(Finish::Flatten),
+    }
+}
+
+/// The single inline of a pass-through container, or its inlines flattened
+/// to text
+fn flatten_inlines(mut inner: Vec<Inline>) -> Option<Inline> {
+    if inner.len() == 1 {
+        inner.pop()
+    } else if inner.is_empty() {
+        None
+    } else {
+        // Flatten multiple inlines
+        Some(Inline::Text(inner.iter().map(inline_to_text).collect()))
+    }
+}
+
+/// Convert an element with the first custom rule matching it, if any
+///
+/// The rule receives the element's children as blocks when they contain
+/// block-level elements (and we are not in inline context), else as inlines.
+fn apply_rule<'c>(
+    node: &'c Node,
+    index: usize,
+    tag: &str,
+    ctx: &'c Context<'c>,
+    target: Target,
+) -> Option<Frame<'c>> {
+    if ctx.rules.is_empty() {
+        return None;
+    }
+
+    let (rule, element) = ctx.rules.find(tag, || rule_element(node, tag, ctx))?;
+
+    let inline = matches!(target, Target::Inline) || !has_block_children(node);
+    let finish = Finish::Rule {
+        rule,
+        element: Box::new(element),
+        target,
+    };
+    Some(visit_children(ctx, tag, node, index, inline, finish))
+}
+
+/// The inline replacing an element in inline context
+fn replacement_inline(replacement: Replacement, options: &Options) -> Option<Inline> {
+    let mut inlines = replacement.into_inlines(options);
+    match inlines.len() {
+        0 => None,
+        1 => inlines.pop(),
+        // A single Inline is expected here, so keep formatting by
+        // embedding the serialized Markdown
         _ => {
-            // Unknown inline - try to get content
-            let inner = collect_inlines(node, options, ctx);
-            if inner.len() == 1 {
-                Some(inner.into_iter().next().unwrap())
-            } else if inner.is_empty() {
-                None
-            } else {
-                Some(Inline::Text(
-                    inner
-                        .iter()
-                        .map(|i| inline_to_text(i))
-                        .collect::<Vec<_>>()
-                        .join(""),
-                ))
+            let paragraph = Block::Paragraph(inlines);
+            let markdown = turndown_core::serialize(&paragraph, options);
+            paragraph.drop_iteratively();
+            Some(Inline::HtmlInline(markdown))
+        }
+    }
+}
+
+/// Build the element view handed to rule filters and replacements
+fn rule_element(node: &Node, tag: &str, ctx: &Context) -> Element {
+    Element {
+        tag: tag.to_string(),
+        attributes: node
+            .attributes
+            .iter()
+            .flat_map(|attrs| attrs.chunks_exact(2))
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect(),
+        ancestors: ctx.ancestors.borrow().clone(),
+    }
+}
+
+/// Check if an element has any block-level child elements
+fn has_block_children(node: &Node) -> bool {
+    node.element_children().any(|child| {
+        matches!(
+            child.tag_name().as_str(),
+            "p" | "div"
+                | "ul"
+                | "ol"
+                | "blockquote"
+                | "pre"
+                | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "table"
+                | "hr"
+                | "section"
+                | "article"
+                | "header"
+                | "footer"
+                | "nav"
+                | "main"
+                | "aside"
+                | "figure"
+                | "figcaption"
+                | "details"
+                | "summary"
+                | "dl"
+                | "dt"
+                | "dd"
+        )
+    })
+}
+
+/// What the keep/remove filters decided for an element
+enum FilterAction {
+    Keep,
+    Remove,
+}
+
+/// Match an element against the keep/remove filters (turndown.js precedence:
+/// blank elements first, then built-in rules, then keep, then remove)
+fn filter_action(node: &Node, tag: &str, options: &Options, ctx: &Context) -> Option<FilterAction> {
+    if ctx.keep_filters.is_empty() && ctx.remove_filters.is_empty() {
+        return None;
+    }
+    if has_builtin_rule(node, tag, options) {
+        return None;
+    }
+
+    // Blank elements never reach predicates in turndown.js
+    let mut element = None;
+    let mut matches = |filters: &[Filter]| {
+        filters.iter().any(|filter| match filter {
+            Filter::Tags(tags) => tags.iter().any(|t| t == tag),
+            Filter::Predicate(predicate) => {
+                !is_blank(node)
+                    && predicate(element.get_or_insert_with(|| rule_element(node, tag, ctx)))
+            }
+        })
+    };
+
+    if matches(ctx.keep_filters) {
+        // Blank elements are dropped before keep is consulted
+        if is_blank(node) {
+            Some(FilterAction::Remove)
+        } else {
+            Some(FilterAction::Keep)
+        }
+    } else if matches(ctx.remove_filters) {
+        Some(FilterAction::Remove)
+    } else {
+        None
+    }
+}
+
+/// Whether one of turndown's CommonMark rules (or an enabled markup
+/// extension) converts this element
+fn has_builtin_rule(node: &Node, tag: &str, options: &Options) -> bool {
+    match tag {
+        "p" | "br" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "ul" | "ol"
+        | "li" | "hr" | "em" | "i" | "strong" | "b" | "code" | "img" => true,
+        "del" | "s" | "strike" => options.strikethrough_style != MarkupStyle::Plain,
+        "mark" => options.highlight_style != MarkupStyle::Plain,
+        "sup" | "sub" => options.script_style != MarkupStyle::Plain,
+        "a" => node.attr("href").is_some_and(|href| !href.is_empty()),
+        "pre" => node
+            .children()
+            .next()
+            .is_some_and(|first| first.is_element() && first.tag_name() == "code"),
+        _ => false,
+    }
+}
+
+/// Check if an element is blank in the turndown.js sense: no text and no
+/// void or meaningful-when-blank descendants
+fn is_blank(node: &Node) -> bool {
+    const MEANINGFUL_WHEN_BLANK: &[&str] = &[
+        "a", "table", "thead", "tbody", "tfoot", "th", "td", "iframe", "script", "audio",
+        "video",
+    ];
+
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        match node.node_type {
+            NodeType::Text if !node.node_value.as_deref().unwrap_or("").trim().is_empty() => {
+                return false;
+            }
+            NodeType::Element => {
+                let tag = node.tag_name();
+                if VOID_ELEMENTS.contains(&tag.as_str())
+                    || MEANINGFUL_WHEN_BLANK.contains(&tag.as_str())
+                {
+                    return false;
+                }
+                stack.extend(node.children());
             }
+            _ => {}
         }
     }
+    true
+}
+
+/// Wrap the content of a del/s/strike/mark/sup/sub element
+fn markup_inline(tag: &str, inner: Vec<Inline>) -> Inline {
+    match tag {
+        "mark" => Inline::Highlight(inner),
+        "sup" => Inline::Superscript(inner),
+        "sub" => Inline::Subscript(inner),
+        _ => Inline::Strikethrough(inner),
+    }
 }
 
 /// Get plain text from an inline (for flattening)
 fn inline_to_text(inline: &Inline) -> String {
-    match inline {
-        Inline::Text(t) => t.clone(),
-        Inline::Strong(inner) | Inline::Emphasis(inner) => {
-            inner.iter().map(|i| inline_to_text(i)).collect()
+    let mut text = String::new();
+    let mut stack = vec![inline];
+    while let Some(inline) = stack.pop() {
+        match inline {
+            Inline::Text(t) => text.push_str(t),
+            Inline::Strong(inner)
+            | Inline::Emphasis(inner)
+            | Inline::Strikethrough(inner)
+            | Inline::Highlight(inner)
+            | Inline::Superscript(inner)
+            | Inline::Subscript(inner) => stack.extend(inner.iter().rev()),
+            Inline::Code(c) => text.push_str(c),
+            Inline::Link { content, .. } => stack.extend(content.iter().rev()),
+            Inline::Image { alt, .. } => text.push_str(alt),
+            Inline::LineBreak => text.push('\n'),
+            Inline::HtmlInline(h) => text.push_str(h),
         }
-        Inline::Code(c) => c.clone(),
-        Inline::Link { content, .. } => content.iter().map(|i| inline_to_text(i)).collect(),
-        Inline::Image { alt, .. } => alt.clone(),
-        Inline::LineBreak => "\n".to_string(),
-        Inline::HtmlInline(h) => h.clone(),
     }
+    text
 }
 
 /// Check if inlines are all blank
@@ -483,24 +1552,16 @@ fn inlines_are_blank(inlines: &[Inline]) -> bool {
     inlines.iter().all(|i| i.is_blank())
 }
 
-/// Collapse whitespace in text
-fn collapse_whitespace(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    let mut prev_was_whitespace = false;
-
-    for c in s.chars() {
-        if c.is_whitespace() {
-            if !prev_was_whitespace {
-                result.push(' ');
-                prev_was_whitespace = true;
-            }
-        } else {
-            result.push(c);
-            prev_was_whitespace = false;
-        }
-    }
-
-    result
+/// Check if the content of a strong or emphasis element is blank
+///
+/// Strong and emphasis are only converted when not blank, so nested ones are
+/// not walked again: checking the whole content at every level of nested
+/// markup takes quadratic time.
+fn markup_is_blank(inlines: &[Inline]) -> bool {
+    inlines.iter().all(|inline| match inline {
+        Inline::Strong(_) | Inline::Emphasis(_) => false,
+        inline => inline.is_blank(),
+    })
 }
 
 /// Escape markdown special characters in text
@@ -523,12 +1584,14 @@ fn escape_markdown(text: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use turndown_core::serialize;
+    use turndown_core::{serialize, serialize_limited, CaptionPosition, CaptionStyle, LimitMode};
 
     fn convert_and_serialize(node: &Node) -> String {
         let options = Options::default();
-        let ast = convert(node, &options);
-        serialize(&ast, &options)
+        let ast = convert(node, &options, &Rules::new(), &[], &[]).unwrap();
+        let result = serialize(&ast, &options);
+        ast.drop_iteratively();
+        result
     }
 
     #[test]
@@ -566,6 +1629,40 @@ mod tests {
         assert_eq!(result, "[Link](https://example.com)");
     }
 
+    #[test]
+    fn test_loose_inline_content() {
+        let mut b = Node::element("b");
+        b.add_child(Node::text("a"));
+        let mut div = Node::element("div");
+        div.add_child(Node::text("text "));
+        div.add_child(b);
+        div.add_child(Node::text(" more"));
+        assert_eq!(convert_and_serialize(&div), "text **a** more");
+
+        let mut b = Node::element("b");
+        b.add_child(Node::text("a"));
+        let mut i = Node::element("i");
+        i.add_child(Node::text("b"));
+        let mut body = Node::element("body");
+        body.add_child(b);
+        body.add_child(Node::text(" "));
+        body.add_child(i);
+        assert_eq!(convert_and_serialize(&body), "**a** _b_");
+
+        let mut a = Node::element_with_attrs("a", vec![("href", "/x")]);
+        a.add_child(Node::text("x"));
+        let mut p = Node::element("p");
+        p.add_child(Node::text("two"));
+        let mut div = Node::element("div");
+        div.add_child(Node::text("one "));
+        div.add_child(a);
+        div.add_child(p);
+        div.add_child(Node::text("three "));
+        div.add_child(Node::element("br"));
+        div.add_child(Node::text(" four"));
+        assert_eq!(convert_and_serialize(&div), "one [x](/x)\n\ntwo\n\nthree  \nfour");
+    }
+
     #[test]
     fn test_image() {
         let img = Node::element_with_attrs("img", vec![("src", "test.png"), ("alt", "Alt")]);
@@ -596,4 +1693,296 @@ mod tests {
         assert!(result.contains("*   One"));
         assert!(result.contains("*   Two"));
     }
+
+    #[test]
+    fn test_table_alignment() {
+        let cell = |tag: &str, attrs: Vec<(&str, &str)>, text: &str| {
+            let mut cell = Node::element_with_attrs(tag, attrs);
+            cell.add_child(Node::text(text));
+            cell
+        };
+        let mut header = Node::element("tr");
+        header.add_child(cell("th", vec![("align", "right")], "A"));
+        header.add_child(cell("th", vec![], "B"));
+        let mut row = Node::element("tr");
+        row.add_child(cell("td", vec![], "1"));
+        row.add_child(cell("td", vec![("style", "text-align: center")], "2"));
+        let mut table = Node::element("table");
+        table.add_child(header);
+        table.add_child(row);
+
+        let result = convert_and_serialize(&table);
+        assert_eq!(result, "| A   | B   |\n| --: | :-: |\n| 1   | 2   |");
+    }
+
+    #[test]
+    fn test_table_caption() {
+        let mut caption = Node::element("caption");
+        caption.add_child(Node::text("Totals"));
+        let mut tr = Node::element("tr");
+        let mut th = Node::element("th");
+        th.add_child(Node::text("A"));
+        tr.add_child(th);
+        let mut table = Node::element("table");
+        table.add_child(caption);
+        table.add_child(tr);
+
+        assert_eq!(convert_and_serialize(&table), "Totals\n\n| A   |\n| --- |");
+
+        let options = Options {
+            caption_position: CaptionPosition::Below,
+            caption_style: CaptionStyle::Bold,
+            ..Options::default()
+        };
+        let ast = convert(&table, &options, &Rules::new(), &[], &[]).unwrap();
+        assert_eq!(serialize(&ast, &options), "| A   |\n| --- |\n\n**Totals**");
+
+        // The summary attribute stands in for a missing caption
+        let mut tr = Node::element("tr");
+        let mut th = Node::element("th");
+        th.add_child(Node::text("A"));
+        tr.add_child(th);
+        let mut table = Node::element_with_attrs("table", vec![("summary", "Unit\n prices")]);
+        table.add_child(tr);
+        assert_eq!(convert_and_serialize(&table), "Unit prices\n\n| A   |\n| --- |");
+    }
+
+    #[test]
+    fn test_table_spans() {
+        let cell = |tag: &str, attrs: Vec<(&str, &str)>, text: &str| {
+            let mut cell = Node::element_with_attrs(tag, attrs);
+            cell.add_child(Node::text(text));
+            cell
+        };
+        let mut header = Node::element("tr");
+        header.add_child(cell("th", vec![("colspan", "2")], "AB"));
+        let mut row = Node::element("tr");
+        row.add_child(cell("td", vec![], "1"));
+        row.add_child(cell("td", vec![], "2"));
+        let mut table = Node::element("table");
+        table.add_child(header);
+        table.add_child(row);
+
+        let options = Options {
+            table_spans: TableSpans::Duplicate,
+            ..Options::default()
+        };
+        let ast = convert(&table, &options, &Rules::new(), &[], &[]).unwrap();
+        let result = serialize(&ast, &options);
+        assert_eq!(result, "| AB  | AB  |\n| --- | --- |\n| 1   | 2   |");
+    }
+
+    #[test]
+    fn test_table_sections() {
+        let row = |cells: &[(&str, &str)]| {
+            let mut tr = Node::element("tr");
+            for &(tag, text) in cells {
+                let attrs = if text == "a" { vec![("rowspan", "0")] } else { vec![] };
+                let mut cell = Node::element_with_attrs(tag, attrs);
+                cell.add_child(Node::text(text));
+                tr.add_child(cell);
+            }
+            tr
+        };
+        let section = |tag: &str, rows: Vec<Node>| {
+            let mut section = Node::element(tag);
+            rows.into_iter().for_each(|tr| section.add_child(tr));
+            section
+        };
+        let mut table = Node::element("table");
+        table.add_child(section(
+            "thead",
+            vec![row(&[("th", "H1"), ("th", "H2")]), row(&[("th", "h1"), ("th", "h2")])],
+        ));
+        table.add_child(section(
+            "tbody",
+            vec![row(&[("td", "a"), ("td", "b")]), row(&[("td", "c")])],
+        ));
+        table.add_child(section("tfoot", vec![row(&[("td", "f1"), ("td", "f2")])]));
+
+        // Every thead row and the tfoot are kept; the rowspan ends with tbody
+        let options = Options {
+            table_spans: TableSpans::Duplicate,
+            ..Options::default()
+        };
+        let ast = convert(&table, &options, &Rules::new(), &[], &[]).unwrap();
+        assert_eq!(
+            serialize(&ast, &options),
+            "| H1  | H2  |\n| --- | --- |\n| h1  | h2  |\n| a   | b   |\n| a   | c   |\n\
+             | f1  | f2  |"
+        );
+    }
+
+    #[test]
+    fn test_complex_table_fallback() {
+        let mut header = Node::element("tr");
+        let mut th = Node::element_with_attrs("th", vec![("colspan", "2")]);
+        th.add_child(Node::text("Wide"));
+        header.add_child(th);
+        let mut row = Node::element("tr");
+        let mut td = Node::element("td");
+        td.add_child(Node::text("a"));
+        row.add_child(td);
+        let mut td = Node::element("td");
+        let mut ul = Node::element("ul");
+        let mut li = Node::element("li");
+        li.add_child(Node::text("item"));
+        ul.add_child(li);
+        td.add_child(ul);
+        row.add_child(td);
+        let mut table = Node::element("table");
+        table.add_child(header);
+        table.add_child(row);
+
+        let result = convert_and_serialize(&table);
+        assert_eq!(result, table.outer_html());
+
+        let options = Options {
+            table_fallback: TableFallback::List,
+            ..Options::default()
+        };
+        let ast = convert(&table, &options, &Rules::new(), &[], &[]).unwrap();
+        let result = serialize(&ast, &options);
+        assert_eq!(
+            result,
+            "*   \n    *   Wide\n*   \n    *   a\n    *   \n        *   item"
+        );
+    }
+
+    #[test]
+    fn test_markup_elements() {
+        let mut p = Node::element("p");
+        let mut del = Node::element("del");
+        del.add_child(Node::text("old"));
+        p.add_child(del);
+        p.add_child(Node::text(" E=mc"));
+        let mut sup = Node::element("sup");
+        sup.add_child(Node::text("2"));
+        p.add_child(sup);
+
+        assert_eq!(convert_and_serialize(&p), "old E=mc2");
+
+        let options = Options {
+            strikethrough_style: MarkupStyle::Markdown,
+            script_style: MarkupStyle::Html,
+            ..Options::default()
+        };
+        let ast = convert(&p, &options, &Rules::new(), &[], &[]).unwrap();
+        assert_eq!(serialize(&ast, &options), "~~old~~ E=mc<sup>2</sup>");
+    }
+
+    #[test]
+    fn test_task_list_items() {
+        let mut ul = Node::element("ul");
+        for (checked, text) in [(true, "Done"), (false, "Todo")] {
+            let mut li = Node::element("li");
+            let mut input = Node::element_with_attrs("input", vec![("type", "checkbox")]);
+            if checked {
+                input.set_attr("checked", "");
+            }
+            li.add_child(input);
+            li.add_child(Node::text(text));
+            ul.add_child(li);
+        }
+        let mut li = Node::element("li");
+        li.add_child(Node::text("Plain"));
+        ul.add_child(li);
+
+        let options = Options {
+            task_lists: true,
+            ..Options::default()
+        };
+        let ast = convert(&ul, &options, &Rules::new(), &[], &[]).unwrap();
+        assert_eq!(serialize(&ast, &options), "*   [x] Done\n*   [ ] Todo\n*   Plain");
+    }
+
+    #[test]
+    fn test_whitespace_collapsing() {
+        // <div> <p> <b>a</b> <i>b</i> </p>\n<pre>  x   y</pre> <p> c <code> d  e </code></p></div>
+        let mut b = Node::element("b");
+        b.add_child(Node::text("a"));
+        let mut i = Node::element("i");
+        i.add_child(Node::text("b"));
+        let mut p = Node::element("p");
+        p.add_child(Node::text(" "));
+        p.add_child(b);
+        p.add_child(Node::text(" "));
+        p.add_child(i);
+        p.add_child(Node::text(" "));
+        let mut pre = Node::element("pre");
+        pre.add_child(Node::text("  x   y"));
+        let mut div = Node::element("div");
+        div.add_child(Node::text(" "));
+        div.add_child(p);
+        div.add_child(Node::text("\n"));
+        let mut code = Node::element("code");
+        code.add_child(Node::text(" d  e "));
+        let mut after = Node::element("p");
+        after.add_child(Node::text(" c "));
+        after.add_child(code);
+        div.add_child(pre);
+        div.add_child(Node::text(" "));
+        div.add_child(after);
+
+        assert_eq!(convert_and_serialize(&div), "**a** _b_\n\n      x   y\n\nc `d e`");
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        const DEPTH: usize = 50_000;
+        let nested = |tag: &str, inner: Node| {
+            let mut node = inner;
+            for _ in 0..DEPTH {
+                let mut parent = Node::element(tag);
+                parent.add_child(node);
+                node = parent;
+            }
+            node
+        };
+
+        let divs = nested("div", Node::text("deep"));
+        assert_eq!(convert_and_serialize(&divs), "deep");
+        divs.drop_iteratively();
+
+        let quotes = nested("blockquote", Node::text("deep"));
+        let quoted = format!("{}deep", "> ".repeat(DEPTH));
+        assert_eq!(convert_and_serialize(&quotes), quoted);
+
+        let mut p = Node::element("p");
+        p.add_child(nested("b", Node::text("deep")));
+        let strong = "**".repeat(DEPTH);
+        assert_eq!(convert_and_serialize(&p), format!("{strong}deep{strong}"));
+
+        // Truncating keeps a copy of the deep tree in front of the cut
+        let mut body = Node::element("body");
+        body.add_child(quotes);
+        body.add_child(p);
+        let mut options = Options::default();
+        options.limits.max_nodes = Some(DEPTH + 2);
+        options.limits.mode = LimitMode::Truncate;
+        let ast = convert(&body, &options, &Rules::new(), &[], &[]).unwrap();
+        assert_eq!(serialize(&ast, &options), quoted);
+        ast.drop_iteratively();
+        body.drop_iteratively();
+
+        // Every list level is indented further, so the output is cut by the
+        // output limit
+        let mut list = Node::text("deep");
+        for _ in 0..DEPTH {
+            let mut li = Node::element("li");
+            li.add_child(Node::text("a"));
+            li.add_child(list);
+            list = Node::element("ul");
+            list.add_child(li);
+        }
+        let mut options = Options::default();
+        options.limits.max_output_bytes = Some(1 << 16);
+        options.limits.mode = LimitMode::Truncate;
+        let ast = convert(&list, &options, &Rules::new(), &[], &[]).unwrap();
+        let result = serialize_limited(&ast, &options).unwrap();
+        ast.drop_iteratively();
+        list.drop_iteratively();
+        assert!(result.starts_with("*   a\n    *   a\n        *   a\n"));
+        assert!(result.len() <= 1 << 16 && result.ends_with('a'));
+    }
 }
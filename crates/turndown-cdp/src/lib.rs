@@ -34,16 +34,10 @@ pub mod node;
 mod service;
 
 pub use node::{Node, NodeRef, NodeType};
-pub use service::{CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle, TurndownOptions, TurndownService};
-
-/// Error type for turndown operations
-#[derive(Debug, thiserror::Error)]
-pub enum TurndownError {
-    #[error("Conversion error: {0}")]
-    ConversionError(String),
-
-    #[error("Invalid input: {0}")]
-    InvalidInput(String),
-}
-
-pub type Result<T> = std::result::Result<T, TurndownError>;
+pub use service::{
+    CodeBlockStyle, HeadingStyle, LimitMode, Limits, LinkReferenceStyle, LinkStyle,
+    TurndownOptions, TurndownService,
+};
+pub use turndown_core::{
+    Block, Content, Element, Filter, Inline, Replacement, Result, Rule, TurndownError,
+};
@@ -36,7 +36,7 @@ impl From<u32> for NodeType {
 ///
 /// This structure is designed to be compatible with Chrome DevTools Protocol
 /// and can be used as a common interface for any HTML parser.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Node {
     /// Node type (1 = Element, 3 = Text, etc.)
     pub node_type: NodeType,
@@ -186,41 +186,45 @@ impl Node {
 
     /// Get all text content from this node and descendants
     pub fn text_content(&self) -> String {
-        match self.node_type {
-            NodeType::Text => self.node_value.clone().unwrap_or_default(),
-            _ => self
-                .children()
-                .map(|child| child.text_content())
-                .collect::<Vec<_>>()
-                .join(""),
+        let mut text = String::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            match node.node_type {
+                NodeType::Text => text.push_str(node.node_value.as_deref().unwrap_or_default()),
+                _ => stack.extend(node.child_nodes().iter().rev()),
+            }
         }
+        text
     }
 
     /// Reconstruct outer HTML (for keep rules)
     pub fn outer_html(&self) -> String {
-        match self.node_type {
-            NodeType::Text => self.node_value.clone().unwrap_or_default(),
-            NodeType::Element => {
-                let tag = self.tag_name();
-                let attrs = self.attributes_string();
-
-                if self.is_void_element() {
+        let mut html = String::new();
+        // Elements are visited again to close them
+        let mut stack = vec![(self, false)];
+        while let Some((node, closing)) = stack.pop() {
+            match node.node_type {
+                NodeType::Text => html.push_str(node.node_value.as_deref().unwrap_or_default()),
+                NodeType::Element if closing => {
+                    html.push_str(&format!("</{}>", node.tag_name()));
+                }
+                NodeType::Element => {
+                    let attrs = node.attributes_string();
                     if attrs.is_empty() {
-                        format!("<{}>", tag)
+                        html.push_str(&format!("<{}>", node.tag_name()));
                     } else {
-                        format!("<{} {}>", tag, attrs)
+                        html.push_str(&format!("<{} {}>", node.tag_name(), attrs));
                     }
-                } else {
-                    let inner = self.inner_html();
-                    if attrs.is_empty() {
-                        format!("<{}>{}</{}>", tag, inner, tag)
-                    } else {
-                        format!("<{} {}>{}</{}>", tag, attrs, inner, tag)
+
+                    if !node.is_void_element() {
+                        stack.push((node, true));
+                        stack.extend(node.child_nodes().iter().rev().map(|child| (child, false)));
                     }
                 }
+                _ => stack.extend(node.child_nodes().iter().rev().map(|child| (child, false))),
             }
-            _ => self.inner_html(),
         }
+        html
     }
 
     /// Reconstruct inner HTML
@@ -231,6 +235,10 @@ impl Node {
             .join("")
     }
 
+    fn child_nodes(&self) -> &[Node] {
+        self.children.as_deref().unwrap_or_default()
+    }
+
     /// Get attributes as a string for HTML output
     fn attributes_string(&self) -> String {
         let Some(ref attrs) = self.attributes else {
@@ -251,6 +259,17 @@ impl Node {
         result.join(" ")
     }
 
+    /// Drop the tree without recursion
+    ///
+    /// A plain drop recurses once per nesting level, which overflows the
+    /// stack for deeply nested trees.
+    pub fn drop_iteratively(mut self) {
+        let mut stack = self.children.take().unwrap_or_default();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.children.take().into_iter().flatten());
+        }
+    }
+
     /// Check if this is a void element
     fn is_void_element(&self) -> bool {
         const VOID_ELEMENTS: &[&str] = &[
@@ -261,6 +280,36 @@ impl Node {
     }
 }
 
+impl Clone for Node {
+    /// Copies the tree iteratively, so deeply nested trees cannot overflow
+    /// the stack
+    fn clone(&self) -> Self {
+        let shallow = |node: &Node| Node {
+            node_type: node.node_type,
+            node_name: node.node_name.clone(),
+            node_value: node.node_value.clone(),
+            attributes: node.attributes.clone(),
+            children: node.children.as_ref().map(|c| Vec::with_capacity(c.len())),
+        };
+
+        // Ancestors of the node being copied, with their copies and the
+        // next child to copy
+        let mut stack = Vec::new();
+        let (mut node, mut copy, mut next) = (self, shallow(self), 0);
+        loop {
+            if let Some(child) = node.child_nodes().get(next) {
+                stack.push((node, copy, next + 1));
+                (node, copy, next) = (child, shallow(child), 0);
+            } else if let Some((parent, mut parent_copy, parent_next)) = stack.pop() {
+                parent_copy.add_child(copy);
+                (node, copy, next) = (parent, parent_copy, parent_next);
+            } else {
+                return copy;
+            }
+        }
+    }
+}
+
 /// Escape HTML attribute value
 fn escape_html_attr(s: &str) -> String {
     s.replace('&', "&amp;")
@@ -392,6 +441,20 @@ mod tests {
         assert_eq!(div.text_content(), "Hello World");
     }
 
+    #[test]
+    fn test_clone() {
+        let mut div = Node::element_with_attrs("div", vec![("id", "a")]);
+        div.add_child(Node::text("one "));
+        let mut em = Node::element("em");
+        em.add_child(Node::text("two"));
+        div.add_child(em);
+        div.add_child(Node::element("br"));
+
+        let copy = div.clone();
+        assert_eq!(copy.outer_html(), "<div id=\"a\">one <em>two</em><br></div>");
+        assert_eq!(copy.children().count(), 3);
+    }
+
     #[test]
     fn test_outer_html() {
         let mut a = Node::element_with_attrs("a", vec![("href", "https://example.com")]);
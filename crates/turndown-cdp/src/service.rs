@@ -3,17 +3,20 @@
 use crate::convert::convert;
 use crate::node::Node;
 use crate::Result;
+use turndown_core::{Filter, Rule, Rules};
 
 // Re-export options from core
 pub use turndown_core::{
-    CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle, Options as TurndownOptions,
+    CodeBlockStyle, HeadingStyle, LimitMode, Limits, LinkReferenceStyle, LinkStyle,
+    Options as TurndownOptions,
 };
 
 /// The main service for converting DOM nodes to Markdown
 pub struct TurndownService {
     options: TurndownOptions,
-    keep_filters: Vec<String>,
-    remove_filters: Vec<String>,
+    rules: Rules,
+    keep_filters: Vec<Filter>,
+    remove_filters: Vec<Filter>,
 }
 
 impl TurndownService {
@@ -21,6 +24,7 @@ impl TurndownService {
     pub fn new() -> Self {
         Self {
             options: TurndownOptions::default(),
+            rules: Rules::new(),
             keep_filters: Vec::new(),
             remove_filters: Vec::new(),
         }
@@ -30,20 +34,34 @@ impl TurndownService {
     pub fn with_options(options: TurndownOptions) -> Self {
         Self {
             options,
+            rules: Rules::new(),
             keep_filters: Vec::new(),
             remove_filters: Vec::new(),
         }
     }
 
     /// Convert a DOM Node tree to Markdown
+    ///
+    /// Fails with [`TurndownError::InvalidOption`](crate::TurndownError) when
+    /// the options cannot produce valid Markdown, and with
+    /// [`TurndownError::LimitExceeded`](crate::TurndownError) when the tree or
+    /// the output exceeds `options.limits` in [`LimitMode::Error`].
     pub fn turndown(&self, node: &Node) -> Result<String> {
+        self.options.validate()?;
+
         // Convert CDP Node to Markdown AST
-        let ast = convert(node, &self.options);
+        let ast = convert(
+            node,
+            &self.options,
+            &self.rules,
+            &self.keep_filters,
+            &self.remove_filters,
+        )?;
 
         // Serialize AST to string
-        let result = turndown_core::serialize(&ast, &self.options);
-
-        Ok(result)
+        let markdown = turndown_core::serialize_limited(&ast, &self.options);
+        ast.drop_iteratively();
+        markdown
     }
 
     /// Get the current options
@@ -56,15 +74,22 @@ impl TurndownService {
         &mut self.options
     }
 
-    /// Keep elements matching the filter as HTML
-    pub fn keep(&mut self, tag: &str) -> &mut Self {
-        self.keep_filters.push(tag.to_lowercase());
+    /// Add a custom rule, taking precedence over built-in conversion and
+    /// all previously added rules
+    pub fn add_rule(&mut self, key: &str, rule: Rule) -> &mut Self {
+        self.rules.add(key, rule);
+        self
+    }
+
+    /// Keep elements matching the filter (tag names or a predicate) as HTML
+    pub fn keep(&mut self, filter: impl Into<Filter>) -> &mut Self {
+        self.keep_filters.push(filter.into());
         self
     }
 
-    /// Remove elements matching the filter
-    pub fn remove(&mut self, tag: &str) -> &mut Self {
-        self.remove_filters.push(tag.to_lowercase());
+    /// Remove elements matching the filter (tag names or a predicate)
+    pub fn remove(&mut self, filter: impl Into<Filter>) -> &mut Self {
+        self.remove_filters.push(filter.into());
         self
     }
 
@@ -212,6 +237,147 @@ mod tests {
         assert_eq!(result, "    function() {}");
     }
 
+    #[test]
+    fn test_add_rule() {
+        use turndown_core::{Block, Filter, Replacement};
+
+        let mut service = TurndownService::new();
+        service.add_rule(
+            "callout",
+            Rule::new(
+                Filter::predicate(|el| el.has_class("callout") && el.parent() == Some("section")),
+                |_, content| Replacement::Blocks(vec![Block::BlockQuote(content.into_blocks())]),
+            ),
+        );
+
+        let mut section = Node::element("section");
+        let mut callout = Node::element_with_attrs("div", vec![("class", "callout")]);
+        callout.add_child(make_p("Note"));
+        section.add_child(callout);
+        let mut other = Node::element_with_attrs("div", vec![("class", "callout")]);
+        other.add_child(make_p("Plain"));
+
+        assert_eq!(service.turndown(&section).unwrap(), "> Note");
+        assert_eq!(service.turndown(&other).unwrap(), "Plain");
+    }
+
+    #[test]
+    fn test_add_rule_overrides_builtin() {
+        use turndown_core::{Inline, Replacement};
+
+        let mut service = TurndownService::new();
+        service.add_rule(
+            "upper-code",
+            Rule::new("code", |_, content| {
+                let text = content
+                    .into_inlines()
+                    .iter()
+                    .map(|i| match i {
+                        Inline::Text(t) => t.to_uppercase(),
+                        _ => String::new(),
+                    })
+                    .collect();
+                Replacement::Inlines(vec![Inline::Text(text)])
+            }),
+        );
+
+        let mut p = Node::element("p");
+        p.add_child(Node::text("run "));
+        let mut code = Node::element("code");
+        code.add_child(Node::text("ls"));
+        p.add_child(code);
+
+        assert_eq!(service.turndown(&p).unwrap(), "run LS");
+    }
+
+    #[test]
+    fn test_keep() {
+        let mut service = TurndownService::new();
+        service.keep("del").keep("figure");
+
+        let mut doc = Node::document_fragment();
+        let mut p = Node::element("p");
+        p.add_child(Node::text("Hello "));
+        let mut del = Node::element("del");
+        del.add_child(Node::text("World"));
+        p.add_child(del);
+        doc.add_child(p);
+        let mut figure = Node::element("figure");
+        figure.add_child(Node::element_with_attrs("img", vec![("src", "a.png")]));
+        doc.add_child(figure);
+
+        let result = service.turndown(&doc).unwrap();
+        assert_eq!(result, "Hello <del>World</del>\n\n<figure><img src=\"a.png\"></figure>");
+    }
+
+    #[test]
+    fn test_keep_ignores_builtin_and_blank() {
+        let mut service = TurndownService::new();
+        service.keep("em").keep("span");
+
+        let mut p = Node::element("p");
+        let mut em = Node::element("em");
+        em.add_child(Node::text("word"));
+        p.add_child(em);
+        let mut span = Node::element("span");
+        span.add_child(Node::text(" "));
+        p.add_child(span);
+
+        let result = service.turndown(&p).unwrap();
+        assert_eq!(result, "_word_");
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut service = TurndownService::new();
+        service.remove("aside").remove("span");
+
+        let mut doc = Node::document_fragment();
+        let mut p = Node::element("p");
+        p.add_child(Node::text("Hello"));
+        let mut span = Node::element("span");
+        span.add_child(Node::text(" there"));
+        p.add_child(span);
+        doc.add_child(p);
+        let mut aside = Node::element("aside");
+        aside.add_child(make_p("Ad"));
+        doc.add_child(aside);
+        doc.add_child(make_p("World"));
+
+        let result = service.turndown(&doc).unwrap();
+        assert_eq!(result, "Hello\n\nWorld");
+    }
+
+    #[test]
+    fn test_keep_and_remove_predicates() {
+        let mut service = TurndownService::new();
+        service
+            .keep(Filter::predicate(|el| el.attr("data-keep").is_some()))
+            .remove(Filter::predicate(|el| el.has_ancestor("aside")))
+            .keep(["ins", "del"]);
+
+        let mut doc = Node::document_fragment();
+        let mut p = Node::element("p");
+        p.add_child(Node::text("a "));
+        let mut span = Node::element_with_attrs("span", vec![("data-keep", "")]);
+        span.add_child(Node::text("b"));
+        p.add_child(span);
+        let mut ins = Node::element("ins");
+        ins.add_child(Node::text("c"));
+        p.add_child(ins);
+        doc.add_child(p);
+        let mut aside = Node::element("aside");
+        let mut p = make_p("x");
+        let mut span = Node::element("span");
+        span.add_child(Node::text(" y"));
+        p.add_child(span);
+        aside.add_child(p);
+        doc.add_child(aside);
+
+        let result = service.turndown(&doc).unwrap();
+        assert_eq!(result, "a <span data-keep>b</span><ins>c</ins>\n\nx");
+    }
+
     #[test]
     fn test_ordered_list() {
         let service = TurndownService::new();
@@ -241,4 +407,57 @@ mod tests {
         assert!(result.contains("*   One"));
         assert!(result.contains("*   Two"));
     }
+
+    #[test]
+    fn test_invalid_options() {
+        let mut service = TurndownService::new();
+        service.options_mut().fence = "--".to_string();
+        let err = service.turndown(&make_p("text")).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::TurndownError::InvalidOption { name: "fence", .. }
+        ));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut deep = make_p("deep");
+        for _ in 0..100 {
+            let mut div = Node::element("div");
+            div.add_child(deep);
+            deep = div;
+        }
+        let mut body = Node::element("body");
+        body.add_child(make_p("before"));
+        body.add_child(deep);
+        body.add_child(make_p("after"));
+
+        let mut service = TurndownService::new();
+        service.options_mut().limits.max_depth = Some(10);
+        assert_eq!(
+            service.turndown(&body),
+            Err(crate::TurndownError::LimitExceeded {
+                limit: "depth",
+                max: 10
+            })
+        );
+        service.options_mut().limits.mode = LimitMode::Truncate;
+        assert_eq!(service.turndown(&body).unwrap(), "before");
+
+        let mut service = TurndownService::new();
+        let limits = &mut service.options_mut().limits;
+        limits.max_nodes = Some(4);
+        limits.mode = LimitMode::Truncate;
+        assert_eq!(service.turndown(&body).unwrap(), "before");
+
+        let limits = &mut service.options_mut().limits;
+        limits.max_nodes = None;
+        limits.max_input_bytes = Some(8);
+        assert_eq!(service.turndown(&body).unwrap(), "before");
+
+        let limits = &mut service.options_mut().limits;
+        limits.max_input_bytes = None;
+        limits.max_output_bytes = Some(8);
+        assert_eq!(service.turndown(&body).unwrap(), "before");
+    }
 }
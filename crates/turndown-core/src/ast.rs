@@ -3,8 +3,15 @@
 //! This module defines the AST nodes for representing Markdown documents.
 //! The AST is the common intermediate format used by both CDP and streaming converters.
 
+use crate::options::Options;
+use crate::serialize::{display_width, serialize_inlines_standalone};
+
 /// A block-level Markdown node
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Cloning and comparing work without recursion, and [`Block::drop_iteratively`]
+/// tears a tree down without it, so deeply nested documents cannot overflow
+/// the stack.
+#[derive(Debug)]
 pub enum Block {
     /// Root document container
     Document(Vec<Block>),
@@ -38,36 +45,234 @@ pub enum Block {
     /// Thematic break (horizontal rule)
     ThematicBreak,
 
-    /// Table with headers and rows
+    /// Table with headers, rows, per-column alignment and optional caption
+    ///
+    /// `alignments` may be shorter than the column count; missing entries
+    /// mean [`Alignment::None`].
     Table {
         headers: Vec<Vec<Inline>>,
         rows: Vec<Vec<Vec<Inline>>>,
+        alignments: Vec<Alignment>,
+        caption: Option<Vec<Inline>>,
     },
 
     /// Raw HTML block (for `keep` elements)
     HtmlBlock(String),
 }
 
+/// Column alignment of a table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// No explicit alignment (`---`)
+    #[default]
+    None,
+    /// Left aligned (`:--`)
+    Left,
+    /// Centered (`:-:`)
+    Center,
+    /// Right aligned (`--:`)
+    Right,
+}
+
+impl Alignment {
+    /// Read the alignment of a table cell from its `align` attribute and
+    /// `style` attribute; a `text-align` declaration takes precedence
+    pub fn from_html(align: Option<&str>, style: Option<&str>) -> Self {
+        let text_align = style.and_then(|style| {
+            style.split(';').rev().find_map(|decl| {
+                let (prop, value) = decl.split_once(':')?;
+                prop.trim()
+                    .eq_ignore_ascii_case("text-align")
+                    .then(|| Self::from_keyword(value))
+            })
+        });
+
+        match text_align {
+            Some(alignment) if alignment != Alignment::None => alignment,
+            _ => align.map(Self::from_keyword).unwrap_or_default(),
+        }
+    }
+
+    fn from_keyword(value: &str) -> Self {
+        let value = value.trim();
+        let value = value
+            .strip_suffix("!important")
+            .map(str::trim_end)
+            .unwrap_or(value);
+        match value.to_ascii_lowercase().as_str() {
+            "left" | "start" => Alignment::Left,
+            "center" => Alignment::Center,
+            "right" | "end" => Alignment::Right,
+            _ => Alignment::None,
+        }
+    }
+
+    /// Resolve column alignments from per-cell alignments, header row first
+    ///
+    /// Each column takes the first explicit alignment found in it.
+    pub fn columns(rows: &[Vec<Alignment>]) -> Vec<Alignment> {
+        let mut columns: Vec<Alignment> = Vec::new();
+        for row in rows {
+            if columns.len() < row.len() {
+                columns.resize(row.len(), Alignment::None);
+            }
+            for (column, &cell) in columns.iter_mut().zip(row) {
+                if *column == Alignment::None {
+                    *column = cell;
+                }
+            }
+        }
+        while columns.last() == Some(&Alignment::None) {
+            columns.pop();
+        }
+        columns
+    }
+}
+
+/// Largest `colspan` honored, as in the HTML table model
+const MAX_COLSPAN: usize = 1000;
+
+/// Most grid positions spans may add to a table
+const MAX_SPANNED_POSITIONS: usize = 1 << 16;
+
+/// A table cell with its column and row span, before grid expansion
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedCell<T> {
+    pub value: T,
+    /// Columns covered; 0 counts as 1
+    pub colspan: usize,
+    /// Rows covered; 0 extends the cell to the last row of its row group
+    pub rowspan: usize,
+}
+
+/// A table row before grid expansion
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedRow<T> {
+    pub cells: Vec<SpannedCell<T>>,
+    /// Row group holding the row: a thead, tbody or tfoot, or a run of `tr`
+    /// elements directly in the table
+    pub group: usize,
+}
+
+/// Expand `colspan`/`rowspan` into a grid with one entry per column
+///
+/// Grid positions covered by a spanning cell get a copy of its value when
+/// `duplicate` is set, and `T::default()` otherwise. Rowspans are cut off at
+/// the last row of their row group; gaps left by the source table are filled
+/// with defaults. Spans are ignored when they would add more than 65536
+/// positions to the grid.
+pub fn expand_table_spans<T: Clone + Default>(
+    rows: Vec<SpannedRow<T>>,
+    duplicate: bool,
+) -> Vec<Vec<T>> {
+    // End of the row group of each row
+    let mut group_ends = vec![rows.len(); rows.len()];
+    for r in (1..rows.len()).rev() {
+        if rows[r - 1].group == rows[r].group {
+            group_ends[r - 1] = group_ends[r];
+        } else {
+            group_ends[r - 1] = r;
+        }
+    }
+    let spans = |r: usize, cell: &SpannedCell<T>| {
+        let rowspan = match cell.rowspan {
+            0 => group_ends[r] - r,
+            n => n.min(group_ends[r] - r),
+        };
+        (cell.colspan.clamp(1, MAX_COLSPAN), rowspan)
+    };
+
+    let mut added = 0usize;
+    for (r, row) in rows.iter().enumerate() {
+        for cell in &row.cells {
+            let (colspan, rowspan) = spans(r, cell);
+            added = added.saturating_add(colspan * rowspan - 1);
+        }
+    }
+    let expand = added <= MAX_SPANNED_POSITIONS;
+
+    let mut grid: Vec<Vec<Option<T>>> = vec![Vec::new(); rows.len()];
+    for (r, row) in rows.into_iter().enumerate() {
+        let mut col = 0;
+        for cell in row.cells {
+            // Skip positions taken by rowspans from the rows above
+            while grid[r].get(col).is_some_and(Option::is_some) {
+                col += 1;
+            }
+
+            let (colspan, rowspan) = if expand { spans(r, &cell) } else { (1, 1) };
+            for (dr, grid_row) in grid[r..r + rowspan].iter_mut().enumerate() {
+                if grid_row.len() < col + colspan {
+                    grid_row.resize(col + colspan, None);
+                }
+                for dc in 0..colspan {
+                    let value = if (dr == 0 && dc == 0) || duplicate {
+                        cell.value.clone()
+                    } else {
+                        T::default()
+                    };
+                    grid_row[col + dc] = Some(value);
+                }
+            }
+            col += colspan;
+        }
+    }
+
+    grid.into_iter()
+        .map(|row| row.into_iter().map(Option::unwrap_or_default).collect())
+        .collect()
+}
+
 /// A list item containing blocks
 #[derive(Debug, Clone, PartialEq)]
 pub struct ListItem {
     pub content: Vec<Block>,
+    /// Task list state from a leading checkbox (`None` for plain items)
+    pub checked: Option<bool>,
+    /// Whether the item gets a list marker; items without one are written
+    /// as they are, with no indent for their continuation lines
+    pub marker: bool,
 }
 
 impl ListItem {
     pub fn new(content: Vec<Block>) -> Self {
-        Self { content }
+        Self {
+            content,
+            checked: None,
+            marker: true,
+        }
     }
 
-    pub fn from_inlines(inlines: Vec<Inline>) -> Self {
+    /// An item replaced by Markdown that stands for the whole item, marker
+    /// included (like the output of a turndown.js rule for `li`)
+    pub fn verbatim(markdown: String) -> Self {
+        let content = if markdown.trim().is_empty() {
+            Vec::new()
+        } else {
+            vec![Block::HtmlBlock(markdown)]
+        };
         Self {
-            content: vec![Block::Paragraph(inlines)],
+            content,
+            checked: None,
+            marker: false,
         }
     }
+
+    pub fn from_inlines(inlines: Vec<Inline>) -> Self {
+        Self::new(vec![Block::Paragraph(inlines)])
+    }
+
+    /// Set the task list state
+    pub fn with_checked(mut self, checked: Option<bool>) -> Self {
+        self.checked = checked;
+        self
+    }
 }
 
 /// An inline Markdown node
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Cloning and comparing work without recursion, like [`Block`].
+#[derive(Debug)]
 pub enum Inline {
     /// Plain text
     Text(String),
@@ -78,6 +283,18 @@ pub enum Inline {
     /// Emphasis (italic)
     Emphasis(Vec<Inline>),
 
+    /// Strikethrough (`<del>`, `<s>`, `<strike>`)
+    Strikethrough(Vec<Inline>),
+
+    /// Highlighted text (`<mark>`)
+    Highlight(Vec<Inline>),
+
+    /// Superscript (`<sup>`)
+    Superscript(Vec<Inline>),
+
+    /// Subscript (`<sub>`)
+    Subscript(Vec<Inline>),
+
     /// Inline code
     Code(String),
 
@@ -103,68 +320,485 @@ pub enum Inline {
 }
 
 impl Block {
+    /// Drop the block without recursion
+    ///
+    /// A plain drop recurses once per nesting level, which overflows the
+    /// stack for deeply nested documents.
+    pub fn drop_iteratively(self) {
+        drop_trees(vec![self], Vec::new());
+    }
+
     /// Check if this block is empty/blank
     pub fn is_blank(&self) -> bool {
         match self {
-            Block::Document(blocks) => blocks.iter().all(|b| b.is_blank()),
-            Block::Paragraph(inlines) => inlines.iter().all(|i| i.is_blank()),
-            Block::Heading { content, .. } => content.iter().all(|i| i.is_blank()),
-            Block::BlockQuote(blocks) => blocks.iter().all(|b| b.is_blank()),
-            Block::List { items, .. } => items.iter().all(|i| i.is_blank()),
-            Block::CodeBlock { code, .. } => code.trim().is_empty(),
-            Block::Table { headers, rows } => {
-                headers.iter().all(|h| h.iter().all(|i| i.is_blank()))
-                    && rows
-                        .iter()
-                        .all(|r| r.iter().all(|c| c.iter().all(|i| i.is_blank())))
+            Block::Paragraph(inlines) | Block::Heading { content: inlines, .. } => {
+                all_blank(&[], inlines)
             }
-            Block::ThematicBreak => false,
-            Block::HtmlBlock(html) => html.trim().is_empty(),
+            _ => all_blank(std::slice::from_ref(self), &[]),
         }
     }
 }
 
 impl ListItem {
     pub fn is_blank(&self) -> bool {
-        self.content.iter().all(|b| b.is_blank())
+        all_blank(&self.content, &[])
     }
 }
 
 impl Inline {
     /// Check if this inline is empty/blank
     pub fn is_blank(&self) -> bool {
-        match self {
-            Inline::Text(text) => text.trim().is_empty(),
-            Inline::Strong(inlines) | Inline::Emphasis(inlines) => {
-                inlines.iter().all(|i| i.is_blank())
+        all_blank(&[], std::slice::from_ref(self))
+    }
+
+    /// Byte length of this inline as serialized with default options
+    pub fn text_len(&self) -> usize {
+        inlines_text_len(std::slice::from_ref(self))
+    }
+
+    /// Display width of this inline as serialized with `options`
+    pub fn display_width(&self, options: &Options) -> usize {
+        inlines_display_width(std::slice::from_ref(self), options)
+    }
+}
+
+/// Byte length of an inline vec as serialized with default options
+pub fn inlines_text_len(inlines: &[Inline]) -> usize {
+    serialize_inlines_standalone(inlines, &Options::default()).len()
+}
+
+/// Display width of an inline vec as serialized with `options` (for table
+/// column widths and setext underlines)
+pub fn inlines_display_width(inlines: &[Inline], options: &Options) -> usize {
+    display_width(&serialize_inlines_standalone(inlines, options))
+}
+
+/// Whether every leaf under the given blocks and inlines is blank
+///
+/// Walks the containers from a work list, so nesting depth does not grow the
+/// call stack.
+fn all_blank(blocks: &[Block], inlines: &[Inline]) -> bool {
+    let mut pending_blocks: Vec<&[Block]> = Vec::new();
+    let mut pending_inlines: Vec<&[Inline]> = Vec::new();
+    let (mut blocks, mut inlines) = (blocks, inlines);
+
+    loop {
+        for block in blocks {
+            let blank = match block {
+                Block::Document(blocks) | Block::BlockQuote(blocks) => {
+                    pending_blocks.push(blocks);
+                    true
+                }
+                Block::Paragraph(inlines) | Block::Heading { content: inlines, .. } => {
+                    pending_inlines.push(inlines);
+                    true
+                }
+                Block::List { items, .. } => {
+                    pending_blocks.extend(items.iter().map(|item| item.content.as_slice()));
+                    true
+                }
+                Block::CodeBlock { code, .. } => code.trim().is_empty(),
+                Block::Table {
+                    headers,
+                    rows,
+                    caption,
+                    ..
+                } => {
+                    pending_inlines.extend(caption.as_deref());
+                    pending_inlines.extend(headers.iter().map(Vec::as_slice));
+                    pending_inlines.extend(rows.iter().flatten().map(Vec::as_slice));
+                    true
+                }
+                Block::ThematicBreak => false,
+                Block::HtmlBlock(html) => html.trim().is_empty(),
+            };
+            if !blank {
+                return false;
             }
-            Inline::Code(code) => code.is_empty(),
-            Inline::Link { content, .. } => content.iter().all(|i| i.is_blank()),
-            Inline::Image { .. } => false,
-            Inline::LineBreak => false,
-            Inline::HtmlInline(html) => html.trim().is_empty(),
+        }
+
+        for inline in inlines {
+            let blank = match inline {
+                Inline::Text(text) => text.trim().is_empty(),
+                Inline::Strong(inlines)
+                | Inline::Emphasis(inlines)
+                | Inline::Strikethrough(inlines)
+                | Inline::Highlight(inlines)
+                | Inline::Superscript(inlines)
+                | Inline::Subscript(inlines)
+                | Inline::Link {
+                    content: inlines, ..
+                } => {
+                    pending_inlines.push(inlines);
+                    true
+                }
+                Inline::Code(code) => code.is_empty(),
+                Inline::Image { .. } => false,
+                Inline::LineBreak => false,
+                Inline::HtmlInline(html) => html.trim().is_empty(),
+            };
+            if !blank {
+                return false;
+            }
+        }
+
+        if let Some(next) = pending_inlines.pop() {
+            (blocks, inlines) = (&[], next);
+        } else if let Some(next) = pending_blocks.pop() {
+            (blocks, inlines) = (next, &[]);
+        } else {
+            return true;
         }
     }
+}
 
-    /// Get the text content of this inline (for measuring table column widths)
-    pub fn text_len(&self) -> usize {
+/// Tree nodes that are cloned, compared and torn down without recursion
+trait Tree: Sized {
+    /// The `index`th list of child nodes
+    fn children(&self, index: usize) -> Option<&Vec<Self>>;
+
+    fn children_mut(&mut self, index: usize) -> Option<&mut Vec<Self>>;
+
+    /// Copy of the node with empty lists of child nodes
+    fn copy_shallow(&self) -> Self;
+
+    /// Compare everything but the child nodes themselves
+    fn eq_shallow(&self, other: &Self) -> bool;
+}
+
+fn clone_tree<T: Tree>(root: &T) -> T {
+    // Ancestors of the node being copied, with their copies and the next
+    // child to copy, as index of the child list and index in it
+    let mut stack = Vec::new();
+    let (mut node, mut copy, mut next) = (root, root.copy_shallow(), (0, 0));
+    loop {
+        match node.children(next.0) {
+            Some(children) if next.1 < children.len() => {
+                let child = &children[next.1];
+                stack.push((node, copy, (next.0, next.1 + 1)));
+                (node, copy, next) = (child, child.copy_shallow(), (0, 0));
+            }
+            Some(_) => next = (next.0 + 1, 0),
+            None => match stack.pop() {
+                Some((parent, mut parent_copy, parent_next)) => {
+                    if let Some(children) = parent_copy.children_mut(parent_next.0) {
+                        children.push(copy);
+                    }
+                    (node, copy, next) = (parent, parent_copy, parent_next);
+                }
+                None => return copy,
+            },
+        }
+    }
+}
+
+fn eq_tree<T: Tree>(a: &T, b: &T) -> bool {
+    let mut stack = vec![(a, b)];
+    while let Some((a, b)) = stack.pop() {
+        if !a.eq_shallow(b) {
+            return false;
+        }
+        let mut index = 0;
+        while let (Some(a), Some(b)) = (a.children(index), b.children(index)) {
+            stack.extend(a.iter().zip(b));
+            index += 1;
+        }
+    }
+    true
+}
+
+/// Drop blocks and inlines after emptying them, so dropping never recurses
+pub(crate) fn drop_trees(mut blocks: Vec<Block>, mut inlines: Vec<Inline>) {
+    loop {
+        if let Some(mut inline) = inlines.pop() {
+            take_children(&mut inline, &mut inlines);
+        } else if let Some(mut block) = blocks.pop() {
+            take_children(&mut block, &mut blocks);
+            match &mut block {
+                Block::Paragraph(content) | Block::Heading { content, .. } => {
+                    inlines.append(content)
+                }
+                Block::Table {
+                    headers,
+                    rows,
+                    caption,
+                    ..
+                } => {
+                    let cells = headers.iter_mut().chain(rows.iter_mut().flatten());
+                    cells.chain(caption).for_each(|cell| inlines.append(cell));
+                }
+                _ => {}
+            }
+        } else {
+            return;
+        }
+    }
+}
+
+fn take_children<T: Tree>(node: &mut T, stack: &mut Vec<T>) {
+    let mut index = 0;
+    while let Some(children) = node.children_mut(index) {
+        stack.append(children);
+        index += 1;
+    }
+}
+
+impl Tree for Block {
+    fn children(&self, index: usize) -> Option<&Vec<Block>> {
+        match self {
+            Block::Document(blocks) | Block::BlockQuote(blocks) if index == 0 => Some(blocks),
+            Block::List { items, .. } => items.get(index).map(|item| &item.content),
+            _ => None,
+        }
+    }
+
+    fn children_mut(&mut self, index: usize) -> Option<&mut Vec<Block>> {
         match self {
-            Inline::Text(text) => text.len(),
-            Inline::Strong(inlines) | Inline::Emphasis(inlines) => {
-                inlines.iter().map(|i| i.text_len()).sum::<usize>() + 4 // ** or _
+            Block::Document(blocks) | Block::BlockQuote(blocks) if index == 0 => Some(blocks),
+            Block::List { items, .. } => items.get_mut(index).map(|item| &mut item.content),
+            _ => None,
+        }
+    }
+
+    fn copy_shallow(&self) -> Self {
+        match self {
+            Block::Document(blocks) => Block::Document(Vec::with_capacity(blocks.len())),
+            Block::Heading { level, content } => Block::Heading {
+                level: *level,
+                content: content.clone(),
+            },
+            Block::Paragraph(inlines) => Block::Paragraph(inlines.clone()),
+            Block::BlockQuote(blocks) => Block::BlockQuote(Vec::with_capacity(blocks.len())),
+            Block::List {
+                ordered,
+                start,
+                items,
+            } => Block::List {
+                ordered: *ordered,
+                start: *start,
+                items: items
+                    .iter()
+                    .map(|item| ListItem {
+                        content: Vec::with_capacity(item.content.len()),
+                        checked: item.checked,
+                        marker: item.marker,
+                    })
+                    .collect(),
+            },
+            Block::CodeBlock {
+                language,
+                code,
+                fenced,
+            } => Block::CodeBlock {
+                language: language.clone(),
+                code: code.clone(),
+                fenced: *fenced,
+            },
+            Block::ThematicBreak => Block::ThematicBreak,
+            Block::Table {
+                headers,
+                rows,
+                alignments,
+                caption,
+            } => Block::Table {
+                headers: headers.clone(),
+                rows: rows.clone(),
+                alignments: alignments.clone(),
+                caption: caption.clone(),
+            },
+            Block::HtmlBlock(html) => Block::HtmlBlock(html.clone()),
+        }
+    }
+
+    fn eq_shallow(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Block::Document(a), Block::Document(b))
+            | (Block::BlockQuote(a), Block::BlockQuote(b)) => a.len() == b.len(),
+            (
+                Block::Heading { level, content },
+                Block::Heading {
+                    level: other_level,
+                    content: other_content,
+                },
+            ) => level == other_level && content == other_content,
+            (Block::Paragraph(a), Block::Paragraph(b)) => a == b,
+            (
+                Block::List {
+                    ordered,
+                    start,
+                    items,
+                },
+                Block::List {
+                    ordered: other_ordered,
+                    start: other_start,
+                    items: other_items,
+                },
+            ) => {
+                ordered == other_ordered
+                    && start == other_start
+                    && items.len() == other_items.len()
+                    && items.iter().zip(other_items).all(|(a, b)| {
+                        a.checked == b.checked
+                            && a.marker == b.marker
+                            && a.content.len() == b.content.len()
+                    })
             }
-            Inline::Code(code) => code.len() + 2, // backticks
-            Inline::Link { content, .. } => {
-                content.iter().map(|i| i.text_len()).sum::<usize>() + 4 // []()
+            (
+                Block::CodeBlock {
+                    language,
+                    code,
+                    fenced,
+                },
+                Block::CodeBlock {
+                    language: other_language,
+                    code: other_code,
+                    fenced: other_fenced,
+                },
+            ) => language == other_language && code == other_code && fenced == other_fenced,
+            (Block::ThematicBreak, Block::ThematicBreak) => true,
+            (
+                Block::Table {
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                },
+                Block::Table {
+                    headers: other_headers,
+                    rows: other_rows,
+                    alignments: other_alignments,
+                    caption: other_caption,
+                },
+            ) => {
+                headers == other_headers
+                    && rows == other_rows
+                    && alignments == other_alignments
+                    && caption == other_caption
             }
-            Inline::Image { alt, .. } => alt.len() + 5, // ![]()
-            Inline::LineBreak => 0,
-            Inline::HtmlInline(html) => html.len(),
+            (Block::HtmlBlock(a), Block::HtmlBlock(b)) => a == b,
+            _ => false,
         }
     }
 }
 
-/// Helper to calculate text length of inline vec
-pub fn inlines_text_len(inlines: &[Inline]) -> usize {
-    inlines.iter().map(|i| i.text_len()).sum()
+impl Tree for Inline {
+    fn children(&self, index: usize) -> Option<&Vec<Inline>> {
+        match self {
+            Inline::Strong(inlines)
+            | Inline::Emphasis(inlines)
+            | Inline::Strikethrough(inlines)
+            | Inline::Highlight(inlines)
+            | Inline::Superscript(inlines)
+            | Inline::Subscript(inlines)
+            | Inline::Link {
+                content: inlines, ..
+            } if index == 0 => Some(inlines),
+            _ => None,
+        }
+    }
+
+    fn children_mut(&mut self, index: usize) -> Option<&mut Vec<Inline>> {
+        match self {
+            Inline::Strong(inlines)
+            | Inline::Emphasis(inlines)
+            | Inline::Strikethrough(inlines)
+            | Inline::Highlight(inlines)
+            | Inline::Superscript(inlines)
+            | Inline::Subscript(inlines)
+            | Inline::Link {
+                content: inlines, ..
+            } if index == 0 => Some(inlines),
+            _ => None,
+        }
+    }
+
+    fn copy_shallow(&self) -> Self {
+        let empty = |inlines: &Vec<Inline>| Vec::with_capacity(inlines.len());
+        match self {
+            Inline::Text(text) => Inline::Text(text.clone()),
+            Inline::Strong(inlines) => Inline::Strong(empty(inlines)),
+            Inline::Emphasis(inlines) => Inline::Emphasis(empty(inlines)),
+            Inline::Strikethrough(inlines) => Inline::Strikethrough(empty(inlines)),
+            Inline::Highlight(inlines) => Inline::Highlight(empty(inlines)),
+            Inline::Superscript(inlines) => Inline::Superscript(empty(inlines)),
+            Inline::Subscript(inlines) => Inline::Subscript(empty(inlines)),
+            Inline::Code(code) => Inline::Code(code.clone()),
+            Inline::Link {
+                content,
+                url,
+                title,
+            } => Inline::Link {
+                content: empty(content),
+                url: url.clone(),
+                title: title.clone(),
+            },
+            Inline::Image { alt, url, title } => Inline::Image {
+                alt: alt.clone(),
+                url: url.clone(),
+                title: title.clone(),
+            },
+            Inline::LineBreak => Inline::LineBreak,
+            Inline::HtmlInline(html) => Inline::HtmlInline(html.clone()),
+        }
+    }
+
+    fn eq_shallow(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Inline::Text(a), Inline::Text(b))
+            | (Inline::Code(a), Inline::Code(b))
+            | (Inline::HtmlInline(a), Inline::HtmlInline(b)) => a == b,
+            (Inline::Strong(a), Inline::Strong(b))
+            | (Inline::Emphasis(a), Inline::Emphasis(b))
+            | (Inline::Strikethrough(a), Inline::Strikethrough(b))
+            | (Inline::Highlight(a), Inline::Highlight(b))
+            | (Inline::Superscript(a), Inline::Superscript(b))
+            | (Inline::Subscript(a), Inline::Subscript(b)) => a.len() == b.len(),
+            (
+                Inline::Link {
+                    content,
+                    url,
+                    title,
+                },
+                Inline::Link {
+                    content: other_content,
+                    url: other_url,
+                    title: other_title,
+                },
+            ) => content.len() == other_content.len() && url == other_url && title == other_title,
+            (
+                Inline::Image { alt, url, title },
+                Inline::Image {
+                    alt: other_alt,
+                    url: other_url,
+                    title: other_title,
+                },
+            ) => alt == other_alt && url == other_url && title == other_title,
+            (Inline::LineBreak, Inline::LineBreak) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Clone for Block {
+    fn clone(&self) -> Self {
+        clone_tree(self)
+    }
+}
+
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        eq_tree(self, other)
+    }
+}
+
+impl Clone for Inline {
+    fn clone(&self) -> Self {
+        clone_tree(self)
+    }
+}
+
+impl PartialEq for Inline {
+    fn eq(&self, other: &Self) -> bool {
+        eq_tree(self, other)
+    }
 }
@@ -0,0 +1,26 @@
+//! Errors of the conversion pipeline
+//!
+//! Shared by both converters, so bindings can map every failure to one
+//! error type regardless of where the DOM came from.
+
+/// Error type for turndown operations
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TurndownError {
+    /// The HTML could not be parsed
+    #[error("HTML parse error: {0}")]
+    Parse(String),
+
+    /// The input exceeded a configured limit
+    #[error("{limit} limit of {max} exceeded")]
+    LimitExceeded { limit: &'static str, max: usize },
+
+    /// An option has a value the serializer cannot work with
+    #[error("Invalid option `{name}`: {message}")]
+    InvalidOption { name: &'static str, message: String },
+
+    /// The conversion was cancelled before it finished
+    #[error("Conversion aborted")]
+    Aborted,
+}
+
+pub type Result<T> = std::result::Result<T, TurndownError>;
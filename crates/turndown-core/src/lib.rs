@@ -35,9 +35,25 @@
 //! ```
 
 mod ast;
+mod error;
+mod limits;
 mod options;
+mod rule;
 mod serialize;
+mod whitespace;
 
-pub use ast::{inlines_text_len, Block, Inline, ListItem};
-pub use options::{CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle, Options};
-pub use serialize::serialize;
+pub use ast::{
+    expand_table_spans, inlines_display_width, inlines_text_len, Alignment, Block, Inline,
+    ListItem, SpannedCell, SpannedRow,
+};
+pub use error::{Result, TurndownError};
+pub use limits::{truncate_str, LimitCounter, LimitMode, Limits};
+pub use options::{
+    CaptionPosition, CaptionStyle, CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle,
+    MarkupStyle, Options, TableFallback, TableSpans,
+};
+pub use rule::{Content, Element, Filter, Replacement, Rule, Rules};
+pub use serialize::{serialize, serialize_fragment, serialize_limited};
+pub use whitespace::{
+    CollapsedText, WhitespaceCollapser, WhitespaceKind, BLOCK_ELEMENTS, VOID_ELEMENTS,
+};
@@ -0,0 +1,151 @@
+//! Resource limits for untrusted input
+//!
+//! Both converters check the input tree against the [`Limits`] in a cheap,
+//! non-recursive walk before converting it. In [`LimitMode::Truncate`] they
+//! convert the part of the document in front of the first node over a limit.
+
+use crate::error::{Result, TurndownError};
+
+/// Reaction to input or output exceeding one of the [`Limits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitMode {
+    /// Fail with [`TurndownError::LimitExceeded`]
+    #[default]
+    Error,
+    /// Convert what fits and drop the rest
+    Truncate,
+}
+
+/// Resource limits, all disabled by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// Maximum nesting depth of nodes (top-level nodes are at depth 1)
+    pub max_depth: Option<usize>,
+
+    /// Maximum size of the input: the HTML source, or the text and attribute
+    /// bytes of a DOM tree
+    pub max_input_bytes: Option<usize>,
+
+    /// Maximum size of the Markdown output
+    pub max_output_bytes: Option<usize>,
+
+    /// Maximum number of nodes (elements, text and comments)
+    pub max_nodes: Option<usize>,
+
+    /// Reaction to exceeding a limit
+    pub mode: LimitMode,
+}
+
+impl Limits {
+    /// Whether the input tree has to be walked before conversion
+    pub fn limits_tree(&self) -> bool {
+        self.max_depth.is_some() || self.max_input_bytes.is_some() || self.max_nodes.is_some()
+    }
+
+    /// `Err` for an exceeded limit in [`LimitMode::Error`], `Ok` when the
+    /// caller should truncate instead
+    pub fn exceeded(&self, err: TurndownError) -> Result<()> {
+        match self.mode {
+            LimitMode::Error => Err(err),
+            LimitMode::Truncate => Ok(()),
+        }
+    }
+}
+
+/// Counts nodes of a document-order walk against the [`Limits`]
+#[derive(Debug)]
+pub struct LimitCounter<'l> {
+    limits: &'l Limits,
+    nodes: usize,
+    input_bytes: usize,
+}
+
+impl<'l> LimitCounter<'l> {
+    pub fn new(limits: &'l Limits) -> Self {
+        Self {
+            limits,
+            nodes: 0,
+            input_bytes: 0,
+        }
+    }
+
+    /// Count a node at `depth` holding `bytes` of input, returning the
+    /// limit it exceeds
+    pub fn visit(&mut self, depth: usize, bytes: usize) -> Option<TurndownError> {
+        self.nodes += 1;
+        self.input_bytes += bytes;
+
+        let checks = [
+            ("depth", self.limits.max_depth, depth),
+            ("node count", self.limits.max_nodes, self.nodes),
+            ("input size", self.limits.max_input_bytes, self.input_bytes),
+        ];
+        checks.into_iter().find_map(|(limit, max, value)| {
+            max.filter(|&max| value > max)
+                .map(|max| TurndownError::LimitExceeded { limit, max })
+        })
+    }
+}
+
+/// Longest prefix of `s` that is at most `max` bytes and ends on a char
+/// boundary
+pub fn truncate_str(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_counter() {
+        let limits = Limits {
+            max_depth: Some(2),
+            max_nodes: Some(3),
+            ..Limits::default()
+        };
+        let mut counter = LimitCounter::new(&limits);
+        assert_eq!(counter.visit(1, 0), None);
+        assert_eq!(counter.visit(2, 0), None);
+        assert_eq!(
+            counter.visit(3, 0),
+            Some(TurndownError::LimitExceeded {
+                limit: "depth",
+                max: 2
+            })
+        );
+        assert_eq!(
+            counter.visit(1, 0),
+            Some(TurndownError::LimitExceeded {
+                limit: "node count",
+                max: 3
+            })
+        );
+
+        assert!(limits
+            .exceeded(TurndownError::Parse(String::new()))
+            .is_err());
+        let truncate = Limits {
+            mode: LimitMode::Truncate,
+            ..limits
+        };
+        assert_eq!(
+            truncate.exceeded(TurndownError::Parse(String::new())),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_truncate_str() {
+        assert_eq!(truncate_str("abc", 5), "abc");
+        assert_eq!(truncate_str("abc", 2), "ab");
+        assert_eq!(truncate_str("aé", 2), "a");
+    }
+}
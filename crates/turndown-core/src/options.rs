@@ -1,5 +1,8 @@
 //! Configuration options for Markdown serialization
 
+use crate::error::{Result, TurndownError};
+use crate::limits::Limits;
+
 /// Heading style options
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum HeadingStyle {
@@ -43,6 +46,64 @@ pub enum LinkReferenceStyle {
     Shortcut,
 }
 
+/// Serialization of inline markup beyond CommonMark (strikethrough,
+/// highlight, superscript and subscript)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkupStyle {
+    /// Emit the content only, like turndown's CommonMark rules
+    #[default]
+    Plain,
+    /// Use Markdown extensions: ~~del~~, ==mark==, ^sup^, ~sub~
+    Markdown,
+    /// Keep the markup as inline HTML: <del>, <mark>, <sup>, <sub>
+    Html,
+}
+
+/// Output for tables that cannot be represented as GFM pipe tables
+/// (cells spanning rows or columns, or holding block content)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableFallback {
+    /// Keep the table as raw HTML
+    #[default]
+    Html,
+    /// Lay the table out as a list: one item per row, a nested item per cell
+    List,
+}
+
+/// Handling of table cells spanning several rows or columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableSpans {
+    /// Treat the table as too complex and use the [`TableFallback`]
+    #[default]
+    Fallback,
+    /// Expand spans into the grid, leaving the covered cells empty
+    Blank,
+    /// Expand spans into the grid, repeating the content in covered cells
+    Duplicate,
+}
+
+/// Placement of a table caption
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaptionPosition {
+    /// Paragraph above the table
+    #[default]
+    Above,
+    /// Paragraph below the table
+    Below,
+}
+
+/// Formatting of a table caption
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaptionStyle {
+    /// The caption text as is
+    #[default]
+    Plain,
+    /// The caption in strong emphasis
+    Bold,
+    /// Pandoc table caption: `Table: caption`
+    Pandoc,
+}
+
 /// Options for Markdown serialization
 #[derive(Debug, Clone)]
 pub struct Options {
@@ -72,6 +133,43 @@ pub struct Options {
 
     /// Reference style for referenced links
     pub link_reference_style: LinkReferenceStyle,
+
+    /// Convert `<table>` to GFM pipe tables (like turndown-plugin-gfm's `tables`)
+    /// instead of flattening cells into paragraphs. Only affects the HTML
+    /// converter; the CDP converter always builds tables.
+    pub tables: bool,
+
+    /// Render list items starting with a checkbox as GFM task list items
+    /// (`[x]` / `[ ]`), like turndown-plugin-gfm's `taskListItems`
+    pub task_lists: bool,
+
+    /// Strikethrough style for `<del>`, `<s>` and `<strike>`
+    pub strikethrough_style: MarkupStyle,
+
+    /// Highlight style for `<mark>`
+    pub highlight_style: MarkupStyle,
+
+    /// Superscript and subscript style for `<sup>` and `<sub>` (Pandoc syntax)
+    pub script_style: MarkupStyle,
+
+    /// Replacement for line breaks inside table cells, where a newline would
+    /// end the row (so it may not contain newlines or `|`)
+    pub table_line_break: String,
+
+    /// Output for tables too complex for a pipe table
+    pub table_fallback: TableFallback,
+
+    /// Handling of `colspan` and `rowspan`
+    pub table_spans: TableSpans,
+
+    /// Placement of table captions
+    pub caption_position: CaptionPosition,
+
+    /// Formatting of table captions
+    pub caption_style: CaptionStyle,
+
+    /// Resource limits for untrusted input
+    pub limits: Limits,
 }
 
 impl Default for Options {
@@ -86,6 +184,96 @@ impl Default for Options {
             strong_delimiter: "**".to_string(),
             link_style: LinkStyle::Inlined,
             link_reference_style: LinkReferenceStyle::Full,
+            tables: false,
+            task_lists: false,
+            strikethrough_style: MarkupStyle::Plain,
+            highlight_style: MarkupStyle::Plain,
+            script_style: MarkupStyle::Plain,
+            table_line_break: "<br>".to_string(),
+            table_fallback: TableFallback::Html,
+            table_spans: TableSpans::Fallback,
+            caption_position: CaptionPosition::Above,
+            caption_style: CaptionStyle::Plain,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl Options {
+    /// Check that the delimiters and markers produce valid Markdown
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |name, message: &str| {
+            Err(TurndownError::InvalidOption {
+                name,
+                message: message.to_string(),
+            })
+        };
+
+        let fence_char = self.fence.chars().next().unwrap_or(' ');
+        if !matches!(fence_char, '`' | '~')
+            || self.fence.chars().count() < 3
+            || self.fence.chars().any(|c| c != fence_char)
+        {
+            return invalid("fence", "expected three or more backticks or tildes");
+        }
+        if !matches!(self.em_delimiter, '_' | '*') {
+            return invalid("em_delimiter", "expected \"_\" or \"*\"");
+        }
+        if !matches!(self.strong_delimiter.as_str(), "**" | "__") {
+            return invalid("strong_delimiter", "expected \"**\" or \"__\"");
+        }
+        if !matches!(self.bullet_list_marker, '*' | '-' | '+') {
+            return invalid("bullet_list_marker", "expected \"*\", \"-\" or \"+\"");
+        }
+        if self.hr.trim().is_empty() || self.hr.contains('\n') {
+            return invalid("hr", "expected a single non-empty line");
+        }
+        if self.table_line_break.contains(['|', '\n', '\r']) {
+            return invalid("table_line_break", "expected text without \"|\" or newlines");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Options::default().validate(), Ok(()));
+
+        let options = Options {
+            fence: "~~~~".to_string(),
+            em_delimiter: '*',
+            ..Options::default()
+        };
+        assert_eq!(options.validate(), Ok(()));
+
+        let options = Options {
+            fence: "``".to_string(),
+            ..Options::default()
+        };
+        let err = options.validate().unwrap_err();
+        assert!(matches!(err, TurndownError::InvalidOption { name: "fence", .. }));
+        assert_eq!(
+            err.to_string(),
+            "Invalid option `fence`: expected three or more backticks or tildes"
+        );
+
+        let options = Options {
+            strong_delimiter: "*".to_string(),
+            ..Options::default()
+        };
+        assert!(options.validate().is_err());
+
+        for line_break in ["a|b", "\n", "<br>\r\n"] {
+            let options = Options {
+                table_line_break: line_break.to_string(),
+                ..Options::default()
+            };
+            let err = options.validate().unwrap_err();
+            assert!(matches!(err, TurndownError::InvalidOption { name: "table_line_break", .. }));
         }
     }
 }
@@ -0,0 +1,396 @@
+//! Custom conversion rules
+//!
+//! The Rust equivalent of turndown.js `addRule`: a filter selecting elements
+//! plus a replacement producing Markdown AST nodes. Rules are DOM-agnostic and
+//! see elements through the [`Element`] view, so the same rule works with both
+//! the tl-based and the CDP converters.
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use crate::ast::{Block, Inline};
+use crate::options::Options;
+use crate::serialize::serialize_fragment;
+
+/// Read-only view of an element handed to rule filters and replacements
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    /// Lowercase tag name
+    pub tag: String,
+    /// Attributes as (name, value) pairs in source order
+    pub attributes: Vec<(String, String)>,
+    /// Lowercase tag names of the enclosing elements, outermost first
+    pub ancestors: Vec<String>,
+}
+
+impl Element {
+    /// Get an attribute value by name (case-insensitive)
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Check if the element carries a class name
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attr("class")
+            .is_some_and(|c| c.split_whitespace().any(|c| c == class))
+    }
+
+    /// Tag name of the direct parent, if known
+    pub fn parent(&self) -> Option<&str> {
+        self.ancestors.last().map(|s| s.as_str())
+    }
+
+    /// Check if any enclosing element has the given tag name
+    pub fn has_ancestor(&self, tag: &str) -> bool {
+        self.ancestors.iter().any(|a| a == tag)
+    }
+}
+
+type Predicate = dyn Fn(&Element) -> bool + Send + Sync;
+type ReplacementFn = dyn Fn(&Element, Content) -> Replacement + Send + Sync;
+
+/// Selects the elements a rule applies to
+#[derive(Clone)]
+pub enum Filter {
+    /// Lowercase tag names
+    Tags(Vec<String>),
+    /// Arbitrary predicate over the element
+    Predicate(Arc<Predicate>),
+}
+
+impl Filter {
+    /// Create a predicate filter
+    pub fn predicate(f: impl Fn(&Element) -> bool + Send + Sync + 'static) -> Self {
+        Filter::Predicate(Arc::new(f))
+    }
+}
+
+impl From<&str> for Filter {
+    fn from(tag: &str) -> Self {
+        Filter::Tags(vec![tag.to_lowercase()])
+    }
+}
+
+impl From<&[&str]> for Filter {
+    fn from(tags: &[&str]) -> Self {
+        Filter::Tags(tags.iter().map(|t| t.to_lowercase()).collect())
+    }
+}
+
+impl<const N: usize> From<[&str; N]> for Filter {
+    fn from(tags: [&str; N]) -> Self {
+        Filter::from(&tags[..])
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Tags(tags) => f.debug_tuple("Tags").field(tags).finish(),
+            Filter::Predicate(_) => f.write_str("Predicate(..)"),
+        }
+    }
+}
+
+/// Converted children of a matched element
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    /// The element contains block-level children
+    Blocks(Vec<Block>),
+    /// The element contains only inline children (or sits in inline context)
+    Inlines(Vec<Inline>),
+}
+
+impl Content {
+    /// Get the content as blocks, wrapping inline content in a paragraph
+    pub fn into_blocks(self) -> Vec<Block> {
+        match self {
+            Content::Blocks(blocks) => blocks,
+            Content::Inlines(inlines) if inlines.is_empty() => Vec::new(),
+            Content::Inlines(inlines) => vec![Block::Paragraph(inlines)],
+        }
+    }
+
+    /// Get the content as inlines, flattening blocks into space-separated text
+    pub fn into_inlines(self) -> Vec<Inline> {
+        match self {
+            Content::Inlines(inlines) => inlines,
+            Content::Blocks(blocks) => {
+                let mut inlines = Vec::new();
+                flatten_blocks(blocks, &mut inlines);
+                inlines
+            }
+        }
+    }
+}
+
+fn flatten_blocks(blocks: Vec<Block>, out: &mut Vec<Inline>) {
+    // One iterator per open container
+    let mut stack = vec![blocks.into_iter()];
+    while let Some(blocks) = stack.last_mut() {
+        let Some(mut block) = blocks.next() else {
+            stack.pop();
+            continue;
+        };
+        let inlines = match &mut block {
+            Block::Paragraph(content) | Block::Heading { content, .. } => mem::take(content),
+            Block::Document(inner) | Block::BlockQuote(inner) => {
+                stack.push(mem::take(inner).into_iter());
+                continue;
+            }
+            Block::List { items, .. } => {
+                let inner: Vec<Block> =
+                    items.iter_mut().flat_map(|item| mem::take(&mut item.content)).collect();
+                stack.push(inner.into_iter());
+                continue;
+            }
+            Block::CodeBlock { code, .. } => vec![Inline::Code(mem::take(code))],
+            Block::HtmlBlock(html) => vec![Inline::HtmlInline(mem::take(html))],
+            Block::ThematicBreak | Block::Table { .. } => continue,
+        };
+        if !out.is_empty() {
+            out.push(Inline::Text(" ".to_string()));
+        }
+        out.extend(inlines);
+    }
+}
+
+/// What a rule produces for a matched element
+#[derive(Debug, Clone, PartialEq)]
+pub enum Replacement {
+    /// Block nodes (wrapped or serialized as needed in inline context)
+    Blocks(Vec<Block>),
+    /// Inline nodes (wrapped in a paragraph at block level)
+    Inlines(Vec<Inline>),
+}
+
+impl Replacement {
+    /// Drop the element and its content
+    pub fn remove() -> Self {
+        Replacement::Inlines(Vec::new())
+    }
+
+    /// Get the replacement as blocks, wrapping inlines in a paragraph
+    pub fn into_blocks(self) -> Vec<Block> {
+        match self {
+            Replacement::Blocks(blocks) => blocks,
+            Replacement::Inlines(inlines) => Content::Inlines(inlines).into_blocks(),
+        }
+    }
+
+    /// Get the replacement as a single block for block-level context
+    pub fn into_block(self) -> Option<Block> {
+        let mut blocks = self.into_blocks();
+        match blocks.len() {
+            0 => None,
+            1 => blocks.pop(),
+            _ => Some(Block::Document(blocks)),
+        }
+    }
+
+    /// Get the replacement as inlines for inline context
+    ///
+    /// Blocks cannot nest inside inline content, so they are serialized and
+    /// embedded verbatim, mirroring turndown.js string replacements.
+    pub fn into_inlines(self, options: &Options) -> Vec<Inline> {
+        match self {
+            Replacement::Inlines(inlines) => inlines,
+            Replacement::Blocks(blocks) if blocks.is_empty() => Vec::new(),
+            Replacement::Blocks(blocks) => {
+                let document = Block::Document(blocks);
+                let markdown = serialize_fragment(&document, options);
+                document.drop_iteratively();
+                vec![Inline::HtmlInline(markdown)]
+            }
+        }
+    }
+}
+
+/// A custom conversion rule
+#[derive(Clone)]
+pub struct Rule {
+    filter: Filter,
+    replacement: Arc<ReplacementFn>,
+}
+
+impl Rule {
+    /// Create a rule from a filter and a replacement function
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use turndown_core::{Block, Replacement, Rule};
+    ///
+    /// // <aside class="note">…</aside> → blockquote
+    /// let rule = Rule::new("aside", |_element, content| {
+    ///     Replacement::Blocks(vec![Block::BlockQuote(content.into_blocks())])
+    /// });
+    /// ```
+    pub fn new(
+        filter: impl Into<Filter>,
+        replacement: impl Fn(&Element, Content) -> Replacement + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            filter: filter.into(),
+            replacement: Arc::new(replacement),
+        }
+    }
+
+    /// The filter selecting elements for this rule
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// Run the replacement for a matched element
+    pub fn replace(&self, element: &Element, content: Content) -> Replacement {
+        (self.replacement)(element, content)
+    }
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rule")
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Ordered set of custom rules
+///
+/// Like turndown.js, the most recently added rule takes precedence, and custom
+/// rules take precedence over built-in conversion and keep/remove filters.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    entries: Vec<(String, Rule)>,
+}
+
+impl Rules {
+    /// Create an empty rule set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule under a key, ahead of all previously added rules
+    pub fn add(&mut self, key: &str, rule: Rule) {
+        self.entries.insert(0, (key.to_string(), rule));
+    }
+
+    /// Check if no rules are registered
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of registered rules
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Iterate over (key, rule) pairs in precedence order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Rule)> {
+        self.entries.iter().map(|(k, r)| (k.as_str(), r))
+    }
+
+    /// Find the first rule matching an element
+    ///
+    /// `element` is only built when a predicate needs it or a rule matches,
+    /// so tag-only rule sets stay cheap for non-matching elements.
+    pub fn find(&self, tag: &str, element: impl FnOnce() -> Element) -> Option<(&Rule, Element)> {
+        let mut make = Some(element);
+        let mut built: Option<Element> = None;
+
+        for (_, rule) in &self.entries {
+            let matched = match &rule.filter {
+                Filter::Tags(tags) => tags.iter().any(|t| t == tag),
+                Filter::Predicate(predicate) => {
+                    let element = built.get_or_insert_with(|| (make.take().unwrap())());
+                    predicate(element)
+                }
+            };
+            if matched {
+                let element = built.unwrap_or_else(|| (make.take().unwrap())());
+                return Some((rule, element));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(tag: &str, attributes: &[(&str, &str)]) -> Element {
+        Element {
+            tag: tag.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ancestors: vec!["body".to_string(), "div".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_find_by_tag() {
+        let mut rules = Rules::new();
+        rules.add("mark", Rule::new(["mark", "ins"], |_, c| Replacement::Inlines(c.into_inlines())));
+
+        assert!(rules.find("ins", || element("ins", &[])).is_some());
+        assert!(rules.find("del", || element("del", &[])).is_none());
+    }
+
+    #[test]
+    fn test_find_by_predicate() {
+        let mut rules = Rules::new();
+        rules.add(
+            "callout",
+            Rule::new(
+                Filter::predicate(|el| el.has_class("callout") && el.parent() == Some("div")),
+                |_, c| Replacement::Blocks(vec![Block::BlockQuote(c.into_blocks())]),
+            ),
+        );
+
+        let (_, el) = rules
+            .find("div", || element("div", &[("class", "box callout")]))
+            .unwrap();
+        assert_eq!(el.attr("CLASS"), Some("box callout"));
+        assert!(rules.find("div", || element("div", &[])).is_none());
+    }
+
+    #[test]
+    fn test_latest_rule_wins() {
+        let mut rules = Rules::new();
+        rules.add("first", Rule::new("span", |_, _| Replacement::remove()));
+        rules.add(
+            "second",
+            Rule::new("span", |_, _| Replacement::Inlines(vec![Inline::Text("x".to_string())])),
+        );
+
+        let (rule, el) = rules.find("span", || element("span", &[])).unwrap();
+        assert_eq!(
+            rule.replace(&el, Content::Inlines(vec![])),
+            Replacement::Inlines(vec![Inline::Text("x".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_content_into_inlines() {
+        let content = Content::Blocks(vec![
+            Block::Paragraph(vec![Inline::Text("a".to_string())]),
+            Block::Paragraph(vec![Inline::Text("b".to_string())]),
+        ]);
+        assert_eq!(
+            content.into_inlines(),
+            vec![
+                Inline::Text("a".to_string()),
+                Inline::Text(" ".to_string()),
+                Inline::Text("b".to_string()),
+            ]
+        );
+    }
+}
@@ -2,201 +2,716 @@
 //!
 //! Converts Markdown AST nodes into Markdown text.
 
-use crate::ast::{inlines_text_len, Block, Inline, ListItem};
-use crate::options::{CodeBlockStyle, HeadingStyle, Options};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::ast::{drop_trees, Alignment, Block, Inline, ListItem};
+use crate::error::{Result, TurndownError};
+use crate::options::{
+    CaptionPosition, CaptionStyle, CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle,
+    MarkupStyle, Options,
+};
 
 /// Serialize a block to Markdown string
 pub fn serialize(block: &Block, options: &Options) -> String {
     // Estimate capacity: ~2x input for markdown overhead
-    let mut output = String::with_capacity(4096);
-    serialize_block(block, options, 0, &mut output);
+    let mut output = Writer::with_capacity(4096);
+    let mut ctx = Context::default();
+    serialize_block(block, options, &mut ctx, &mut output);
+    finish(output, ctx)
+}
 
-    // Post-process: collapse multiple newlines and trim
-    collapse_and_trim(&mut output);
-    output
+/// Serialize a block for embedding into other Markdown
+///
+/// A fragment cannot carry link reference definitions, so its links are
+/// always inlined; the surrounding document collects its own definitions.
+pub fn serialize_fragment(block: &Block, options: &Options) -> String {
+    let mut output = Writer::with_capacity(0);
+    let mut ctx = Context {
+        inline_links: true,
+        ..Context::default()
+    };
+    serialize_block(block, options, &mut ctx, &mut output);
+    finish(output, ctx)
 }
 
-fn serialize_block(block: &Block, options: &Options, depth: usize, out: &mut String) {
-    match block {
-        Block::Document(blocks) => serialize_blocks(blocks, options, depth, out),
+/// Serialize a block, enforcing the `max_output_bytes` limit
+///
+/// Serialization stops as soon as the output exceeds the limit. When
+/// truncating, the output ends after the last block that fit, so it stays
+/// well-formed.
+pub fn serialize_limited(block: &Block, options: &Options) -> Result<String> {
+    let Some(max) = options.limits.max_output_bytes else {
+        return Ok(serialize(block, options));
+    };
+    let mut output = Writer::with_capacity(max.min(4096));
+    output.budget = Some(max);
+    let mut ctx = Context::default();
+    serialize_block(block, options, &mut ctx, &mut output);
+
+    let (exceeded, (len, count)) = (output.exceeded, output.fit);
+    let mut output = output.finish();
+    let definitions = &ctx.references.definitions;
+    if !exceeded {
+        append_definitions(&mut output, definitions);
+        if output.len() <= max {
+            return Ok(output);
+        }
+    }
+    options.limits.exceeded(TurndownError::LimitExceeded {
+        limit: "output size",
+        max,
+    })?;
+
+    output.truncate(len);
+    append_definitions(&mut output, &definitions[..count]);
+    Ok(output)
+}
 
-        Block::Heading { level, content } => serialize_heading(*level, content, options, out),
+/// Append link reference definitions to the output
+fn finish(output: Writer, ctx: Context) -> String {
+    let mut output = output.finish();
+    append_definitions(&mut output, &ctx.references.definitions);
+    output
+}
 
-        Block::Paragraph(inlines) => {
-            let start_len = out.len();
-            serialize_inlines(inlines, options, out);
-            if out[start_len..].trim().is_empty() {
-                out.truncate(start_len);
-            } else {
-                out.push_str("\n\n");
-            }
+/// Append link reference definitions collected along the way
+fn append_definitions(output: &mut String, definitions: &[String]) {
+    if !definitions.is_empty() {
+        if !output.is_empty() {
+            output.push_str("\n\n");
         }
+        output.push_str(&definitions.join("\n"));
+    }
+}
 
-        Block::BlockQuote(blocks) => {
-            let start_len = out.len();
-            serialize_blocks(blocks, options, depth, out);
+/// State shared across one serialization run
+#[derive(Default)]
+struct Context {
+    references: References,
+    /// Inline links even with `LinkStyle::Referenced`
+    inline_links: bool,
+    /// Ranges of the current block output written verbatim (code and HTML
+    /// blocks), whose blank lines are kept
+    verbatim: Vec<Range<usize>>,
+    /// Serializing the inlines of a table cell
+    in_table_cell: bool,
+    /// Whether each block is blank and left out, by index in document order
+    blank: Vec<bool>,
+    /// Index following the subtree of each block
+    subtree_ends: Vec<usize>,
+    /// Reused buffer for the closing delimiters of inline containers
+    scratch: String,
+}
 
-            // Process the content we just wrote to add > prefixes
-            let content = out[start_len..].trim_end().to_string();
-            out.truncate(start_len);
+impl Context {
+    /// Mark `out[start..]` as verbatim so blank lines in it are kept
+    fn mark_verbatim(&mut self, start: usize, out: &str) {
+        if start < out.len() {
+            self.verbatim.push(start..out.len());
+        }
+    }
+}
+
+/// Whether each block of the tree under `root` is blank, by index in document
+/// order, and the index following the subtree of each block
+///
+/// Containers are checked bottom-up for their whole subtree at once, as
+/// checking every nesting level on its own takes quadratic time in deeply
+/// nested documents.
+fn blank_blocks(root: &Block) -> (Vec<bool>, Vec<usize>) {
+    /// Step of the walk: a block to enter, or the container at an index to
+    /// leave once its children are checked
+    enum Walk<'b> {
+        Enter(&'b Block),
+        Leave(usize),
+    }
 
-            for (i, line) in content.lines().enumerate() {
-                if i > 0 {
-                    out.push('\n');
+    let mut blank = Vec::new();
+    let mut subtree_ends = Vec::new();
+    let mut stack = vec![Walk::Enter(root)];
+    while let Some(step) = stack.pop() {
+        match step {
+            Walk::Enter(block) => {
+                let index = blank.len();
+                if is_container(block) {
+                    blank.push(true);
+                    subtree_ends.push(index);
+                    stack.push(Walk::Leave(index));
+                    stack.extend(child_blocks(block).rev().map(Walk::Enter));
+                } else {
+                    blank.push(block.is_blank());
+                    subtree_ends.push(index + 1);
                 }
-                out.push('>');
-                if !line.is_empty() {
-                    out.push(' ');
-                    out.push_str(line);
+            }
+            Walk::Leave(index) => {
+                let end = blank.len();
+                subtree_ends[index] = end;
+                let mut child = index + 1;
+                while child < end {
+                    blank[index] &= blank[child];
+                    child = subtree_ends[child];
                 }
             }
-            out.push_str("\n\n");
         }
+    }
+    (blank, subtree_ends)
+}
 
-        Block::List {
-            ordered,
-            start,
-            items,
-        } => serialize_list(*ordered, *start, items, options, depth, out),
+fn is_container(block: &Block) -> bool {
+    matches!(block, Block::Document(_) | Block::BlockQuote(_) | Block::List { .. })
+}
 
-        Block::CodeBlock {
-            language,
-            code,
-            fenced,
-        } => serialize_code_block(language.as_deref(), code, *fenced, options, out),
+/// Blocks directly inside a container block, including list item content
+fn child_blocks(block: &Block) -> impl DoubleEndedIterator<Item = &Block> {
+    let (blocks, items): (&[Block], &[ListItem]) = match block {
+        Block::Document(blocks) | Block::BlockQuote(blocks) => (blocks, &[]),
+        Block::List { items, .. } => (&[], items),
+        _ => (&[], &[]),
+    };
+    blocks.iter().chain(items.iter().flat_map(|item| &item.content))
+}
 
-        Block::ThematicBreak => {
-            out.push_str(&options.hr);
-            out.push_str("\n\n");
-        }
+/// Link reference definitions collected for `LinkStyle::Referenced`
+#[derive(Default)]
+struct References {
+    /// Rendered `[label]: url "title"` lines, in order of first use
+    definitions: Vec<String>,
+    /// Total length of `definitions`
+    bytes: usize,
+    /// Dedup key to the label already assigned to it and its destination
+    labels: HashMap<String, (String, String)>,
+}
 
-        Block::Table { headers, rows } => serialize_table(headers, rows, options, out),
+impl References {
+    /// Register a link target and return the label it is referenced by.
+    ///
+    /// Full references are numbered and deduplicated by destination; collapsed
+    /// and shortcut references use the link text as label. A (case-insensitive)
+    /// label already defined with another destination or title cannot be
+    /// reused, and blank link text is no valid label, so `None` is returned and
+    /// the link has to be inlined.
+    fn label_for(
+        &mut self,
+        style: LinkReferenceStyle,
+        text: &str,
+        url: &str,
+        title: Option<&str>,
+    ) -> Option<String> {
+        let mut destination = String::new();
+        push_link_destination(url, &mut destination);
+        if let Some(t) = title {
+            push_link_title(t, &mut destination);
+        }
 
-        Block::HtmlBlock(html) => {
-            out.push_str(html);
-            out.push_str("\n\n");
+        let key = match style {
+            LinkReferenceStyle::Full => destination.clone(),
+            LinkReferenceStyle::Collapsed | LinkReferenceStyle::Shortcut => {
+                if text.trim().is_empty() {
+                    return None;
+                }
+                text.to_lowercase()
+            }
+        };
+        if let Some((label, defined)) = self.labels.get(&key) {
+            return (*defined == destination).then(|| label.clone());
         }
+
+        let label = match style {
+            LinkReferenceStyle::Full => (self.definitions.len() + 1).to_string(),
+            LinkReferenceStyle::Collapsed | LinkReferenceStyle::Shortcut => text.to_string(),
+        };
+        let definition = format!("[{}]: {}", label, destination);
+        self.bytes += definition.len();
+        self.definitions.push(definition);
+        self.labels.insert(key, (label.clone(), destination));
+        Some(label)
     }
-}
 
-fn serialize_blocks(blocks: &[Block], options: &Options, depth: usize, out: &mut String) {
-    for block in blocks {
-        if !block.is_blank() {
-            serialize_block(block, options, depth, out);
+    /// Length the definitions add when appended to `output`
+    fn appended_len(&self, output: &str) -> usize {
+        match self.definitions.len() {
+            0 => 0,
+            n if output.is_empty() => self.bytes + n - 1,
+            n => self.bytes + n + 1,
         }
     }
 }
 
-fn serialize_heading(level: u8, content: &[Inline], options: &Options, out: &mut String) {
-    let start_len = out.len();
-    serialize_inlines(content, options, out);
+/// Work item of the block serializer
+///
+/// Blocks are serialized from an explicit stack rather than by recursion, so
+/// deeply nested documents cannot overflow the call stack. Containers queue
+/// their content followed by a task that finishes them.
+///
+/// Blocks carry their index in document order (see [`blank_blocks`]).
+enum Task<'b> {
+    Block(&'b Block, usize),
+    /// A list item, its number in an ordered list and the index of its first
+    /// block
+    ListItem(&'b ListItem, Option<u32>, usize),
+    /// A block of a list item and the block following it
+    ItemBlock(&'b Block, usize, Option<&'b Block>),
+    /// Close the innermost block quote or list item
+    EndContainer,
+    EndList,
+}
 
-    if out[start_len..].trim().is_empty() {
-        out.truncate(start_len);
-        return;
+/// Line prefix of an open container block
+enum Prefix {
+    /// `> ` of a block quote
+    Quote,
+    /// Indent of the continuation lines of a list item
+    Indent(usize),
+}
+
+struct Container {
+    prefix: Prefix,
+    /// [`Writer::written`] when the container was opened
+    written: usize,
+}
+
+/// Block output, written line by line below the open container blocks
+///
+/// Every line starts with the prefixes of the containers it is in, so nested
+/// block quotes and list items never rewrite finished output. Containers and
+/// the document drop trailing whitespace when they end, so whitespace is held
+/// back until more content follows.
+struct Writer {
+    out: String,
+    containers: Vec<Container>,
+    /// Containers from this index on have not started the current line
+    fresh: usize,
+    /// List items from this index on are still on their first line, which
+    /// follows the list marker without indent
+    first_line: usize,
+    /// Held back whitespace: container depth, char and whether it is verbatim
+    pending: Vec<(usize, char, bool)>,
+    /// Number of writes with other chars than newlines
+    written: usize,
+    /// Newlines since the last other char, to keep at most one blank line
+    newlines: usize,
+    /// Output size limit of [`serialize_limited`]
+    budget: Option<usize>,
+    /// Output length and number of link reference definitions at the last
+    /// block end within the budget
+    fit: (usize, usize),
+    /// Serialization stopped over the budget
+    exceeded: bool,
+}
+
+impl Writer {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            out: String::with_capacity(capacity),
+            containers: Vec::new(),
+            fresh: 0,
+            first_line: 0,
+            pending: Vec::new(),
+            written: 0,
+            newlines: 0,
+            budget: None,
+            fit: (0, 0),
+            exceeded: false,
+        }
     }
 
-    let text_len = out.len() - start_len;
+    /// Check the output against the budget, recording block ends that fit;
+    /// false once it is exceeded
+    fn within_budget(&mut self, references: &References, block_end: bool) -> bool {
+        let Some(max) = self.budget else {
+            return true;
+        };
+        if self.out.len() + references.appended_len(&self.out) > max {
+            self.exceeded = true;
+            return false;
+        }
+        if block_end {
+            self.fit = (self.out.len(), references.definitions.len());
+        }
+        true
+    }
 
-    match options.heading_style {
-        HeadingStyle::Setext if level <= 2 => {
-            out.push('\n');
-            let underline = if level == 1 { '=' } else { '-' };
-            for _ in 0..text_len {
-                out.push(underline);
+    /// Write output of the innermost container; blank lines in the
+    /// `verbatim` ranges of `text` are kept
+    fn write(&mut self, text: &str, verbatim: &[Range<usize>]) {
+        let depth = self.containers.len();
+        let in_verbatim = |i: usize| depth == 0 && verbatim.iter().any(|r| r.contains(&i));
+        let (body, tail) = text.split_at(text.trim_end().len());
+
+        if !body.is_empty() {
+            self.written += 1;
+            self.flush();
+            let mut offset = 0;
+            for piece in body.split_inclusive('\n') {
+                match piece.strip_suffix('\n') {
+                    Some(line) => {
+                        if !line.is_empty() {
+                            self.push_str(depth, line);
+                        }
+                        self.push_newline(depth, in_verbatim(offset + line.len()));
+                    }
+                    None => self.push_str(depth, piece),
+                }
+                offset += piece.len();
             }
-            out.push_str("\n\n");
         }
-        _ => {
-            // Need to prepend hashes - shift content
-            let text = out[start_len..].to_string();
-            out.truncate(start_len);
-            for _ in 0..level {
-                out.push('#');
+
+        for (i, c) in tail.char_indices() {
+            if c != '\n' {
+                self.written += 1;
             }
-            out.push(' ');
-            out.push_str(&text);
-            out.push_str("\n\n");
+            self.pending.push((depth, c, in_verbatim(body.len() + i)));
         }
     }
-}
 
-fn serialize_list(
-    ordered: bool,
-    start: u32,
-    items: &[ListItem],
-    options: &Options,
-    depth: usize,
-    out: &mut String,
-) {
-    let indent = "    ".repeat(depth);
+    fn open(&mut self, prefix: Prefix) {
+        self.containers.push(Container {
+            prefix,
+            written: self.written,
+        });
+    }
 
-    for (i, item) in items.iter().enumerate() {
-        out.push_str(&indent);
+    /// Close the innermost container, dropping its trailing whitespace
+    /// (block quotes) or newlines (list items)
+    fn close(&mut self) {
+        let depth = self.containers.len();
+        let Some(container) = self.containers.pop() else {
+            return;
+        };
+        let outer = depth - 1;
+        self.fresh = self.fresh.min(outer);
+        self.first_line = self.first_line.min(outer);
+        let held = self.pending.iter().rposition(|&(d, ..)| d < depth).map_or(0, |i| i + 1);
+
+        match container.prefix {
+            Prefix::Quote => {
+                self.pending.truncate(held);
+                self.pending.extend([(outer, '\n', false), (outer, '\n', false)]);
+            }
+            Prefix::Indent(indent) => {
+                while self.pending[held..].last().is_some_and(|p| p.1 == '\n') {
+                    self.pending.pop();
+                }
+                // Whitespace kept inside the item moves out to the enclosing
+                // container, indented like any other line of the item
+                let kept: Vec<char> = self.pending.drain(held..).map(|p| p.1).collect();
+                let mut line_start = self.fresh >= outer;
+                let mut first_line = self.first_line <= outer;
+                for c in kept {
+                    if line_start && !first_line {
+                        self.pending.extend(std::iter::repeat_n((outer, ' ', false), indent));
+                    }
+                    self.pending.push((outer, c, false));
+                    line_start = c == '\n';
+                    first_line &= c != '\n';
+                }
+                if self.written > container.written {
+                    self.pending.push((outer, '\n', false));
+                }
+            }
+        }
+    }
 
-        if ordered {
-            // Write number prefix
-            let num = start + i as u32;
-            out.push_str(&num.to_string());
-            out.push_str(".  ");
-        } else {
-            out.push(options.bullet_list_marker);
-            out.push_str("   ");
+    /// Write the held back whitespace, as more content follows it
+    fn flush(&mut self) {
+        for (depth, c, verbatim) in std::mem::take(&mut self.pending) {
+            if c == '\n' {
+                self.push_newline(depth, verbatim);
+            } else {
+                self.push_str(depth, c.encode_utf8(&mut [0; 4]));
+            }
         }
+    }
 
-        let prefix_len = if ordered {
-            (start + i as u32).to_string().len() + 3
+    fn push_str(&mut self, depth: usize, s: &str) {
+        self.start_line(depth, false);
+        self.newlines = 0;
+        self.out.push_str(s);
+    }
+
+    fn push_newline(&mut self, depth: usize, verbatim: bool) {
+        self.start_line(depth, true);
+        self.fresh = 0;
+        self.first_line = depth;
+        if self.out.is_empty() {
+            return;
+        }
+        if verbatim {
+            self.newlines = 0;
         } else {
-            4
+            self.newlines += 1;
+            if self.newlines > 2 {
+                return;
+            }
+        }
+        self.out.push('\n');
+    }
+
+    /// Write the prefixes of the containers (up to `depth`) that have not
+    /// started the current line yet
+    fn start_line(&mut self, depth: usize, empty: bool) {
+        if self.fresh >= depth {
+            return;
+        }
+        let has_prefix = |i: usize| match self.containers[i].prefix {
+            Prefix::Quote => true,
+            Prefix::Indent(_) => i < self.first_line,
         };
+        // On an empty line the innermost `>` goes without its space
+        let bare = (self.fresh..depth).rev().find(|&i| has_prefix(i)).filter(|_| empty);
+
+        let start = self.out.len();
+        for i in self.fresh..depth {
+            match self.containers[i].prefix {
+                Prefix::Quote if bare == Some(i) => self.out.push('>'),
+                Prefix::Quote => self.out.push_str("> "),
+                Prefix::Indent(indent) if i < self.first_line => {
+                    self.out.extend(std::iter::repeat_n(' ', indent))
+                }
+                Prefix::Indent(_) => {}
+            }
+        }
+        if self.out.len() > start {
+            self.newlines = 0;
+        }
+        self.fresh = depth;
+    }
 
-        serialize_list_item(item, options, depth + 1, prefix_len, &indent, out);
+    fn finish(mut self) -> String {
+        while self.pending.last().is_some_and(|p| p.1 == '\n') {
+            self.pending.pop();
+        }
+        self.flush();
+        self.out
     }
+}
 
-    out.push('\n');
+fn serialize_block(block: &Block, options: &Options, ctx: &mut Context, out: &mut Writer) {
+    (ctx.blank, ctx.subtree_ends) = blank_blocks(block);
+    serialize_tasks(vec![Task::Block(block, 0)], options, ctx, out);
 }
 
-fn serialize_list_item(
-    item: &ListItem,
-    options: &Options,
-    depth: usize,
-    prefix_len: usize,
-    indent: &str,
-    out: &mut String,
+/// Queue blocks starting at index `first` for serialization in order,
+/// skipping blank ones
+fn push_blocks<'b>(blocks: &'b [Block], first: usize, ctx: &Context, stack: &mut Vec<Task<'b>>) {
+    let start = stack.len();
+    let mut index = first;
+    for block in blocks {
+        if !ctx.blank[index] {
+            stack.push(Task::Block(block, index));
+        }
+        index = ctx.subtree_ends[index];
+    }
+    stack[start..].reverse();
+}
+
+/// Queue the blocks of a list item starting at index `first`
+fn push_item_blocks<'b>(
+    content: &'b [Block],
+    first: usize,
+    ctx: &Context,
+    stack: &mut Vec<Task<'b>>,
 ) {
-    let start_len = out.len();
+    let start = stack.len();
+    let mut index = first;
+    for (i, block) in content.iter().enumerate() {
+        stack.push(Task::ItemBlock(block, index, content.get(i + 1)));
+        index = ctx.subtree_ends[index];
+    }
+    stack[start..].reverse();
+}
+
+fn serialize_tasks(mut stack: Vec<Task>, options: &Options, ctx: &mut Context, out: &mut Writer) {
+    // Leaf blocks are serialized on their own, then written below the
+    // open containers
+    let mut buf = String::new();
 
-    for (i, block) in item.content.iter().enumerate() {
-        match block {
-            Block::Paragraph(inlines) => {
-                serialize_inlines(inlines, options, out);
-                if i < item.content.len() - 1 {
-                    out.push_str("\n\n");
+    while let Some(task) = stack.pop() {
+        match task {
+            Task::Block(block, index) => match block {
+                Block::Document(blocks) => push_blocks(blocks, index + 1, ctx, &mut stack),
+
+                Block::Heading { level, content } => {
+                    serialize_heading(*level, content, options, ctx, &mut buf)
                 }
+
+                Block::Paragraph(inlines) => {
+                    serialize_inlines(inlines, options, ctx, &mut buf);
+                    if buf.trim().is_empty() {
+                        buf.clear();
+                    } else {
+                        buf.push_str("\n\n");
+                    }
+                }
+
+                Block::BlockQuote(blocks) => {
+                    out.open(Prefix::Quote);
+                    stack.push(Task::EndContainer);
+                    push_blocks(blocks, index + 1, ctx, &mut stack);
+                }
+
+                Block::List {
+                    ordered,
+                    start,
+                    items,
+                } => {
+                    stack.push(Task::EndList);
+                    let first = stack.len();
+                    let mut index = index + 1;
+                    for (i, item) in items.iter().enumerate() {
+                        let number = ordered.then(|| start + i as u32);
+                        stack.push(Task::ListItem(item, number, index));
+                        for _ in &item.content {
+                            index = ctx.subtree_ends[index];
+                        }
+                    }
+                    stack[first..].reverse();
+                }
+
+                Block::CodeBlock {
+                    language,
+                    code,
+                    fenced,
+                } => {
+                    serialize_code_block(language.as_deref(), code, *fenced, options, ctx, &mut buf)
+                }
+
+                Block::ThematicBreak => {
+                    buf.push_str(&options.hr);
+                    buf.push_str("\n\n");
+                }
+
+                Block::Table {
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                } => {
+                    let caption = caption.as_deref();
+                    if options.caption_position == CaptionPosition::Above {
+                        serialize_caption(caption, options, ctx, &mut buf);
+                    }
+                    serialize_table(headers, rows, alignments, options, ctx, &mut buf);
+                    if options.caption_position == CaptionPosition::Below {
+                        serialize_caption(caption, options, ctx, &mut buf);
+                    }
+                }
+
+                Block::HtmlBlock(html) => {
+                    buf.push_str(html);
+                    ctx.mark_verbatim(0, &buf);
+                    buf.push_str("\n\n");
+                }
+            },
+
+            Task::ListItem(item, _, first) if !item.marker => {
+                out.open(Prefix::Indent(0));
+                stack.push(Task::EndContainer);
+                push_item_blocks(&item.content, first, ctx, &mut stack);
             }
-            Block::List { .. } => {
-                out.push('\n');
-                serialize_block(block, options, depth, out);
-            }
-            _ => {
-                serialize_block(block, options, depth, out);
+
+            Task::ListItem(item, number, first) => {
+                let indent = match number {
+                    Some(num) => {
+                        let num = num.to_string();
+                        out.write(&format!("{}.  ", num), &[]);
+                        num.len() + 3
+                    }
+                    None => {
+                        out.write(&format!("{}   ", options.bullet_list_marker), &[]);
+                        4
+                    }
+                };
+                // Continuation lines, nested lists included, are indented
+                out.open(Prefix::Indent(indent));
+                stack.push(Task::EndContainer);
+
+                if options.task_lists {
+                    match item.checked {
+                        Some(true) => out.write("[x] ", &[]),
+                        Some(false) => out.write("[ ] ", &[]),
+                        None => {}
+                    }
+                }
+
+                push_item_blocks(&item.content, first, ctx, &mut stack);
             }
+
+            Task::ItemBlock(block, index, next) => match block {
+                Block::Paragraph(inlines) => {
+                    serialize_inlines(inlines, options, ctx, &mut buf);
+                    // A nested list directly follows its lead paragraph
+                    match next {
+                        Some(Block::List { .. }) | None => {}
+                        Some(_) => buf.push_str("\n\n"),
+                    }
+                }
+                Block::List { .. } => {
+                    out.write("\n", &[]);
+                    stack.push(Task::Block(block, index));
+                }
+                _ => stack.push(Task::Block(block, index)),
+            },
+
+            Task::EndContainer => out.close(),
+
+            Task::EndList => out.write("\n", &[]),
+        }
+
+        let block_end = !buf.is_empty();
+        if block_end {
+            out.write(&buf, &ctx.verbatim);
+            buf.clear();
+        }
+        ctx.verbatim.clear();
+        if !out.within_budget(&ctx.references, block_end) {
+            break;
         }
     }
+}
 
-    // Indent continuation lines
-    let content = out[start_len..].to_string();
-    out.truncate(start_len);
+fn serialize_heading(
+    level: u8,
+    content: &[Inline],
+    options: &Options,
+    ctx: &mut Context,
+    out: &mut String,
+) {
+    let start_len = out.len();
+    serialize_inlines(content, options, ctx, out);
+
+    if out[start_len..].trim().is_empty() {
+        out.truncate(start_len);
+        return;
+    }
 
-    let continuation_indent: String = std::iter::repeat(' ').take(prefix_len).collect();
+    let text_width = display_width(&out[start_len..]);
 
-    for (i, line) in content.lines().enumerate() {
-        if i > 0 {
-            out.push_str(indent);
-            out.push_str(&continuation_indent);
+    match options.heading_style {
+        HeadingStyle::Setext if level <= 2 => {
+            out.push('\n');
+            let underline = if level == 1 { '=' } else { '-' };
+            for _ in 0..text_width {
+                out.push(underline);
+            }
+            out.push_str("\n\n");
+        }
+        _ => {
+            // Need to prepend hashes - shift content
+            let text = out[start_len..].to_string();
+            out.truncate(start_len);
+            for _ in 0..level {
+                out.push('#');
+            }
+            out.push(' ');
+            out.push_str(&text);
+            out.push_str("\n\n");
         }
-        out.push_str(line);
-        out.push('\n');
     }
 }
 
@@ -205,47 +720,105 @@ fn serialize_code_block(
     code: &str,
     fenced: bool,
     options: &Options,
+    ctx: &mut Context,
     out: &mut String,
 ) {
     let use_fenced = fenced || options.code_block_style == CodeBlockStyle::Fenced;
 
     if use_fenced {
-        out.push_str(&options.fence);
+        let fence = fence_for(code, &options.fence);
+        out.push_str(&fence);
         out.push_str(language.unwrap_or(""));
         out.push('\n');
-        out.push_str(code);
+        let start = out.len();
+        out.push_str(code.strip_suffix('\n').unwrap_or(code));
+        ctx.mark_verbatim(start, out);
         out.push('\n');
-        out.push_str(&options.fence);
+        out.push_str(&fence);
         out.push_str("\n\n");
     } else {
+        let start = out.len();
         for line in code.lines() {
             out.push_str("    ");
             out.push_str(line);
             out.push('\n');
         }
+        ctx.mark_verbatim(start, out);
         out.push('\n');
     }
 }
 
+/// Lengthen the configured fence past any fence-like line in the code
+fn fence_for(code: &str, fence: &str) -> String {
+    let Some(fence_char) = fence.chars().next() else {
+        return fence.to_string();
+    };
+
+    // A line of at least as many fence characters (indented by up to three
+    // spaces) would close the block early
+    let longest = code
+        .lines()
+        .filter_map(|line| {
+            let trimmed = line.trim_start_matches(' ');
+            (line.len() - trimmed.len() <= 3)
+                .then(|| trimmed.chars().take_while(|&c| c == fence_char).count())
+        })
+        .max()
+        .unwrap_or(0);
+
+    let fence_len = fence.chars().count();
+    if longest < fence_len {
+        fence.to_string()
+    } else {
+        fence_char.to_string().repeat(longest + 1)
+    }
+}
+
+/// Length of the longest run of `c` in `s`
+fn longest_run(s: &str, c: char) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    for ch in s.chars() {
+        if ch == c {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
 fn serialize_table(
     headers: &[Vec<Inline>],
     rows: &[Vec<Vec<Inline>>],
+    alignments: &[Alignment],
     options: &Options,
+    ctx: &mut Context,
     out: &mut String,
 ) {
     if headers.is_empty() {
         return;
     }
 
-    // Calculate column widths
-    let col_count = headers.len();
-    let mut widths: Vec<usize> = headers.iter().map(|h| inlines_text_len(h)).collect();
-
-    for row in rows {
+    // Serialize the cells first so the column widths match the output; rows
+    // may have uneven cell counts, so the widest row determines the column
+    // count and short rows are padded
+    let cells: Vec<Vec<String>> = std::iter::once(headers)
+        .chain(rows.iter().map(|r| r.as_slice()))
+        .map(|row| {
+            row.iter()
+                .map(|cell| serialize_table_cell(cell, options, ctx))
+                .collect()
+        })
+        .collect();
+
+    let col_count = cells.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut widths = vec![0; col_count];
+
+    for row in &cells {
         for (i, cell) in row.iter().enumerate() {
-            if i < widths.len() {
-                widths[i] = widths[i].max(inlines_text_len(cell));
-            }
+            widths[i] = widths[i].max(display_width(cell));
         }
     }
 
@@ -255,178 +828,445 @@ fn serialize_table(
     }
 
     // Header row
-    out.push('|');
-    for (i, header) in headers.iter().enumerate() {
-        let start = out.len();
-        out.push(' ');
-        serialize_inlines(header, options, out);
-        let text_len = out.len() - start - 1;
-        let padding = widths.get(i).copied().unwrap_or(3).saturating_sub(text_len);
-        for _ in 0..padding {
-            out.push(' ');
-        }
-        out.push_str(" |");
-    }
-    out.push('\n');
+    serialize_table_row(&cells[0], &widths, out);
 
     // Separator row
     out.push('|');
-    for &width in &widths[..col_count] {
+    for (i, &width) in widths.iter().enumerate() {
+        let (left, right) = match alignments.get(i).copied().unwrap_or_default() {
+            Alignment::None => ("-", "-"),
+            Alignment::Left => (":", "-"),
+            Alignment::Center => (":", ":"),
+            Alignment::Right => ("-", ":"),
+        };
         out.push(' ');
-        for _ in 0..width {
+        out.push_str(left);
+        for _ in 2..width {
             out.push('-');
         }
+        out.push_str(right);
         out.push_str(" |");
     }
     out.push('\n');
 
     // Data rows
-    for row in rows {
-        out.push('|');
-        for (i, cell) in row.iter().enumerate() {
-            let start = out.len();
-            out.push(' ');
-            serialize_inlines(cell, options, out);
-            let text_len = out.len() - start - 1;
-            let width = widths.get(i).copied().unwrap_or(3);
-            let padding = width.saturating_sub(text_len);
-            for _ in 0..padding {
-                out.push(' ');
-            }
-            out.push_str(" |");
-        }
-        out.push('\n');
+    for row in &cells[1..] {
+        serialize_table_row(row, &widths, out);
     }
 
     out.push('\n');
 }
 
-fn serialize_inlines(inlines: &[Inline], options: &Options, out: &mut String) {
-    for inline in inlines {
-        serialize_inline(inline, options, out);
+/// Serialize a table caption as its own paragraph
+fn serialize_caption(
+    caption: Option<&[Inline]>,
+    options: &Options,
+    ctx: &mut Context,
+    out: &mut String,
+) {
+    let Some(caption) = caption else {
+        return;
+    };
+    let start = out.len();
+    if options.caption_style == CaptionStyle::Bold {
+        let bold = vec![Inline::Strong(caption.to_vec())];
+        serialize_inlines(&bold, options, ctx, out);
+        drop_trees(Vec::new(), bold);
+    } else {
+        serialize_inlines(caption, options, ctx, out);
+    }
+    if out[start..].trim().is_empty() {
+        out.truncate(start);
+        return;
     }
-}
 
-fn serialize_inline(inline: &Inline, options: &Options, out: &mut String) {
-    match inline {
-        Inline::Text(text) => out.push_str(text),
+    match options.caption_style {
+        CaptionStyle::Plain | CaptionStyle::Bold => {}
+        CaptionStyle::Pandoc => {
+            let leading = out[start..].len() - out[start..].trim_start().len();
+            out.replace_range(start..start + leading, "Table: ");
+        }
+    }
+    out.push_str("\n\n");
+}
 
-        Inline::Strong(content) => {
-            let start = out.len();
-            serialize_inlines(content, options, out);
-            if out[start..].trim().is_empty() {
-                out.truncate(start);
-            } else {
-                let inner = out[start..].to_string();
-                out.truncate(start);
-                out.push_str(&options.strong_delimiter);
-                out.push_str(&inner);
-                out.push_str(&options.strong_delimiter);
-            }
+fn serialize_table_row(row: &[String], widths: &[usize], out: &mut String) {
+    out.push('|');
+    for (i, &width) in widths.iter().enumerate() {
+        let cell = row.get(i).map(String::as_str).unwrap_or("");
+        out.push(' ');
+        out.push_str(cell);
+        let padding = width.saturating_sub(display_width(cell));
+        for _ in 0..padding {
+            out.push(' ');
         }
+        out.push_str(" |");
+    }
+    out.push('\n');
+}
 
-        Inline::Emphasis(content) => {
-            let start = out.len();
-            serialize_inlines(content, options, out);
-            if out[start..].trim().is_empty() {
-                out.truncate(start);
-            } else {
-                let inner = out[start..].to_string();
-                out.truncate(start);
-                out.push(options.em_delimiter);
-                out.push_str(&inner);
-                out.push(options.em_delimiter);
-            }
+/// Serialize the content of a table cell
+///
+/// Pipes are escaped (also inside code spans, as GFM splits the row first),
+/// line breaks use `options.table_line_break` and any other newline becomes
+/// a space, so the cell stays on its row.
+fn serialize_table_cell(cell: &[Inline], options: &Options, ctx: &mut Context) -> String {
+    let mut content = String::new();
+    ctx.in_table_cell = true;
+    serialize_inlines(cell, options, ctx, &mut content);
+    ctx.in_table_cell = false;
+
+    let mut out = String::with_capacity(content.len());
+    for c in content.chars() {
+        match c {
+            '|' => out.push_str("\\|"),
+            '\n' => out.push(' '),
+            _ => out.push(c),
         }
+    }
+    out
+}
 
-        Inline::Code(code) => {
-            if !code.is_empty() {
-                let backticks = if code.contains('`') { "``" } else { "`" };
-                let space = if code.starts_with('`') || code.ends_with('`') {
-                    " "
-                } else {
-                    ""
+/// Width of text in monospace columns
+///
+/// East Asian wide characters and emoji take two columns; combining marks,
+/// zero-width joiners and other zero-width characters take none.
+pub(crate) fn display_width(s: &str) -> usize {
+    s.width()
+}
+
+/// Serialize inlines on their own, for measuring them
+pub(crate) fn serialize_inlines_standalone(inlines: &[Inline], options: &Options) -> String {
+    let mut out = String::new();
+    serialize_inlines(inlines, options, &mut Context::default(), &mut out);
+    out
+}
+
+/// Serialize inlines
+///
+/// Container inlines are opened on a stack. Their opening delimiters are
+/// written in front of the first non-whitespace output of their content, and
+/// their closing delimiters once the content is written, so that only the
+/// trailing whitespace of the content is ever moved.
+fn serialize_inlines(inlines: &[Inline], options: &Options, ctx: &mut Context, out: &mut String) {
+    let mut stack: Vec<OpenInline> = Vec::new();
+    // The containers in front of this index have written their opening delimiter
+    let mut opened = 0;
+    let mut siblings = inlines.iter();
+
+    loop {
+        match siblings.next() {
+            Some(inline) => match inline_content(inline) {
+                Some(content) => {
+                    stack.push(OpenInline {
+                        inline,
+                        start: out.len(),
+                        content_start: None,
+                        siblings,
+                    });
+                    siblings = content.iter();
+                }
+                None => {
+                    let leading = if opened < stack.len() {
+                        leading_whitespace(inline, options, ctx)
+                    } else {
+                        None
+                    };
+                    let Some(leading) = leading else {
+                        serialize_leaf(inline, 0, options, ctx, out);
+                        continue;
+                    };
+                    // Flanking whitespace stays outside the delimiters
+                    out.push_str(leading);
+                    for open in &mut stack[opened..] {
+                        push_delimiter(open.inline, true, options, out);
+                        open.content_start = Some(out.len());
+                    }
+                    opened = stack.len();
+                    serialize_leaf(inline, leading.len(), options, ctx, out);
+                }
+            },
+            None => {
+                let Some(open) = stack.pop() else {
+                    break;
                 };
-                out.push_str(backticks);
-                out.push_str(space);
-                out.push_str(code);
-                out.push_str(space);
-                out.push_str(backticks);
+                opened = opened.min(stack.len());
+                close_inline(&open, options, ctx, out);
+                siblings = open.siblings;
             }
         }
+    }
+}
 
-        Inline::Link {
-            content,
-            url,
-            title,
-        } => {
-            out.push('[');
-            serialize_inlines(content, options, out);
-            out.push_str("](");
-            out.push_str(url);
-            if let Some(t) = title {
-                out.push_str(" \"");
-                out.push_str(t);
-                out.push('"');
-            }
-            out.push(')');
+/// A container inline whose content is being serialized
+struct OpenInline<'a> {
+    inline: &'a Inline,
+    /// Offset of the content
+    start: usize,
+    /// Offset of the content after the opening delimiter, once written
+    content_start: Option<usize>,
+    /// Next siblings of the container
+    siblings: std::slice::Iter<'a, Inline>,
+}
+
+/// Content of an inline that wraps other inlines
+fn inline_content(inline: &Inline) -> Option<&[Inline]> {
+    match inline {
+        Inline::Strong(content)
+        | Inline::Emphasis(content)
+        | Inline::Strikethrough(content)
+        | Inline::Highlight(content)
+        | Inline::Superscript(content)
+        | Inline::Subscript(content)
+        | Inline::Link { content, .. } => Some(content),
+        _ => None,
+    }
+}
+
+/// Leading whitespace of a leaf inline's output, `None` when the output is
+/// blank
+fn leading_whitespace<'a>(
+    inline: &'a Inline,
+    options: &'a Options,
+    ctx: &Context,
+) -> Option<&'a str> {
+    let output = match inline {
+        // Escaping never adds whitespace
+        Inline::Text(text) => text,
+        Inline::Code(code) if code.is_empty() => return None,
+        Inline::LineBreak if ctx.in_table_cell => &options.table_line_break,
+        Inline::LineBreak => return None,
+        Inline::HtmlInline(html) => html,
+        _ => return Some(""),
+    };
+    let rest = output.trim_start();
+    (!rest.is_empty()).then(|| &output[..output.len() - rest.len()])
+}
+
+/// Serialize a leaf inline, skipping the first `skip` bytes of its leading
+/// whitespace
+fn serialize_leaf(inline: &Inline, skip: usize, options: &Options, ctx: &Context, out: &mut String) {
+    match inline {
+        Inline::Text(text) => push_text(&text[skip..], options, out),
+
+        Inline::Code(code) if !code.is_empty() => {
+            let backticks = "`".repeat(longest_run(code, '`') + 1);
+            // Padding is needed next to a backtick, and when the code is
+            // itself space-padded (CommonMark strips one space per side)
+            let space_padded = code.starts_with(' ')
+                && code.ends_with(' ')
+                && !code.trim_matches(' ').is_empty();
+            let space = if code.starts_with('`') || code.ends_with('`') || space_padded {
+                " "
+            } else {
+                ""
+            };
+            out.push_str(&backticks);
+            out.push_str(space);
+            out.push_str(code);
+            out.push_str(space);
+            out.push_str(&backticks);
         }
 
         Inline::Image { alt, url, title } => {
             out.push_str("![");
-            out.push_str(alt);
+            push_escaped(&clean_attribute(alt), &['[', ']'], out);
             out.push_str("](");
-            out.push_str(url);
+            push_link_destination(url, out);
             if let Some(t) = title {
-                out.push_str(" \"");
-                out.push_str(t);
-                out.push('"');
+                push_link_title(t, out);
             }
             out.push(')');
         }
 
+        Inline::LineBreak if ctx.in_table_cell => out.push_str(&options.table_line_break[skip..]),
         Inline::LineBreak => out.push_str("  \n"),
 
-        Inline::HtmlInline(html) => out.push_str(html),
+        Inline::HtmlInline(html) => out.push_str(&html[skip..]),
+
+        // Empty code, and containers (closed by `close_inline`)
+        _ => {}
     }
 }
 
-/// Collapse multiple consecutive newlines into at most two, in place
-fn collapse_and_trim(s: &mut String) {
-    let bytes = s.as_bytes();
-    let mut result = Vec::with_capacity(bytes.len());
-    let mut newline_count = 0;
-    let mut start = 0;
-    let mut end = bytes.len();
-
-    // Find start (skip leading newlines)
-    while start < bytes.len() && bytes[start] == b'\n' {
-        start += 1;
+/// Close a container inline once its content is written
+///
+/// As in turndown, trailing whitespace of the content is moved after the
+/// closing delimiter (`** a **` would not parse as emphasis). Blank content
+/// keeps its whitespace and gets no delimiters, except for the brackets of
+/// links.
+fn close_inline(open: &OpenInline, options: &Options, ctx: &mut Context, out: &mut String) {
+    let text_start = open.content_start.unwrap_or(open.start);
+    let text_end = text_start + out[text_start..].trim_end().len();
+    let mut close = mem::take(&mut ctx.scratch);
+    close.clear();
+
+    match open.inline {
+        Inline::Link { url, title, .. } => {
+            if open.content_start.is_none() {
+                close.push('[');
+            }
+            let style = options.link_reference_style;
+            let referenced = options.link_style == LinkStyle::Referenced && !ctx.inline_links;
+            let label = (referenced && !url.is_empty())
+                .then(|| {
+                    ctx.references
+                        .label_for(style, &out[text_start..text_end], url, title.as_deref())
+                })
+                .flatten();
+            if let Some(label) = label {
+                close.push(']');
+                match style {
+                    LinkReferenceStyle::Full => {
+                        close.push('[');
+                        close.push_str(&label);
+                        close.push(']');
+                    }
+                    LinkReferenceStyle::Collapsed => close.push_str("[]"),
+                    LinkReferenceStyle::Shortcut => {}
+                }
+            } else {
+                close.push_str("](");
+                push_link_destination(url, &mut close);
+                if let Some(t) = title {
+                    push_link_title(t, &mut close);
+                }
+                close.push(')');
+            }
+        }
+        inline if open.content_start.is_some() => push_delimiter(inline, false, options, &mut close),
+        _ => {}
     }
 
-    // Find end (skip trailing newlines)
-    while end > start && bytes[end - 1] == b'\n' {
-        end -= 1;
+    out.insert_str(text_end, &close);
+    ctx.scratch = close;
+}
+
+/// Write the opening or closing delimiter of a container inline
+fn push_delimiter(inline: &Inline, opening: bool, options: &Options, out: &mut String) {
+    match inline {
+        Inline::Strong(_) => out.push_str(&options.strong_delimiter),
+        Inline::Emphasis(_) => out.push(options.em_delimiter),
+        Inline::Strikethrough(_) => {
+            push_markup(options.strikethrough_style, "~~", "del", opening, out)
+        }
+        Inline::Highlight(_) => push_markup(options.highlight_style, "==", "mark", opening, out),
+        Inline::Superscript(_) => push_markup(options.script_style, "^", "sup", opening, out),
+        Inline::Subscript(_) => push_markup(options.script_style, "~", "sub", opening, out),
+        Inline::Link { .. } if opening => out.push('['),
+        _ => {}
     }
+}
 
-    for &b in &bytes[start..end] {
-        if b == b'\n' {
-            newline_count += 1;
-            if newline_count <= 2 {
-                result.push(b);
+/// Write a link destination
+///
+/// Destinations with spaces or control characters use the `<...>` form (line
+/// endings percent-encoded); others escape parentheses like turndown does.
+fn push_link_destination(url: &str, out: &mut String) {
+    if url.chars().any(|c| c == ' ' || c.is_ascii_control()) {
+        out.push('<');
+        for c in url.chars() {
+            match c {
+                '\n' => out.push_str("%0A"),
+                '\r' => out.push_str("%0D"),
+                '<' | '>' | '\\' => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                _ => out.push(c),
             }
-        } else {
-            newline_count = 0;
-            result.push(b);
         }
+        out.push('>');
+    } else {
+        push_escaped(url, &['(', ')', '\\'], out);
+    }
+}
+
+/// Write a link title as ` "title"`
+fn push_link_title(title: &str, out: &mut String) {
+    out.push_str(" \"");
+    push_escaped(&clean_attribute(title), &['"'], out);
+    out.push('"');
+}
+
+/// Write (already escaped) text, also escaping the delimiters of the inline
+/// syntax enabled in Markdown style
+fn push_text(text: &str, options: &Options, out: &mut String) {
+    let strikethrough = options.strikethrough_style == MarkupStyle::Markdown;
+    let highlight = options.highlight_style == MarkupStyle::Markdown;
+    let script = options.script_style == MarkupStyle::Markdown;
+    let special = |c| match c {
+        '~' => strikethrough || script,
+        '=' => highlight,
+        '^' => script,
+        _ => false,
+    };
+    if !text.contains(special) {
+        out.push_str(text);
+        return;
+    }
+
+    // Characters after an odd run of backslashes are escaped already
+    let mut escaped = false;
+    for c in text.chars() {
+        if !escaped && special(c) {
+            out.push('\\');
+        }
+        escaped = !escaped && c == '\\';
+        out.push(c);
     }
+}
+
+/// Write `s`, backslash-escaping `specials` and any backslash that would
+/// otherwise escape the character after it
+fn push_escaped(s: &str, specials: &[char], out: &mut String) {
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escapes_next = c == '\\' && chars.peek().is_some_and(|n| n.is_ascii_punctuation());
+        if escapes_next || specials.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// Collapse blank lines in an attribute value (turndown's `cleanAttribute`),
+/// which would otherwise end the paragraph
+fn clean_attribute(value: &str) -> Cow<'_, str> {
+    if !value.contains('\n') {
+        return Cow::Borrowed(value);
+    }
+    let mut cleaned = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        cleaned.push(c);
+        if c == '\n' {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        }
+    }
+    Cow::Owned(cleaned)
+}
 
-    *s = String::from_utf8(result).unwrap_or_default();
+/// Write a delimiter of a markup extension in its configured style
+fn push_markup(style: MarkupStyle, delimiter: &str, tag: &str, opening: bool, out: &mut String) {
+    match style {
+        MarkupStyle::Plain => {}
+        MarkupStyle::Markdown => out.push_str(delimiter),
+        MarkupStyle::Html => {
+            out.push_str(if opening { "<" } else { "</" });
+            out.push_str(tag);
+            out.push('>');
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::{
+        expand_table_spans, inlines_display_width, inlines_text_len, SpannedCell, SpannedRow,
+    };
+    use crate::limits::LimitMode;
 
     fn default_options() -> Options {
         Options::default()
@@ -459,6 +1299,16 @@ mod tests {
         assert_eq!(result, "Subtitle\n--------");
     }
 
+    #[test]
+    fn test_heading_setext_display_width() {
+        let block = Block::Heading {
+            level: 1,
+            content: vec![Inline::Text("日本語 Cafe\u{301}".to_string())],
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(result, "日本語 Cafe\u{301}\n===========");
+    }
+
     #[test]
     fn test_heading_atx() {
         let mut options = default_options();
@@ -497,6 +1347,44 @@ mod tests {
         assert_eq!(result, "`code`");
     }
 
+    #[test]
+    fn test_inline_code_delimiters() {
+        let cases = [
+            ("a ` b", "``a ` b``"),
+            ("a `` b ``` c", "````a `` b ``` c````"),
+            ("`tick", "`` `tick ``"),
+            (" padded ", "`  padded  `"),
+            ("   ", "`   `"),
+        ];
+        for (code, expected) in cases {
+            let block = Block::Paragraph(vec![Inline::Code(code.to_string())]);
+            assert_eq!(serialize(&block, &default_options()), expected);
+        }
+    }
+
+    #[test]
+    fn test_code_block_fence_lengthened() {
+        let block = Block::CodeBlock {
+            language: Some("md".to_string()),
+            code: "```js\nx\n  ````\n    `````".to_string(),
+            fenced: true,
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(result, "`````md\n```js\nx\n  ````\n    `````\n`````");
+
+        let mut options = default_options();
+        options.fence = "~~~".to_string();
+        let result = serialize(&block, &options);
+        assert_eq!(result, "~~~md\n```js\nx\n  ````\n    `````\n~~~");
+
+        let block = Block::CodeBlock {
+            language: None,
+            code: "~~~~\ncode".to_string(),
+            fenced: true,
+        };
+        assert_eq!(serialize(&block, &options), "~~~~~\n~~~~\ncode\n~~~~~");
+    }
+
     #[test]
     fn test_link() {
         let block = Block::Paragraph(vec![Inline::Link {
@@ -508,6 +1396,117 @@ mod tests {
         assert_eq!(result, "[Example](https://example.com)");
     }
 
+    fn link(text: &str, url: &str, title: Option<&str>) -> Inline {
+        Inline::Link {
+            content: vec![Inline::Text(text.to_string())],
+            url: url.to_string(),
+            title: title.map(|t| t.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_link_referenced_full() {
+        let mut options = default_options();
+        options.link_style = LinkStyle::Referenced;
+
+        let block = Block::Document(vec![
+            Block::Paragraph(vec![
+                link("One", "https://one.example", None),
+                Inline::Text(" and ".to_string()),
+                link("Two", "https://two.example", Some("Second")),
+            ]),
+            Block::Paragraph(vec![link("Again", "https://one.example", None)]),
+        ]);
+        let result = serialize(&block, &options);
+        assert_eq!(
+            result,
+            "[One][1] and [Two][2]\n\n[Again][1]\n\n\
+             [1]: https://one.example\n\
+             [2]: https://two.example \"Second\""
+        );
+    }
+
+    #[test]
+    fn test_link_referenced_collapsed() {
+        let mut options = default_options();
+        options.link_style = LinkStyle::Referenced;
+        options.link_reference_style = LinkReferenceStyle::Collapsed;
+
+        let block = Block::Paragraph(vec![
+            link("Example", "https://example.com", None),
+            Inline::Text(" ".to_string()),
+            link("example", "https://example.com", None),
+        ]);
+        let result = serialize(&block, &options);
+        assert_eq!(
+            result,
+            "[Example][] [example][]\n\n[Example]: https://example.com"
+        );
+    }
+
+    #[test]
+    fn test_link_referenced_shortcut() {
+        let mut options = default_options();
+        options.link_style = LinkStyle::Referenced;
+        options.link_reference_style = LinkReferenceStyle::Shortcut;
+
+        let block = Block::Paragraph(vec![link("Example", "https://example.com", Some("Title"))]);
+        let result = serialize(&block, &options);
+        assert_eq!(
+            result,
+            "[Example]\n\n[Example]: https://example.com \"Title\""
+        );
+    }
+
+    #[test]
+    fn test_link_referenced_label_collision() {
+        let mut options = default_options();
+        options.link_style = LinkStyle::Referenced;
+        options.link_reference_style = LinkReferenceStyle::Collapsed;
+
+        let block = Block::Paragraph(vec![
+            link("Docs", "https://a.example", None),
+            Inline::Text(" ".to_string()),
+            link("docs", "https://b.example", None),
+            Inline::Text(" ".to_string()),
+            link("docs", "https://a.example", Some("Title")),
+            Inline::Text(" ".to_string()),
+            link("DOCS", "https://a.example", None),
+        ]);
+        let result = serialize(&block, &options);
+        assert_eq!(
+            result,
+            "[Docs][] [docs](https://b.example) [docs](https://a.example \"Title\") [DOCS][]\n\n\
+             [Docs]: https://a.example"
+        );
+    }
+
+    #[test]
+    fn test_link_referenced_blank_text() {
+        let mut options = default_options();
+        options.link_style = LinkStyle::Referenced;
+
+        let block = Block::Paragraph(vec![
+            link("", "https://a.example", None),
+            Inline::Text(" and ".to_string()),
+            link(" ", "https://b.example", None),
+            Inline::Text(".".to_string()),
+        ]);
+        for style in [LinkReferenceStyle::Collapsed, LinkReferenceStyle::Shortcut] {
+            options.link_reference_style = style;
+            assert_eq!(
+                serialize(&block, &options),
+                "[](https://a.example) and [](https://b.example) ."
+            );
+        }
+
+        options.link_reference_style = LinkReferenceStyle::Full;
+        assert_eq!(
+            serialize(&block, &options),
+            "[][1] and [][2] .\n\n[1]: https://a.example\n[2]: https://b.example"
+        );
+    }
+
     #[test]
     fn test_image() {
         let block = Block::Paragraph(vec![Inline::Image {
@@ -519,6 +1518,45 @@ mod tests {
         assert_eq!(result, "![Alt text](image.png)");
     }
 
+    #[test]
+    fn test_link_destination_and_title_escaping() {
+        let cases = [
+            ("a(b)c", None, "[x](a\\(b\\)c)"),
+            ("my file.html", None, "[x](<my file.html>)"),
+            ("a b<c>", None, "[x](<a b\\<c\\>>)"),
+            ("a\nb", None, "[x](<a%0Ab>)"),
+            ("a\\b\\(", None, "[x](a\\\\b\\\\\\()"),
+            ("a b\\c", None, "[x](<a b\\\\c>)"),
+            ("u", Some("say \"hi\""), "[x](u \"say \\\"hi\\\"\")"),
+            ("u", Some("a\\*b"), "[x](u \"a\\\\*b\")"),
+            ("u", Some("one\n\n  two"), "[x](u \"one\ntwo\")"),
+        ];
+        for (url, title, expected) in cases {
+            let block = Block::Paragraph(vec![link("x", url, title)]);
+            assert_eq!(serialize(&block, &default_options()), expected);
+        }
+    }
+
+    #[test]
+    fn test_image_alt_escaping() {
+        let block = Block::Paragraph(vec![Inline::Image {
+            alt: "a [b] c".to_string(),
+            url: "x y.png".to_string(),
+            title: Some("\"t\"".to_string()),
+        }]);
+        let result = serialize(&block, &default_options());
+        assert_eq!(result, "![a \\[b\\] c](<x y.png> \"\\\"t\\\"\")");
+    }
+
+    #[test]
+    fn test_link_referenced_escaping() {
+        let mut options = default_options();
+        options.link_style = LinkStyle::Referenced;
+        let block = Block::Paragraph(vec![link("x", "a b", Some("\"q\""))]);
+        let result = serialize(&block, &options);
+        assert_eq!(result, "[x][1]\n\n[1]: <a b> \"\\\"q\\\"\"");
+    }
+
     #[test]
     fn test_code_block_indented() {
         let block = Block::CodeBlock {
@@ -544,6 +1582,45 @@ mod tests {
         assert_eq!(result, "```rust\nlet x = 1;\n```");
     }
 
+    #[test]
+    fn test_code_block_blank_lines_preserved() {
+        let code = "fn a() {}\n\n\n\nfn b() {}\n";
+        let doc = Block::Document(vec![
+            Block::Paragraph(vec![Inline::Text("Before".to_string())]),
+            Block::CodeBlock {
+                language: None,
+                code: code.to_string(),
+                fenced: true,
+            },
+            Block::HtmlBlock("<div>\n\n\n</div>".to_string()),
+            Block::Paragraph(vec![Inline::Text("After".to_string())]),
+        ]);
+        let result = serialize(&doc, &default_options());
+        assert_eq!(
+            result,
+            "Before\n\n```\nfn a() {}\n\n\n\nfn b() {}\n```\n\n<div>\n\n\n</div>\n\nAfter"
+        );
+
+        let indented = Block::CodeBlock {
+            language: None,
+            code: code.to_string(),
+            fenced: false,
+        };
+        let result = serialize(&indented, &default_options());
+        assert_eq!(result, "    fn a() {}\n    \n    \n    \n    fn b() {}");
+    }
+
+    #[test]
+    fn test_code_block_in_blockquote() {
+        let block = Block::BlockQuote(vec![Block::CodeBlock {
+            language: None,
+            code: "a\n\n\nb".to_string(),
+            fenced: true,
+        }]);
+        let result = serialize(&block, &default_options());
+        assert_eq!(result, "> ```\n> a\n>\n>\n> b\n> ```");
+    }
+
     #[test]
     fn test_blockquote() {
         let block = Block::BlockQuote(vec![Block::Paragraph(vec![Inline::Text(
@@ -581,6 +1658,28 @@ mod tests {
         assert_eq!(result, "1.  First\n2.  Second");
     }
 
+    #[test]
+    fn test_nested_list() {
+        let nested = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![ListItem::from_inlines(vec![Inline::Text("b".to_string())])],
+        };
+        let block = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![
+                ListItem::new(vec![
+                    Block::Paragraph(vec![Inline::Text("a".to_string())]),
+                    nested,
+                ]),
+                ListItem::from_inlines(vec![Inline::Text("c".to_string())]),
+            ],
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(result, "*   a\n    *   b\n*   c");
+    }
+
     #[test]
     fn test_thematic_break() {
         let block = Block::ThematicBreak;
@@ -599,10 +1698,431 @@ mod tests {
                 vec![Inline::Text("1".to_string())],
                 vec![Inline::Text("2".to_string())],
             ]],
+            alignments: vec![],
+            caption: None,
         };
         let result = serialize(&block, &default_options());
         assert!(result.contains("| A"));
         assert!(result.contains("| B"));
         assert!(result.contains("---"));
     }
+
+    #[test]
+    fn test_table_uneven_rows() {
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let block = Block::Table {
+            headers: vec![text("A")],
+            rows: vec![vec![text("1"), text("2")], vec![]],
+            alignments: vec![],
+            caption: None,
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(
+            result,
+            "| A   |     |\n| --- | --- |\n| 1   | 2   |\n|     |     |"
+        );
+    }
+
+    #[test]
+    fn test_table_display_width() {
+        let text = |s: &str| vec![Inline::Text(s.to_string())];
+        let block = Block::Table {
+            headers: vec![text("名前"), text("x")],
+            rows: vec![
+                vec![text("e\u{301}te\u{301}"), text("👩\u{200d}💻")],
+                vec![vec![Inline::Strong(text("粗"))], text("a")],
+            ],
+            alignments: vec![],
+            caption: None,
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(
+            result,
+            "| 名前   | x   |\n| ------ | --- |\n| e\u{301}te\u{301}    | 👩\u{200d}💻  |\n| **粗** | a   |"
+        );
+    }
+
+    #[test]
+    fn test_display_width_measures_output() {
+        let options = default_options();
+        let code = Inline::Code("a ` b".to_string());
+        assert_eq!(code.display_width(&options), "``a ` b``".len());
+        assert_eq!(code.text_len(), "``a ` b``".len());
+        let strong = Inline::Strong(vec![Inline::Text("漢字".to_string())]);
+        assert_eq!(strong.display_width(&options), 8);
+        let link = Inline::Link {
+            content: vec![Inline::Text("x".to_string())],
+            url: "u".to_string(),
+            title: None,
+        };
+        let inlines = [link, Inline::Text("é".to_string())];
+        assert_eq!(inlines_display_width(&inlines, &options), 7);
+        assert_eq!(inlines_text_len(&inlines), "[x](u)é".len());
+    }
+
+    #[test]
+    fn test_table_cell_escaping() {
+        let block = Block::Table {
+            headers: vec![vec![Inline::Text("a|b".to_string())]],
+            rows: vec![vec![vec![
+                Inline::Text("one".to_string()),
+                Inline::LineBreak,
+                Inline::Code("x || y".to_string()),
+            ]]],
+            alignments: vec![],
+            caption: None,
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(
+            result,
+            "| a\\|b              |\n| ----------------- |\n| one<br>`x \\|\\| y` |"
+        );
+
+        let mut options = default_options();
+        options.table_line_break = " ".to_string();
+        let result = serialize(&block, &options);
+        assert!(result.ends_with("| one `x \\|\\| y` |"));
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let block = Block::Table {
+            headers: vec![text("Left"), text("Center"), text("Right"), text("None")],
+            rows: vec![],
+            alignments: vec![Alignment::Left, Alignment::Center, Alignment::Right],
+            caption: None,
+        };
+        let result = serialize(&block, &default_options());
+        assert_eq!(
+            result,
+            "| Left | Center | Right | None |\n| :--- | :----: | ----: | ---- |"
+        );
+    }
+
+    #[test]
+    fn test_alignment_from_html() {
+        assert_eq!(Alignment::from_html(Some("CENTER"), None), Alignment::Center);
+        assert_eq!(
+            Alignment::from_html(Some("left"), Some("color: red; text-align: right")),
+            Alignment::Right
+        );
+        assert_eq!(Alignment::from_html(Some("right"), Some("text-align: inherit")), Alignment::Right);
+        assert_eq!(Alignment::from_html(None, Some("text-align:center !important")), Alignment::Center);
+        assert_eq!(Alignment::from_html(Some("justify"), None), Alignment::None);
+    }
+
+    #[test]
+    fn test_expand_table_spans() {
+        let cell = |value: &'static str, colspan, rowspan| SpannedCell {
+            value,
+            colspan,
+            rowspan,
+        };
+        let row = |group, cells| SpannedRow { cells, group };
+        // | a (2 cols)  | b (2 rows) |
+        // | c | d (0 = to end) |     |
+        // | e |
+        let rows = vec![
+            row(0, vec![cell("a", 2, 1), cell("b", 1, 2)]),
+            row(0, vec![cell("c", 1, 1), cell("d", 0, 0)]),
+            row(0, vec![cell("e", 1, 1)]),
+        ];
+        assert_eq!(
+            expand_table_spans(rows.clone(), false),
+            [vec!["a", "", "b"], vec!["c", "d", ""], vec!["e", ""]]
+        );
+        assert_eq!(
+            expand_table_spans(rows, true),
+            [vec!["a", "a", "b"], vec!["c", "d", "b"], vec!["e", "d"]]
+        );
+
+        // Rowspans end with their row group
+        let rows = vec![
+            row(0, vec![cell("a", 1, 0), cell("b", 1, 3)]),
+            row(0, vec![cell("c", 1, 1)]),
+            row(1, vec![cell("d", 1, 1)]),
+        ];
+        assert_eq!(
+            expand_table_spans(rows, true),
+            [vec!["a", "b"], vec!["a", "b", "c"], vec!["d"]]
+        );
+
+        // Spans covering too much of the grid are ignored
+        let rows = vec![row(0, vec![cell("a", 1000, 1), cell("b", 1, 1)]); 100];
+        let grid = expand_table_spans(rows, true);
+        assert_eq!(grid.len(), 100);
+        assert!(grid.iter().all(|row| row == &["a", "b"]));
+    }
+
+    #[test]
+    fn test_markup_styles() {
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let block = Block::Paragraph(vec![
+            Inline::Strikethrough(text("old")),
+            Inline::Text(" ".to_string()),
+            Inline::Highlight(text("key")),
+            Inline::Text(" x".to_string()),
+            Inline::Superscript(text("2")),
+            Inline::Text(" H".to_string()),
+            Inline::Subscript(text("2")),
+            Inline::Text("O".to_string()),
+        ]);
+
+        assert_eq!(serialize(&block, &default_options()), "old key x2 H2O");
+
+        let markdown = Options {
+            strikethrough_style: MarkupStyle::Markdown,
+            highlight_style: MarkupStyle::Markdown,
+            script_style: MarkupStyle::Markdown,
+            ..default_options()
+        };
+        assert_eq!(serialize(&block, &markdown), "~~old~~ ==key== x^2^ H~2~O");
+
+        let literal = Block::Paragraph(text("a~b == c^2 \\~~ \\\\~"));
+        assert_eq!(serialize(&literal, &default_options()), "a~b == c^2 \\~~ \\\\~");
+        assert_eq!(serialize(&literal, &markdown), r"a\~b \=\= c\^2 \~\~ \\\~");
+        let strikethrough = Options {
+            strikethrough_style: MarkupStyle::Markdown,
+            ..default_options()
+        };
+        assert_eq!(serialize(&literal, &strikethrough), r"a\~b == c^2 \~\~ \\\~");
+
+        let html = Options {
+            strikethrough_style: MarkupStyle::Html,
+            highlight_style: MarkupStyle::Html,
+            script_style: MarkupStyle::Html,
+            ..default_options()
+        };
+        assert_eq!(
+            serialize(&block, &html),
+            "<del>old</del> <mark>key</mark> x<sup>2</sup> H<sub>2</sub>O"
+        );
+    }
+
+    #[test]
+    fn test_task_list() {
+        let item = |text: &str, checked| {
+            ListItem::from_inlines(vec![Inline::Text(text.to_string())]).with_checked(checked)
+        };
+        let block = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![item("done", Some(true)), item("todo", Some(false)), item("note", None)],
+        };
+
+        assert_eq!(serialize(&block, &default_options()), "*   done\n*   todo\n*   note");
+
+        let options = Options {
+            task_lists: true,
+            bullet_list_marker: '-',
+            ..default_options()
+        };
+        assert_eq!(serialize(&block, &options), "-   [x] done\n-   [ ] todo\n-   note");
+    }
+
+    #[test]
+    fn test_verbatim_list_items() {
+        let nested = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![ListItem::verbatim("+ b\n  more".to_string())],
+        };
+        let block = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![
+                ListItem::verbatim("- a".to_string()),
+                ListItem::new(vec![Block::Paragraph(vec![Inline::Text("c".to_string())]), nested]),
+                ListItem::verbatim(String::new()),
+            ],
+        };
+        assert_eq!(serialize(&block, &default_options()), "- a\n*   c\n    + b\n      more");
+    }
+
+    #[test]
+    fn test_flanking_whitespace() {
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let block = Block::Paragraph(vec![
+            Inline::Text("a".to_string()),
+            Inline::Strong(text(" bold ")),
+            Inline::Text("b".to_string()),
+            Inline::Emphasis(vec![Inline::Text("it".to_string()), Inline::LineBreak]),
+            Inline::Text("c".to_string()),
+            Inline::Link {
+                content: text(" link "),
+                url: "/x".to_string(),
+                title: None,
+            },
+            Inline::Strong(text(" ")),
+            Inline::Text("d".to_string()),
+        ]);
+        assert_eq!(
+            serialize(&block, &default_options()),
+            "a **bold** b_it_  \nc [link](/x)  d"
+        );
+
+        let options = Options {
+            strikethrough_style: MarkupStyle::Markdown,
+            ..default_options()
+        };
+        let block = Block::Paragraph(vec![
+            Inline::Text("x".to_string()),
+            Inline::Strikethrough(text(" old ")),
+            Inline::Text("y".to_string()),
+        ]);
+        assert_eq!(serialize(&block, &options), "x ~~old~~ y");
+
+        // Nested containers open at the first non-whitespace output
+        let block = Block::Paragraph(vec![
+            Inline::Text("x".to_string()),
+            Inline::Strong(vec![
+                Inline::Emphasis(text(" ")),
+                Inline::Link {
+                    content: vec![Inline::Emphasis(text(" a ")), Inline::Text(" ".to_string())],
+                    url: "/a".to_string(),
+                    title: None,
+                },
+            ]),
+            Inline::Text("y".to_string()),
+        ]);
+        assert_eq!(serialize(&block, &options), "x  **[_a_](/a)**  y");
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let block = Block::Document(vec![
+            Block::Paragraph(text("first")),
+            Block::Paragraph(text("second")),
+            Block::Paragraph(text("third")),
+        ]);
+        let mut options = default_options();
+        options.limits.max_output_bytes = Some(16);
+        assert_eq!(
+            serialize_limited(&block, &options),
+            Err(TurndownError::LimitExceeded {
+                limit: "output size",
+                max: 16
+            })
+        );
+
+        options.limits.mode = LimitMode::Truncate;
+        assert_eq!(serialize_limited(&block, &options).unwrap(), "first\n\nsecond");
+
+        // Blocks are never cut
+        options.limits.max_output_bytes = Some(3);
+        assert_eq!(serialize_limited(&block, &options).unwrap(), "");
+
+        options.limits.max_output_bytes = Some(100);
+        assert_eq!(
+            serialize_limited(&block, &options).unwrap(),
+            "first\n\nsecond\n\nthird"
+        );
+
+        // Nested blocks end at block boundaries too, keeping the link
+        // reference definitions of the blocks kept
+        let link = |t: &str| Inline::Link {
+            content: text(t),
+            url: format!("/{}", t),
+            title: None,
+        };
+        let item = |t: &str| ListItem::new(vec![Block::Paragraph(vec![link(t)])]);
+        let block = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![item("a"), item("b"), item("c")],
+        };
+        options.link_style = LinkStyle::Referenced;
+        options.limits.max_output_bytes = Some(40);
+        assert_eq!(
+            serialize_limited(&block, &options).unwrap(),
+            "*   [a][1]\n*   [b][2]\n\n[1]: /a\n[2]: /b"
+        );
+    }
+
+    #[test]
+    fn test_blank_blocks_skipped() {
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let list = Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![
+                ListItem::new(vec![
+                    Block::Paragraph(text("a")),
+                    Block::BlockQuote(vec![Block::Paragraph(text(" "))]),
+                ]),
+                ListItem::new(vec![
+                    Block::Paragraph(text("b")),
+                    Block::BlockQuote(vec![Block::Document(vec![])]),
+                ]),
+            ],
+        };
+        let block = Block::Document(vec![
+            list,
+            Block::BlockQuote(vec![Block::Document(vec![Block::Paragraph(text(""))])]),
+            Block::BlockQuote(vec![Block::Paragraph(text("")), Block::Paragraph(text("c"))]),
+            Block::Document(vec![]),
+            Block::Paragraph(text("d")),
+        ]);
+        assert_eq!(serialize(&block, &default_options()), "*   a\n*   b\n\n> c\n\nd");
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // Deeper than recursive serialization, cloning, comparing or dropping
+        // gets on a test thread's stack; trees are dropped iteratively
+        let depth = 50_000;
+        let text = |t: &str| vec![Inline::Text(t.to_string())];
+        let mut inline = Inline::Text("deep".to_string());
+        for _ in 0..depth {
+            inline = Inline::Emphasis(vec![inline]);
+        }
+        let item = ListItem::new(vec![Block::Paragraph(vec![inline])]);
+        let mut block = Block::BlockQuote(vec![Block::List {
+            ordered: false,
+            start: 1,
+            items: vec![item],
+        }]);
+        for _ in 0..depth {
+            block = Block::Document(vec![block]);
+        }
+
+        let mut options = default_options();
+        let delimiters = "_".repeat(depth);
+        assert_eq!(
+            serialize(&block, &options),
+            format!("> *   {}deep{}", delimiters, delimiters)
+        );
+        assert!(!block.is_blank());
+        let copy = block.clone();
+        assert_eq!(copy, block);
+        copy.drop_iteratively();
+        block.drop_iteratively();
+
+        let mut quote = Block::Paragraph(text("deep"));
+        for _ in 0..depth {
+            quote = Block::BlockQuote(vec![quote]);
+        }
+        assert_eq!(serialize(&quote, &options), format!("{}deep", "> ".repeat(depth)));
+        quote.drop_iteratively();
+
+        // Every list level is indented further, so the output grows
+        // quadratically and is cut by the output limit
+        let mut list = Block::Paragraph(text("deep"));
+        for _ in 0..depth {
+            let item = ListItem::new(vec![Block::Paragraph(text("a")), list]);
+            list = Block::List {
+                ordered: false,
+                start: 1,
+                items: vec![item],
+            };
+        }
+        options.limits.max_output_bytes = Some(1 << 16);
+        options.limits.mode = LimitMode::Truncate;
+        let result = serialize_limited(&list, &options).unwrap();
+        list.drop_iteratively();
+        assert!(result.starts_with("*   a\n    *   a\n        *   a\n"));
+        assert!(result.len() <= 1 << 16 && result.ends_with('a'));
+    }
 }
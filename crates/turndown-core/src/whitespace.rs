@@ -0,0 +1,239 @@
+//! Whitespace collapsing across text nodes
+//!
+//! A port of turndown's `collapseWhitespace`: before conversion, the DOM is
+//! walked in document order and runs of ASCII whitespace are collapsed across
+//! text node boundaries, trimmed at block boundaries and kept next to inline
+//! void elements. `pre` contents are left alone.
+//!
+//! The collapser is DOM-agnostic. A converter walks its own tree, reports
+//! every text node and every element (on entry and on exit), and afterwards
+//! looks up the collapsed text by node index.
+
+use std::ops::Range;
+
+/// Block-level elements (turndown's `blockElements`)
+pub const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "audio", "blockquote", "body", "canvas", "center", "dd",
+    "dir", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "frameset",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr", "html", "isindex", "li",
+    "main", "menu", "nav", "noframes", "noscript", "ol", "output", "p", "pre", "section",
+    "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+/// Void elements (turndown's `voidElements`)
+pub const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "command", "embed", "hr", "img", "input", "keygen", "link",
+    "meta", "param", "source", "track", "wbr",
+];
+
+/// How an element affects the whitespace around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhitespaceKind {
+    /// Block elements and `<br>`: whitespace is trimmed on both sides
+    Block,
+    /// Inline void elements: adjacent whitespace is kept
+    Preserve,
+    /// Other inline elements: whitespace collapses across them
+    Inline,
+}
+
+impl WhitespaceKind {
+    /// Classify an element by its lowercase tag name
+    pub fn of(tag: &str) -> Self {
+        if tag == "br" || BLOCK_ELEMENTS.contains(&tag) {
+            WhitespaceKind::Block
+        } else if VOID_ELEMENTS.contains(&tag) {
+            WhitespaceKind::Preserve
+        } else {
+            WhitespaceKind::Inline
+        }
+    }
+}
+
+/// Collapsed text of the visited text nodes
+///
+/// Keys are node indexes chosen by the converter (like the parser's node
+/// index or a pre-order counter) and index a vector, so they should be dense.
+/// Text nodes that collapsed away map to an empty string; nodes that were
+/// never visited (like `pre` contents) have no entry.
+#[derive(Debug, Default)]
+pub struct CollapsedText {
+    /// Collapsed text of all visited nodes, in document order
+    text: String,
+    /// Range of each node's text in `text`, by key
+    ranges: Vec<Option<Range<usize>>>,
+}
+
+impl CollapsedText {
+    /// Collapsed text of a node, if it was visited
+    pub fn get(&self, key: usize) -> Option<&str> {
+        let range = self.ranges.get(key)?.clone()?;
+        Some(&self.text[range])
+    }
+}
+
+/// State machine of the whitespace collapsing walk
+#[derive(Debug, Default)]
+pub struct WhitespaceCollapser {
+    collapsed: CollapsedText,
+    /// Last text node that may still lose its trailing space; it always ends
+    /// the collapsed text, as only empty nodes are visited after it
+    prev_text: Option<usize>,
+    keep_leading_ws: bool,
+}
+
+impl WhitespaceCollapser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A collapser for keys below `nodes`
+    pub fn with_capacity(nodes: usize) -> Self {
+        let mut collapser = Self::new();
+        collapser.collapsed.ranges.reserve(nodes);
+        collapser
+    }
+
+    /// Visit a text node (already entity-decoded)
+    pub fn text(&mut self, key: usize, data: &str) {
+        let text = &mut self.collapsed.text;
+        let start = text.len();
+        // A leading space is dropped after a space or at a block boundary
+        let mut prev_ws = match self.prev_text {
+            Some(_) => text.ends_with(' '),
+            None => true,
+        } && !self.keep_leading_ws;
+        for c in data.chars() {
+            if matches!(c, ' ' | '\r' | '\n' | '\t') {
+                if !prev_ws {
+                    text.push(' ');
+                    prev_ws = true;
+                }
+            } else {
+                text.push(c);
+                prev_ws = false;
+            }
+        }
+
+        let end = text.len();
+        let ranges = &mut self.collapsed.ranges;
+        if ranges.len() <= key {
+            ranges.resize(key + 1, None);
+        }
+        if end > start {
+            ranges[key] = Some(start..end);
+            self.prev_text = Some(key);
+        } else {
+            // Not past the end once the previous node loses its trailing space
+            ranges[key] = Some(0..0);
+        }
+    }
+    /// Visit an element, once when entering and once when leaving it
+    ///
+    /// The walk must not descend into `pre` elements.
+    pub fn element(&mut self, kind: WhitespaceKind) {
+        match kind {
+            WhitespaceKind::Block => {
+                self.trim_prev_text();
+                self.prev_text = None;
+                self.keep_leading_ws = false;
+            }
+            WhitespaceKind::Preserve => {
+                self.prev_text = None;
+                self.keep_leading_ws = true;
+            }
+            WhitespaceKind::Inline => {
+                if self.prev_text.is_some() {
+                    self.keep_leading_ws = false;
+                }
+            }
+        }
+    }
+
+    /// Finish the walk, trimming the last text node
+    pub fn finish(mut self) -> CollapsedText {
+        self.trim_prev_text();
+        self.collapsed
+    }
+
+    fn trim_prev_text(&mut self) {
+        let collapsed = &mut self.collapsed;
+        if let Some(key) = self.prev_text {
+            if collapsed.text.ends_with(' ') {
+                collapsed.text.pop();
+                if let Some(range) = &mut collapsed.ranges[key] {
+                    range.end -= 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walk a flat event list: Ok(text) for text nodes, Err(tag) for elements
+    fn collapse(events: &[Result<&str, &str>]) -> Vec<String> {
+        let mut collapser = WhitespaceCollapser::new();
+        for (key, event) in events.iter().enumerate() {
+            match event {
+                Ok(text) => collapser.text(key, text),
+                Err(tag) => collapser.element(WhitespaceKind::of(tag)),
+            }
+        }
+        let collapsed = collapser.finish();
+        events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.is_ok())
+            .map(|(key, _)| collapsed.get(key).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_collapse_across_inline_elements() {
+        // <p> <b>a </b> <i> b</i> </p>
+        let texts = collapse(&[
+            Err("p"),
+            Ok(" "),
+            Err("b"),
+            Ok("a "),
+            Err("b"),
+            Ok(" "),
+            Err("i"),
+            Ok(" b"),
+            Err("i"),
+            Ok(" "),
+            Err("p"),
+        ]);
+        assert_eq!(texts, ["", "a ", "", "b", ""]);
+    }
+
+    #[test]
+    fn test_block_and_br_boundaries() {
+        // a <br> b\n\t c <div> d </div>
+        let texts = collapse(&[Ok("a "), Err("br"), Ok(" b\n\t c "), Err("div"), Ok(" d "), Err("div")]);
+        assert_eq!(texts, ["a", "b c", "d"]);
+    }
+
+    #[test]
+    fn test_empty_text_after_trimmed_space() {
+        // <p>a <b> </b></p>
+        let texts = collapse(&[Err("p"), Ok("a "), Err("b"), Ok(" "), Err("b"), Err("p")]);
+        assert_eq!(texts, ["a", ""]);
+    }
+
+    #[test]
+    fn test_whitespace_kept_around_inline_void() {
+        // a <img> b
+        let texts = collapse(&[Ok("a "), Err("img"), Ok(" b")]);
+        assert_eq!(texts, ["a ", " b"]);
+    }
+
+    #[test]
+    fn test_nbsp_is_not_collapsed() {
+        let texts = collapse(&[Ok("\u{a0} a \u{a0}")]);
+        assert_eq!(texts, ["\u{a0} a \u{a0}"]);
+    }
+}
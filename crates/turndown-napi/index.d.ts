@@ -4,26 +4,75 @@
 /* auto-generated by NAPI-RS */
 
 export interface Options {
-  headingStyle?: string;
+  headingStyle?: "setext" | "atx";
   hr?: string;
-  bulletListMarker?: string;
-  codeBlockStyle?: string;
+  bulletListMarker?: "*" | "-" | "+";
+  codeBlockStyle?: "indented" | "fenced";
   fence?: string;
-  emDelimiter?: string;
-  strongDelimiter?: string;
-  linkStyle?: string;
-  linkReferenceStyle?: string;
+  emDelimiter?: "_" | "*";
+  strongDelimiter?: "**" | "__";
+  linkStyle?: "inlined" | "referenced";
+  linkReferenceStyle?: "full" | "collapsed" | "shortcut";
+  tables?: boolean;
+  taskLists?: boolean;
+  strikethroughStyle?: "plain" | "markdown" | "html";
+  highlightStyle?: "plain" | "markdown" | "html";
+  scriptStyle?: "plain" | "markdown" | "html";
+  tableLineBreak?: string;
+  tableFallback?: "html" | "list";
+  tableSpans?: "fallback" | "blank" | "duplicate";
+  captionPosition?: "above" | "below";
+  captionStyle?: "plain" | "bold" | "pandoc";
+  maxDepth?: number;
+  maxInputBytes?: number;
+  maxOutputBytes?: number;
+  maxNodes?: number;
+  limitMode?: "error" | "truncate";
+}
+/**
+ * Element description passed to rule filters and replacements
+ *
+ * Properties are read from the converter when accessed, so rules that only
+ * look at the tag name never copy the element's text or markup.
+ */
+export declare class NodeDescription {
+  /** Uppercase tag name, like `Node.nodeName` */
+  get nodeName(): string;
+  /** Attributes by name */
+  get attributes(): Record<string, string>;
+  get className(): string;
+  get id(): string;
+  get textContent(): string;
+  /** Original source markup of the element */
+  get outerHTML(): string;
+  /** Whether the element is block-level in the turndown.js sense */
+  get isBlock(): boolean;
+  /** Uppercase tag names of the enclosing elements, outermost first */
+  get ancestors(): Array<string>;
 }
 export declare class TurndownService {
   constructor(options?: Options | undefined | null);
+  /** The effective options, with defaults filled in */
+  get options(): Options;
   /** Convert HTML to Markdown using tl parser */
   turndown(html: string): string;
-  /** Add a custom rule (currently no-op) */
-  addRule(key: string, filter: string): this;
-  /** Keep elements matching the filter as HTML (currently no-op) */
-  keep(filter: Array<string>): this;
-  /** Remove elements matching the filter (currently no-op) */
-  remove(filter: Array<string>): this;
+  /**
+   * Convert HTML to Markdown on the libuv threadpool
+   *
+   * Aborting `signal` rejects the promise once the conversion reaches the
+   * next element. Rules added with `addRule` and function filters can only
+   * run on the main thread, so services with them throw instead.
+   */
+  turndownAsync(html: string, signal?: AbortSignal | undefined | null): Promise<string>;
+  /**
+   * Add a custom rule, taking precedence over built-in conversion and
+   * previously added rules
+   */
+  addRule(key: string, rule: { filter: string | Array<string> | ((node: NodeDescription, options: Options) => boolean); replacement: (content: string, node: NodeDescription, options: Options) => string }): this;
+  /** Keep elements matching the filter as HTML */
+  keep(filter: string | Array<string> | ((node: NodeDescription, options: Options) => boolean)): this;
+  /** Remove elements matching the filter */
+  remove(filter: string | Array<string> | ((node: NodeDescription, options: Options) => boolean)): this;
   /** Escape markdown special characters */
   escape(text: string): string;
 }
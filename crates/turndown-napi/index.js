@@ -319,6 +319,7 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`);
 }
 
-const { TurndownService } = nativeBinding;
+const { NodeDescription, TurndownService } = nativeBinding;
 
+module.exports.NodeDescription = NodeDescription;
 module.exports.TurndownService = TurndownService;
@@ -0,0 +1,207 @@
+//! HTML character reference decoding
+//!
+//! tl hands out text and attribute values as they appear in the source, so
+//! character references have to be decoded before the content is escaped for
+//! Markdown. Decoding follows the HTML5 tokenizer: all WHATWG named references
+//! (including the legacy ones that may omit the semicolon), decimal and hex
+//! numeric references, and the replacement rules for invalid code points.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Longest named reference (`&CounterClockwiseContourIntegral;`) without `&`
+const MAX_NAME_LEN: usize = 32;
+
+/// Named references keyed without the leading `&`; legacy references appear
+/// both with and without the trailing `;`
+fn named_references() -> &'static HashMap<&'static str, &'static str> {
+    static REFERENCES: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    REFERENCES.get_or_init(|| {
+        entities::ENTITIES
+            .iter()
+            .map(|e| (&e.entity[1..], e.characters))
+            .collect()
+    })
+}
+
+/// Decode character references in text content
+pub fn decode_text(s: &str) -> Cow<'_, str> {
+    decode(s, false)
+}
+
+/// Decode character references in an attribute value
+///
+/// Unlike text, a legacy reference without semicolon followed by `=` or an
+/// alphanumeric character is left alone (`?a=1&copy=2` stays intact).
+pub fn decode_attribute(s: &str) -> Cow<'_, str> {
+    decode(s, true)
+}
+
+fn decode(s: &str, in_attribute: bool) -> Cow<'_, str> {
+    let Some(first) = s.find('&') else {
+        return Cow::Borrowed(s);
+    };
+
+    let mut out = String::with_capacity(s.len());
+    out.push_str(&s[..first]);
+    let mut rest = &s[first..];
+
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        let consumed = if rest[1..].starts_with('#') {
+            decode_numeric(rest, &mut out)
+        } else {
+            decode_named(rest, in_attribute, &mut out)
+        };
+
+        match consumed {
+            Some(len) => rest = &rest[len..],
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Decode `&#123;` / `&#x7B;` at the start of `s`, returning the bytes consumed
+fn decode_numeric(s: &str, out: &mut String) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let (radix, digits_start) = match bytes.get(2) {
+        Some(b'x' | b'X') => (16, 3),
+        _ => (10, 2),
+    };
+
+    let digits_len = bytes[digits_start..]
+        .iter()
+        .take_while(|b| if radix == 16 { b.is_ascii_hexdigit() } else { b.is_ascii_digit() })
+        .count();
+    if digits_len == 0 {
+        return None;
+    }
+
+    let digits = &s[digits_start..digits_start + digits_len];
+    let mut end = digits_start + digits_len;
+    if bytes.get(end) == Some(&b';') {
+        end += 1;
+    }
+
+    // Overlong values are out of range, like any code point above U+10FFFF
+    let code = u32::from_str_radix(digits, radix).unwrap_or(u32::MAX);
+    out.push(numeric_char(code));
+    Some(end)
+}
+
+/// Map a numeric reference to a character per the HTML5 tokenizer
+fn numeric_char(code: u32) -> char {
+    match code {
+        0 => '\u{FFFD}',
+        0x80..=0x9F => windows_1252(code).unwrap_or_else(|| char::from_u32(code).unwrap()),
+        _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+    }
+}
+
+/// C1 control references are interpreted as Windows-1252
+fn windows_1252(code: u32) -> Option<char> {
+    Some(match code {
+        0x80 => '\u{20AC}',
+        0x82 => '\u{201A}',
+        0x83 => '\u{0192}',
+        0x84 => '\u{201E}',
+        0x85 => '\u{2026}',
+        0x86 => '\u{2020}',
+        0x87 => '\u{2021}',
+        0x88 => '\u{02C6}',
+        0x89 => '\u{2030}',
+        0x8A => '\u{0160}',
+        0x8B => '\u{2039}',
+        0x8C => '\u{0152}',
+        0x8E => '\u{017D}',
+        0x91 => '\u{2018}',
+        0x92 => '\u{2019}',
+        0x93 => '\u{201C}',
+        0x94 => '\u{201D}',
+        0x95 => '\u{2022}',
+        0x96 => '\u{2013}',
+        0x97 => '\u{2014}',
+        0x98 => '\u{02DC}',
+        0x99 => '\u{2122}',
+        0x9A => '\u{0161}',
+        0x9B => '\u{203A}',
+        0x9C => '\u{0153}',
+        0x9E => '\u{017E}',
+        0x9F => '\u{0178}',
+        _ => return None,
+    })
+}
+
+/// Decode a named reference at the start of `s`, returning the bytes consumed
+fn decode_named(s: &str, in_attribute: bool, out: &mut String) -> Option<usize> {
+    let references = named_references();
+    let name_len = s[1..]
+        .bytes()
+        .take_while(u8::is_ascii_alphanumeric)
+        .take(MAX_NAME_LEN)
+        .count();
+    if name_len == 0 {
+        return None;
+    }
+
+    // Exact match including the semicolon
+    if s[1 + name_len..].starts_with(';') {
+        if let Some(chars) = references.get(&s[1..name_len + 2]) {
+            out.push_str(chars);
+            return Some(name_len + 2);
+        }
+    }
+
+    // Longest legacy reference without semicolon (`&copy2024` → `©2024`)
+    let (len, chars) = (1..=name_len)
+        .rev()
+        .find_map(|len| references.get(&s[1..1 + len]).map(|chars| (len, chars)))?;
+
+    if in_attribute {
+        let next = s.as_bytes().get(1 + len);
+        if next.is_some_and(|&b| b == b'=' || b.is_ascii_alphanumeric()) {
+            return None;
+        }
+    }
+
+    out.push_str(chars);
+    Some(1 + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named() {
+        assert_eq!(decode_text("Tom &amp; Jerry&nbsp;&hellip;"), "Tom & Jerry\u{a0}…");
+        assert_eq!(decode_text("&CounterClockwiseContourIntegral;"), "∳");
+        assert_eq!(decode_text("&unknown; & &;"), "&unknown; & &;");
+        assert!(matches!(decode_text("no references"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_legacy_without_semicolon() {
+        assert_eq!(decode_text("&copy2024 &lt;b&gt"), "©2024 <b>");
+        assert_eq!(decode_text("&notin; &notit;"), "∉ ¬it;");
+        assert_eq!(decode_attribute("?a=1&copy=2&amp;b"), "?a=1&copy=2&b");
+        assert_eq!(decode_attribute("&copy 2024"), "© 2024");
+    }
+
+    #[test]
+    fn test_numeric() {
+        assert_eq!(decode_text("&#8217;&#x2019;&#X41;&#65"), "’’AA");
+        assert_eq!(decode_text("&#0;&#xD800;&#x110000;&#99999999999;"), "\u{FFFD}".repeat(4));
+        assert_eq!(decode_text("&#150;&#x81;"), "–\u{81}");
+        assert_eq!(decode_text("&#; &#x;"), "&#; &#x;");
+    }
+}
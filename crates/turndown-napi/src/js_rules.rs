@@ -0,0 +1,287 @@
+//! JavaScript `addRule` support
+//!
+//! Rules registered from JavaScript keep their filter and replacement as
+//! function references, as do predicates passed to `keep()` and `remove()`.
+//! The converter only calls into JavaScript for elements a rule or predicate
+//! can match, so everything else stays on the native fast path.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use napi::bindgen_prelude::Either3;
+use napi::bindgen_prelude::FunctionRef;
+use napi::{Env, Error, JsObject, JsUnknown, Result, Status, ValueType};
+use napi_derive::napi;
+
+use crate::tl_streaming::{CallbackNode, CallbackRules};
+use crate::Options;
+
+type ReplacementFn = FunctionRef<(String, NodeDescription, Options), JsUnknown>;
+pub type PredicateFn = FunctionRef<(NodeDescription, Options), JsUnknown>;
+
+/// A turndown.js filter: a tag name, an array of tag names or a predicate
+pub type FilterArg = Either3<String, Vec<String>, PredicateFn>;
+
+/// Element description passed to rule filters and replacements
+///
+/// Properties are read from the converter when accessed, so rules that only
+/// look at the tag name never copy the element's text or markup.
+#[napi]
+pub struct NodeDescription {
+    node: CallbackNode,
+}
+
+#[napi]
+impl NodeDescription {
+    /// Uppercase tag name, like `Node.nodeName`
+    #[napi(getter)]
+    pub fn node_name(&self) -> String {
+        self.node.tag.to_ascii_uppercase()
+    }
+
+    /// Attributes by name
+    #[napi(getter)]
+    pub fn attributes(&self) -> HashMap<String, String> {
+        self.node.attributes.iter().cloned().collect()
+    }
+
+    #[napi(getter)]
+    pub fn class_name(&self) -> String {
+        self.node.attr("class").unwrap_or_default().to_string()
+    }
+
+    #[napi(getter)]
+    pub fn id(&self) -> String {
+        self.node.attr("id").unwrap_or_default().to_string()
+    }
+
+    #[napi(getter)]
+    pub fn text_content(&self) -> String {
+        self.node.text_content()
+    }
+
+    /// Original source markup of the element
+    #[napi(getter, js_name = "outerHTML")]
+    pub fn outer_html(&self) -> String {
+        self.node.outer_html().to_string()
+    }
+
+    /// Whether the element is block-level in the turndown.js sense
+    #[napi(getter)]
+    pub fn is_block(&self) -> bool {
+        self.node.is_block
+    }
+
+    /// Uppercase tag names of the enclosing elements, outermost first
+    #[napi(getter)]
+    pub fn ancestors(&self) -> Vec<String> {
+        let mut ancestors = self.node.ancestors();
+        ancestors.iter_mut().for_each(|a| a.make_ascii_uppercase());
+        ancestors
+    }
+}
+
+impl From<&CallbackNode> for NodeDescription {
+    fn from(node: &CallbackNode) -> Self {
+        Self { node: node.clone() }
+    }
+}
+
+pub enum JsFilter {
+    /// Lowercase tag names
+    Tags(Vec<String>),
+    Predicate(PredicateFn),
+}
+
+impl From<FilterArg> for JsFilter {
+    fn from(filter: FilterArg) -> Self {
+        match filter {
+            Either3::A(tag) => JsFilter::Tags(vec![tag.to_lowercase()]),
+            Either3::B(tags) => JsFilter::Tags(tags.iter().map(|t| t.to_lowercase()).collect()),
+            Either3::C(predicate) => JsFilter::Predicate(predicate),
+        }
+    }
+}
+
+/// A rule registered via `addRule`
+pub struct JsRule {
+    key: String,
+    filter: JsFilter,
+    replacement: ReplacementFn,
+}
+
+impl JsRule {
+    /// Read a turndown.js style `{ filter, replacement }` rule object
+    pub fn from_object(key: String, rule: &JsObject) -> Result<Self> {
+        let filter: JsUnknown = rule.get_named_property("filter")?;
+        let valid = match filter.get_type()? {
+            ValueType::String | ValueType::Function => true,
+            ValueType::Object => filter.is_array()?,
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::new(
+                Status::InvalidArg,
+                format!(
+                    "Rule \"{}\": filter must be a string, an array of strings or a function",
+                    key
+                ),
+            ));
+        }
+        let filter = JsFilter::from(rule.get_named_property::<FilterArg>("filter")?);
+
+        let replacement: JsUnknown = rule.get_named_property("replacement")?;
+        if replacement.get_type()? != ValueType::Function {
+            return Err(Error::new(
+                Status::InvalidArg,
+                format!("Rule \"{}\": replacement must be a function", key),
+            ));
+        }
+
+        Ok(Self {
+            replacement: rule.get_named_property("replacement")?,
+            key,
+            filter,
+        })
+    }
+}
+
+/// Rules and filters that call into JavaScript
+#[derive(Default)]
+pub struct JsRules {
+    /// Rules added with `addRule`, most recent first
+    pub rules: Vec<JsRule>,
+    /// Predicates passed to `keep()`
+    pub keep: Vec<PredicateFn>,
+    /// Predicates passed to `remove()`
+    pub remove: Vec<PredicateFn>,
+}
+
+impl JsRules {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.keep.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// Callback rules bound to the environment of one `turndown()` call
+pub struct JsCallbacks<'r> {
+    env: Env,
+    rules: &'r JsRules,
+    options: Options,
+    /// First exception thrown by a callback; later callbacks are skipped
+    error: RefCell<Option<Error>>,
+}
+
+impl<'r> JsCallbacks<'r> {
+    pub fn new(env: Env, rules: &'r JsRules, options: Options) -> Self {
+        Self {
+            env,
+            rules,
+            options,
+            error: RefCell::new(None),
+        }
+    }
+
+    /// Take the first error raised by a callback, if any
+    pub fn take_error(&self) -> Option<Error> {
+        self.error.borrow_mut().take()
+    }
+
+    fn failed(&self) -> bool {
+        self.error.borrow().is_some()
+    }
+
+    /// Record the first error, naming the rule or filter that raised it
+    fn record<T>(&self, source: &str, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                let mut error = self.error.borrow_mut();
+                if error.is_none() {
+                    *error = Some(Error::new(
+                        err.status,
+                        format!("{} failed: {}", source, err.reason),
+                    ));
+                }
+                None
+            }
+        }
+    }
+
+    fn matches(&self, predicate: &PredicateFn, node: &CallbackNode) -> Result<bool> {
+        let result = predicate
+            .borrow_back(&self.env)?
+            .call((NodeDescription::from(node), self.options.clone()))?;
+        result.coerce_to_bool()?.get_value()
+    }
+
+    /// Whether one of the `keep()` or `remove()` predicates matches
+    fn any_matches(&self, method: &str, predicates: &[PredicateFn], node: &CallbackNode) -> bool {
+        !self.failed()
+            && predicates.iter().any(|predicate| {
+                let source = format!("{}() filter", method);
+                self.record(&source, self.matches(predicate, node))
+                    .unwrap_or(false)
+            })
+    }
+
+    fn call_replacement(&self, rule: &JsRule, content: &str, node: &CallbackNode) -> Result<String> {
+        let result = rule.replacement.borrow_back(&self.env)?.call((
+            content.to_string(),
+            NodeDescription::from(node),
+            self.options.clone(),
+        ))?;
+        match result.get_type()? {
+            ValueType::Undefined | ValueType::Null => Ok(String::new()),
+            _ => result.coerce_to_string()?.into_utf8()?.into_owned(),
+        }
+    }
+}
+
+impl CallbackRules for JsCallbacks<'_> {
+    fn may_match(&self, tag_lower: &str) -> bool {
+        !self.failed()
+            && self.rules.rules.iter().any(|rule| match &rule.filter {
+                JsFilter::Tags(tags) => tags.iter().any(|t| t == tag_lower),
+                JsFilter::Predicate(_) => true,
+            })
+    }
+
+    fn find(&self, node: &CallbackNode) -> Option<usize> {
+        for (index, rule) in self.rules.rules.iter().enumerate() {
+            let matched = match &rule.filter {
+                JsFilter::Tags(tags) => tags.contains(&node.tag),
+                JsFilter::Predicate(predicate) => {
+                    let source = format!("Rule \"{}\"", rule.key);
+                    self.record(&source, self.matches(predicate, node))?
+                }
+            };
+            if matched {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    fn replace(&self, index: usize, content: &str, node: &CallbackNode) -> String {
+        if self.failed() {
+            return String::new();
+        }
+        let rule = &self.rules.rules[index];
+        let source = format!("Rule \"{}\"", rule.key);
+        self.record(&source, self.call_replacement(rule, content, node))
+            .unwrap_or_default()
+    }
+
+    fn has_filters(&self) -> bool {
+        !self.rules.keep.is_empty() || !self.rules.remove.is_empty()
+    }
+
+    fn keeps(&self, node: &CallbackNode) -> bool {
+        self.any_matches("keep", &self.rules.keep, node)
+    }
+
+    fn removes(&self, node: &CallbackNode) -> bool {
+        self.any_matches("remove", &self.rules.remove, node)
+    }
+}
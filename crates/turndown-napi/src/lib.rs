@@ -1,34 +1,159 @@
 #![deny(clippy::all)]
 
+mod entities;
+mod js_rules;
 mod tl_streaming;
 
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use js_rules::{FilterArg, JsCallbacks, JsFilter, JsRule, JsRules};
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, JsFunction, JsObject, JsString, Ref, Status, Task};
 use napi_derive::napi;
+use tl_streaming::Filters;
 use turndown_core::{
-    CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle, Options as CoreOptions,
+    Block, CaptionPosition, CaptionStyle, CodeBlockStyle, HeadingStyle, LimitMode,
+    LinkReferenceStyle, LinkStyle, MarkupStyle, Options as CoreOptions, TableFallback, TableSpans,
+    TurndownError,
 };
 
 #[napi(object)]
+#[derive(Clone)]
 pub struct Options {
+    #[napi(ts_type = "\"setext\" | \"atx\"")]
     pub heading_style: Option<String>,
     pub hr: Option<String>,
+    #[napi(ts_type = "\"*\" | \"-\" | \"+\"")]
     pub bullet_list_marker: Option<String>,
+    #[napi(ts_type = "\"indented\" | \"fenced\"")]
     pub code_block_style: Option<String>,
     pub fence: Option<String>,
+    #[napi(ts_type = "\"_\" | \"*\"")]
     pub em_delimiter: Option<String>,
+    #[napi(ts_type = "\"**\" | \"__\"")]
     pub strong_delimiter: Option<String>,
+    #[napi(ts_type = "\"inlined\" | \"referenced\"")]
     pub link_style: Option<String>,
+    #[napi(ts_type = "\"full\" | \"collapsed\" | \"shortcut\"")]
     pub link_reference_style: Option<String>,
+    pub tables: Option<bool>,
+    pub task_lists: Option<bool>,
+    #[napi(ts_type = "\"plain\" | \"markdown\" | \"html\"")]
+    pub strikethrough_style: Option<String>,
+    #[napi(ts_type = "\"plain\" | \"markdown\" | \"html\"")]
+    pub highlight_style: Option<String>,
+    #[napi(ts_type = "\"plain\" | \"markdown\" | \"html\"")]
+    pub script_style: Option<String>,
+    pub table_line_break: Option<String>,
+    #[napi(ts_type = "\"html\" | \"list\"")]
+    pub table_fallback: Option<String>,
+    #[napi(ts_type = "\"fallback\" | \"blank\" | \"duplicate\"")]
+    pub table_spans: Option<String>,
+    #[napi(ts_type = "\"above\" | \"below\"")]
+    pub caption_position: Option<String>,
+    #[napi(ts_type = "\"plain\" | \"bold\" | \"pandoc\"")]
+    pub caption_style: Option<String>,
+    pub max_depth: Option<f64>,
+    pub max_input_bytes: Option<f64>,
+    pub max_output_bytes: Option<f64>,
+    pub max_nodes: Option<f64>,
+    #[napi(ts_type = "\"error\" | \"truncate\"")]
+    pub limit_mode: Option<String>,
 }
 
-impl From<Options> for CoreOptions {
-    fn from(opts: Options) -> Self {
+/// Option names as passed from JavaScript, in declaration order
+const OPTION_NAMES: &[&str] = &[
+    "headingStyle",
+    "hr",
+    "bulletListMarker",
+    "codeBlockStyle",
+    "fence",
+    "emDelimiter",
+    "strongDelimiter",
+    "linkStyle",
+    "linkReferenceStyle",
+    "tables",
+    "taskLists",
+    "strikethroughStyle",
+    "highlightStyle",
+    "scriptStyle",
+    "tableLineBreak",
+    "tableFallback",
+    "tableSpans",
+    "captionPosition",
+    "captionStyle",
+    "maxDepth",
+    "maxInputBytes",
+    "maxOutputBytes",
+    "maxNodes",
+    "limitMode",
+];
+
+/// Largest limit accepted, the largest integer a JavaScript number holds exactly
+const MAX_LIMIT: f64 = 9_007_199_254_740_991.0;
+
+// Accepted option values, in the order they are listed in error messages
+
+const HEADING_STYLES: &[(&str, HeadingStyle)] =
+    &[("setext", HeadingStyle::Setext), ("atx", HeadingStyle::Atx)];
+
+const BULLET_LIST_MARKERS: &[(&str, char)] = &[("*", '*'), ("-", '-'), ("+", '+')];
+
+const CODE_BLOCK_STYLES: &[(&str, CodeBlockStyle)] = &[
+    ("indented", CodeBlockStyle::Indented),
+    ("fenced", CodeBlockStyle::Fenced),
+];
+
+const EM_DELIMITERS: &[(&str, char)] = &[("_", '_'), ("*", '*')];
+
+const LINK_STYLES: &[(&str, LinkStyle)] =
+    &[("inlined", LinkStyle::Inlined), ("referenced", LinkStyle::Referenced)];
+
+const LINK_REFERENCE_STYLES: &[(&str, LinkReferenceStyle)] = &[
+    ("full", LinkReferenceStyle::Full),
+    ("collapsed", LinkReferenceStyle::Collapsed),
+    ("shortcut", LinkReferenceStyle::Shortcut),
+];
+
+const MARKUP_STYLES: &[(&str, MarkupStyle)] = &[
+    ("plain", MarkupStyle::Plain),
+    ("markdown", MarkupStyle::Markdown),
+    ("html", MarkupStyle::Html),
+];
+
+const TABLE_FALLBACKS: &[(&str, TableFallback)] =
+    &[("html", TableFallback::Html), ("list", TableFallback::List)];
+
+const TABLE_SPANS: &[(&str, TableSpans)] = &[
+    ("fallback", TableSpans::Fallback),
+    ("blank", TableSpans::Blank),
+    ("duplicate", TableSpans::Duplicate),
+];
+
+const CAPTION_POSITIONS: &[(&str, CaptionPosition)] = &[
+    ("above", CaptionPosition::Above),
+    ("below", CaptionPosition::Below),
+];
+
+const CAPTION_STYLES: &[(&str, CaptionStyle)] = &[
+    ("plain", CaptionStyle::Plain),
+    ("bold", CaptionStyle::Bold),
+    ("pandoc", CaptionStyle::Pandoc),
+];
+
+const LIMIT_MODES: &[(&str, LimitMode)] =
+    &[("error", LimitMode::Error), ("truncate", LimitMode::Truncate)];
+
+impl TryFrom<Options> for CoreOptions {
+    type Error = TurndownError;
+
+    fn try_from(opts: Options) -> Result<Self, TurndownError> {
         let mut result = CoreOptions::default();
 
         if let Some(style) = opts.heading_style {
-            result.heading_style = match style.to_lowercase().as_str() {
-                "atx" => HeadingStyle::Atx,
-                _ => HeadingStyle::Setext,
-            };
+            result.heading_style = parse_choice("headingStyle", &style, HEADING_STYLES)?;
         }
 
         if let Some(hr) = opts.hr {
@@ -36,16 +161,12 @@ impl From<Options> for CoreOptions {
         }
 
         if let Some(marker) = opts.bullet_list_marker {
-            if let Some(c) = marker.chars().next() {
-                result.bullet_list_marker = c;
-            }
+            result.bullet_list_marker =
+                parse_choice("bulletListMarker", &marker, BULLET_LIST_MARKERS)?;
         }
 
         if let Some(style) = opts.code_block_style {
-            result.code_block_style = match style.to_lowercase().as_str() {
-                "fenced" => CodeBlockStyle::Fenced,
-                _ => CodeBlockStyle::Indented,
-            };
+            result.code_block_style = parse_choice("codeBlockStyle", &style, CODE_BLOCK_STYLES)?;
         }
 
         if let Some(fence) = opts.fence {
@@ -53,9 +174,7 @@ impl From<Options> for CoreOptions {
         }
 
         if let Some(delim) = opts.em_delimiter {
-            if let Some(c) = delim.chars().next() {
-                result.em_delimiter = c;
-            }
+            result.em_delimiter = parse_choice("emDelimiter", &delim, EM_DELIMITERS)?;
         }
 
         if let Some(delim) = opts.strong_delimiter {
@@ -63,67 +182,294 @@ impl From<Options> for CoreOptions {
         }
 
         if let Some(style) = opts.link_style {
-            result.link_style = match style.to_lowercase().as_str() {
-                "referenced" => LinkStyle::Referenced,
-                _ => LinkStyle::Inlined,
-            };
+            result.link_style = parse_choice("linkStyle", &style, LINK_STYLES)?;
         }
 
         if let Some(style) = opts.link_reference_style {
-            result.link_reference_style = match style.to_lowercase().as_str() {
-                "collapsed" => LinkReferenceStyle::Collapsed,
-                "shortcut" => LinkReferenceStyle::Shortcut,
-                _ => LinkReferenceStyle::Full,
-            };
+            result.link_reference_style =
+                parse_choice("linkReferenceStyle", &style, LINK_REFERENCE_STYLES)?;
+        }
+
+        if let Some(tables) = opts.tables {
+            result.tables = tables;
+        }
+
+        if let Some(task_lists) = opts.task_lists {
+            result.task_lists = task_lists;
         }
 
-        result
+        if let Some(style) = opts.strikethrough_style {
+            result.strikethrough_style = parse_choice("strikethroughStyle", &style, MARKUP_STYLES)?;
+        }
+
+        if let Some(style) = opts.highlight_style {
+            result.highlight_style = parse_choice("highlightStyle", &style, MARKUP_STYLES)?;
+        }
+
+        if let Some(style) = opts.script_style {
+            result.script_style = parse_choice("scriptStyle", &style, MARKUP_STYLES)?;
+        }
+
+        if let Some(line_break) = opts.table_line_break {
+            result.table_line_break = line_break;
+        }
+
+        if let Some(fallback) = opts.table_fallback {
+            result.table_fallback = parse_choice("tableFallback", &fallback, TABLE_FALLBACKS)?;
+        }
+
+        if let Some(spans) = opts.table_spans {
+            result.table_spans = parse_choice("tableSpans", &spans, TABLE_SPANS)?;
+        }
+
+        if let Some(position) = opts.caption_position {
+            result.caption_position =
+                parse_choice("captionPosition", &position, CAPTION_POSITIONS)?;
+        }
+
+        if let Some(style) = opts.caption_style {
+            result.caption_style = parse_choice("captionStyle", &style, CAPTION_STYLES)?;
+        }
+
+        let limits = &mut result.limits;
+        limits.max_depth = parse_limit("maxDepth", opts.max_depth)?;
+        limits.max_input_bytes = parse_limit("maxInputBytes", opts.max_input_bytes)?;
+        limits.max_output_bytes = parse_limit("maxOutputBytes", opts.max_output_bytes)?;
+        limits.max_nodes = parse_limit("maxNodes", opts.max_nodes)?;
+
+        if let Some(mode) = opts.limit_mode {
+            limits.mode = parse_choice("limitMode", &mode, LIMIT_MODES)?;
+        }
+
+        result.validate()?;
+        Ok(result)
     }
 }
 
+impl From<&CoreOptions> for Options {
+    fn from(opts: &CoreOptions) -> Self {
+        Self {
+            heading_style: Some(choice_name(HEADING_STYLES, opts.heading_style)),
+            hr: Some(opts.hr.clone()),
+            bullet_list_marker: Some(opts.bullet_list_marker.to_string()),
+            code_block_style: Some(choice_name(CODE_BLOCK_STYLES, opts.code_block_style)),
+            fence: Some(opts.fence.clone()),
+            em_delimiter: Some(opts.em_delimiter.to_string()),
+            strong_delimiter: Some(opts.strong_delimiter.clone()),
+            link_style: Some(choice_name(LINK_STYLES, opts.link_style)),
+            link_reference_style: Some(choice_name(
+                LINK_REFERENCE_STYLES,
+                opts.link_reference_style,
+            )),
+            tables: Some(opts.tables),
+            task_lists: Some(opts.task_lists),
+            strikethrough_style: Some(choice_name(MARKUP_STYLES, opts.strikethrough_style)),
+            highlight_style: Some(choice_name(MARKUP_STYLES, opts.highlight_style)),
+            script_style: Some(choice_name(MARKUP_STYLES, opts.script_style)),
+            table_line_break: Some(opts.table_line_break.clone()),
+            table_fallback: Some(choice_name(TABLE_FALLBACKS, opts.table_fallback)),
+            table_spans: Some(choice_name(TABLE_SPANS, opts.table_spans)),
+            caption_position: Some(choice_name(CAPTION_POSITIONS, opts.caption_position)),
+            caption_style: Some(choice_name(CAPTION_STYLES, opts.caption_style)),
+            max_depth: opts.limits.max_depth.map(limit_value),
+            max_input_bytes: opts.limits.max_input_bytes.map(limit_value),
+            max_output_bytes: opts.limits.max_output_bytes.map(limit_value),
+            max_nodes: opts.limits.max_nodes.map(limit_value),
+            limit_mode: Some(choice_name(LIMIT_MODES, opts.limits.mode)),
+        }
+    }
+}
+
+/// Look up an option value in its accepted values
+fn parse_choice<T: Copy>(
+    name: &'static str,
+    value: &str,
+    choices: &[(&str, T)],
+) -> Result<T, TurndownError> {
+    if let Some(&(_, choice)) = choices.iter().find(|(v, _)| *v == value) {
+        return Ok(choice);
+    }
+
+    let names: Vec<String> = choices.iter().map(|(v, _)| format!("{v:?}")).collect();
+    let (last, rest) = names.split_last().expect("options have several values");
+    Err(TurndownError::InvalidOption {
+        name,
+        message: format!("expected {} or {last}, got {value:?}", rest.join(", ")),
+    })
+}
+
+fn choice_name<T: PartialEq>(choices: &[(&str, T)], value: T) -> String {
+    choices
+        .iter()
+        .find(|(_, v)| *v == value)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_default()
+}
+
+/// Check that a limit is a whole number of nodes or bytes
+fn parse_limit(name: &'static str, value: Option<f64>) -> Result<Option<usize>, TurndownError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    // NaN and infinities fail both checks
+    if value.fract() == 0.0 && (0.0..=MAX_LIMIT).contains(&value) {
+        if let Ok(limit) = usize::try_from(value as u64) {
+            return Ok(Some(limit));
+        }
+    }
+    Err(TurndownError::InvalidOption {
+        name,
+        message: format!("expected an integer from 0 to {MAX_LIMIT}, got {value}"),
+    })
+}
+
+fn limit_value(max: usize) -> f64 {
+    max as f64
+}
+
+/// Find an option name that is not one of [`OPTION_NAMES`]
+///
+/// Unknown options would otherwise be ignored, hiding typos such as
+/// `maxdepth` behind a missing limit.
+fn unknown_option<I: IntoIterator<Item = String>>(names: I) -> Option<String> {
+    names.into_iter().find(|name| !OPTION_NAMES.contains(&name.as_str()))
+}
+
+/// Convert the options object passed to the constructor
+fn parse_options(env: Env, object: JsObject) -> napi::Result<Options> {
+    let keys = object.get_property_names()?;
+    let names = (0..keys.get_array_length()?)
+        .map(|index| keys.get_element::<JsString>(index)?.into_utf8()?.into_owned())
+        .collect::<napi::Result<Vec<String>>>()?;
+    if let Some(name) = unknown_option(names) {
+        return Err(napi::Error::new(Status::InvalidArg, format!("Unknown option `{name}`")));
+    }
+
+    // Read back through a property to use the generated conversion
+    let mut holder = env.create_object()?;
+    holder.set_named_property("options", object)?;
+    holder.get_named_property("options")
+}
+
 #[napi]
 pub struct TurndownService {
     options: CoreOptions,
+    filters: Filters,
+    /// Rules and filters that call into JavaScript
+    js_rules: JsRules,
 }
 
 #[napi]
 impl TurndownService {
-    #[napi(constructor)]
-    pub fn new(options: Option<Options>) -> Self {
+    #[napi(constructor, ts_args_type = "options?: Options | undefined | null")]
+    pub fn new(env: Env, options: Option<JsObject>) -> napi::Result<Self> {
         let options = match options {
-            Some(opts) => opts.into(),
+            Some(opts) => CoreOptions::try_from(parse_options(env, opts)?).map_err(to_napi_error)?,
             None => CoreOptions::default(),
         };
-        Self { options }
+        Ok(Self {
+            options,
+            filters: Filters::default(),
+            js_rules: JsRules::default(),
+        })
+    }
+
+    /// The effective options, with defaults filled in
+    #[napi(getter)]
+    pub fn options(&self) -> Options {
+        Options::from(&self.options)
     }
 
     /// Convert HTML to Markdown using tl parser
     #[napi]
-    pub fn turndown(&self, html: String) -> napi::Result<String> {
+    pub fn turndown(&self, env: Env, html: String) -> napi::Result<String> {
         // Use tl conversion: HTML → AST → Markdown
-        let ast = tl_streaming::html_to_ast(&html, &self.options);
-        let result = turndown_core::serialize(&ast, &self.options);
-        Ok(result)
+        let ast = if self.js_rules.is_empty() {
+            tl_streaming::html_to_ast(&html, &self.options, &self.filters)
+        } else {
+            let callbacks = JsCallbacks::new(env, &self.js_rules, Options::from(&self.options));
+            let ast = tl_streaming::html_to_ast_with_callbacks(
+                Rc::new(html),
+                &self.options,
+                &self.filters,
+                &callbacks,
+            );
+            if let Some(err) = callbacks.take_error() {
+                if let Ok(ast) = ast {
+                    ast.drop_iteratively();
+                }
+                return Err(err);
+            }
+            ast
+        }
+        .map_err(to_napi_error)?;
+        serialize(ast, &self.options)
     }
 
-    /// Add a custom rule (currently no-op)
-    #[napi]
-    pub fn add_rule(&mut self, _key: String, _filter: String) -> napi::Result<&Self> {
-        // TODO: Re-implement custom rules
+    /// Convert HTML to Markdown on the libuv threadpool
+    ///
+    /// Aborting `signal` rejects the promise once the conversion reaches the
+    /// next element. Rules added with `addRule` and function filters can only
+    /// run on the main thread, so services with them throw instead.
+    #[napi(ts_args_type = "html: string, signal?: AbortSignal | undefined | null")]
+    pub fn turndown_async(
+        &self,
+        env: Env,
+        html: String,
+        signal: Option<JsObject>,
+    ) -> napi::Result<AsyncTask<ConvertTask>> {
+        if !self.js_rules.is_empty() {
+            return Err(napi::Error::new(
+                Status::InvalidArg,
+                "turndownAsync does not support rules added with addRule or function filters, use turndown instead",
+            ));
+        }
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let listener = match signal {
+            Some(signal) => listen_for_abort(env, &signal, &abort)?,
+            None => None,
+        };
+        Ok(AsyncTask::new(ConvertTask {
+            html,
+            options: self.options.clone(),
+            filters: self.filters.clone(),
+            abort,
+            listener,
+        }))
+    }
+
+    /// Add a custom rule, taking precedence over built-in conversion and
+    /// previously added rules
+    #[napi(
+        ts_args_type = "key: string, rule: { filter: string | Array<string> | ((node: NodeDescription, options: Options) => boolean); replacement: (content: string, node: NodeDescription, options: Options) => string }"
+    )]
+    pub fn add_rule(&mut self, key: String, rule: JsObject) -> napi::Result<&Self> {
+        self.js_rules.rules.insert(0, JsRule::from_object(key, &rule)?);
         Ok(self)
     }
 
-    /// Keep elements matching the filter as HTML (currently no-op)
-    #[napi]
-    pub fn keep(&mut self, _filter: Vec<String>) -> &Self {
-        // TODO: Implement keep in streaming
+    /// Keep elements matching the filter as HTML
+    #[napi(
+        ts_args_type = "filter: string | Array<string> | ((node: NodeDescription, options: Options) => boolean)"
+    )]
+    pub fn keep(&mut self, filter: FilterArg) -> &Self {
+        match JsFilter::from(filter) {
+            JsFilter::Tags(tags) => self.filters.keep.extend(tags),
+            JsFilter::Predicate(predicate) => self.js_rules.keep.push(predicate),
+        }
         self
     }
 
-    /// Remove elements matching the filter (currently no-op)
-    #[napi]
-    pub fn remove(&mut self, _filter: Vec<String>) -> &Self {
-        // TODO: Implement remove in streaming
+    /// Remove elements matching the filter
+    #[napi(
+        ts_args_type = "filter: string | Array<string> | ((node: NodeDescription, options: Options) => boolean)"
+    )]
+    pub fn remove(&mut self, filter: FilterArg) -> &Self {
+        match JsFilter::from(filter) {
+            JsFilter::Tags(tags) => self.filters.remove.extend(tags),
+            JsFilter::Predicate(predicate) => self.js_rules.remove.push(predicate),
+        }
         self
     }
 
@@ -134,6 +480,160 @@ impl TurndownService {
     }
 }
 
+/// Conversion run off the main thread by `turndownAsync`
+pub struct ConvertTask {
+    html: String,
+    options: CoreOptions,
+    filters: Filters,
+    /// Set when the AbortSignal fires
+    abort: Arc<AtomicBool>,
+    /// Listener setting `abort`, removed once the task settles
+    listener: Option<AbortListener>,
+}
+
+impl ConvertTask {
+    fn check_abort(&self) -> napi::Result<()> {
+        if self.abort.load(Ordering::Relaxed) {
+            return Err(to_napi_error(TurndownError::Aborted));
+        }
+        Ok(())
+    }
+}
+
+impl Task for ConvertTask {
+    type Output = String;
+    type JsValue = String;
+
+    fn compute(&mut self) -> napi::Result<String> {
+        self.check_abort()?;
+        let ast = tl_streaming::html_to_ast_abortable(
+            &self.html,
+            &self.options,
+            &self.filters,
+            &self.abort,
+        )
+        .map_err(to_napi_error)?;
+        match self.check_abort() {
+            Ok(()) => serialize(ast, &self.options),
+            Err(err) => {
+                ast.drop_iteratively();
+                Err(err)
+            }
+        }
+    }
+
+    fn resolve(&mut self, _env: Env, output: String) -> napi::Result<String> {
+        Ok(output)
+    }
+
+    fn finally(&mut self, env: Env) -> napi::Result<()> {
+        match self.listener.take() {
+            Some(listener) => listener.remove(env),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Set `abort` when the AbortSignal fires, or right away if it already has
+///
+/// Returns the listener added to the signal, if any.
+fn listen_for_abort(
+    env: Env,
+    signal: &JsObject,
+    abort: &Arc<AtomicBool>,
+) -> napi::Result<Option<AbortListener>> {
+    if signal.get_named_property::<bool>("aborted")? {
+        abort.store(true, Ordering::Relaxed);
+        return Ok(None);
+    }
+
+    let abort = Arc::clone(abort);
+    let onabort = env.create_function_from_closure("onabort", move |_| {
+        abort.store(true, Ordering::Relaxed);
+        Ok(())
+    })?;
+    let listener = AbortListener {
+        signal: env.create_reference(signal)?,
+        listener: env.create_reference(onabort)?,
+    };
+    let mut options = env.create_object()?;
+    options.set_named_property("once", true)?;
+    match listener.call(env, "addEventListener", Some(options)) {
+        Ok(()) => Ok(Some(listener)),
+        Err(err) => {
+            listener.release(env)?;
+            Err(err)
+        }
+    }
+}
+
+/// An abort listener added to an AbortSignal
+///
+/// A signal can outlive many conversions, so the listener is removed when
+/// the conversion settles rather than left to hold on to it.
+struct AbortListener {
+    signal: Ref<()>,
+    listener: Ref<()>,
+}
+
+impl AbortListener {
+    fn remove(self, env: Env) -> napi::Result<()> {
+        let removed = self.call(env, "removeEventListener", None);
+        self.release(env)?;
+        removed
+    }
+
+    /// Call `addEventListener` or `removeEventListener` on the signal
+    fn call(&self, env: Env, method: &str, options: Option<JsObject>) -> napi::Result<()> {
+        let signal: JsObject = env.get_reference_value(&self.signal)?;
+        let listener: JsFunction = env.get_reference_value(&self.listener)?;
+        let method: JsFunction = signal.get_named_property(method)?;
+        let mut args = vec![env.create_string("abort")?.into_unknown(), listener.into_unknown()];
+        args.extend(options.map(JsObject::into_unknown));
+        method.call(Some(&signal), &args)?;
+        Ok(())
+    }
+
+    fn release(mut self, env: Env) -> napi::Result<()> {
+        self.signal.unref(env)?;
+        self.listener.unref(env)?;
+        Ok(())
+    }
+}
+
+/// Serialize a converted document, then drop it without recursion
+fn serialize(ast: Block, options: &CoreOptions) -> napi::Result<String> {
+    let markdown = turndown_core::serialize_limited(&ast, options);
+    ast.drop_iteratively();
+    markdown.map_err(to_napi_error)
+}
+
+/// Surface a conversion error as a JavaScript exception
+fn to_napi_error(err: TurndownError) -> napi::Error {
+    match err {
+        // Core options are named as in Rust, report them as passed from JS
+        TurndownError::InvalidOption { name, message } => napi::Error::new(
+            Status::InvalidArg,
+            format!("Invalid option `{}`: {message}", camel_case(name)),
+        ),
+        TurndownError::Parse(_) | TurndownError::LimitExceeded { .. } => {
+            napi::Error::new(Status::GenericFailure, err.to_string())
+        }
+        TurndownError::Aborted => napi::Error::new(Status::Cancelled, err.to_string()),
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut parts = name.split('_');
+    let mut result = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        result.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+        result.push_str(chars.as_str());
+    }
+    result
+}
+
 fn escape_markdown(text: &str) -> String {
     let mut result = String::with_capacity(text.len());
 
@@ -149,3 +649,164 @@ fn escape_markdown(text: &str) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Options {
+        Options::from(&CoreOptions::default())
+    }
+
+    #[test]
+    fn test_options_round_trip() {
+        let opts = Options {
+            heading_style: Some("atx".to_string()),
+            bullet_list_marker: Some("-".to_string()),
+            table_spans: Some("duplicate".to_string()),
+            max_depth: Some(64.0),
+            limit_mode: Some("truncate".to_string()),
+            ..options()
+        };
+        let core = CoreOptions::try_from(opts).unwrap();
+        assert_eq!(core.heading_style, HeadingStyle::Atx);
+        assert_eq!(core.bullet_list_marker, '-');
+        assert_eq!(core.limits.max_depth, Some(64));
+
+        let effective = Options::from(&core);
+        assert_eq!(effective.heading_style.as_deref(), Some("atx"));
+        assert_eq!(effective.table_spans.as_deref(), Some("duplicate"));
+        assert_eq!(effective.limit_mode.as_deref(), Some("truncate"));
+        assert_eq!(effective.max_nodes, None);
+    }
+
+    #[test]
+    fn test_convert_task() {
+        let mut task = ConvertTask {
+            html: "<h1>Title</h1><p>Some <em>text</em></p>".to_string(),
+            options: CoreOptions::default(),
+            filters: Filters::default(),
+            abort: Arc::new(AtomicBool::new(false)),
+            listener: None,
+        };
+        assert_eq!(task.compute().unwrap(), "Title\n=====\n\nSome _text_");
+
+        task.abort.store(true, Ordering::Relaxed);
+        let err = task.compute().unwrap_err();
+        assert_eq!(err.status, Status::Cancelled);
+        assert_eq!(err.reason, "Conversion aborted");
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let err = |opts: Options| to_napi_error(CoreOptions::try_from(opts).unwrap_err()).reason;
+
+        let opts = Options {
+            heading_style: Some("ATX ".to_string()),
+            ..options()
+        };
+        assert_eq!(
+            err(opts),
+            "Invalid option `headingStyle`: expected \"setext\" or \"atx\", got \"ATX \""
+        );
+
+        // Values are case-sensitive
+        let opts = Options {
+            heading_style: Some("ATX".to_string()),
+            ..options()
+        };
+        assert_eq!(
+            err(opts),
+            "Invalid option `headingStyle`: expected \"setext\" or \"atx\", got \"ATX\""
+        );
+
+        let opts = Options {
+            code_block_style: Some("fence".to_string()),
+            ..options()
+        };
+        assert_eq!(
+            err(opts),
+            "Invalid option `codeBlockStyle`: expected \"indented\" or \"fenced\", got \"fence\""
+        );
+
+        let opts = Options {
+            bullet_list_marker: Some("-+".to_string()),
+            ..options()
+        };
+        assert_eq!(
+            err(opts),
+            "Invalid option `bulletListMarker`: expected \"*\", \"-\" or \"+\", got \"-+\""
+        );
+
+        let opts = Options {
+            strong_delimiter: Some("*".to_string()),
+            ..options()
+        };
+        assert_eq!(err(opts), "Invalid option `strongDelimiter`: expected \"**\" or \"__\"");
+
+        let opts = Options {
+            table_line_break: Some(" | ".to_string()),
+            ..options()
+        };
+        assert_eq!(
+            err(opts),
+            "Invalid option `tableLineBreak`: expected text without \"|\" or newlines"
+        );
+    }
+
+    #[test]
+    fn test_invalid_limits() {
+        let limits = |max_depth| {
+            let opts = Options {
+                max_depth: Some(max_depth),
+                ..options()
+            };
+            CoreOptions::try_from(opts).map(|core| core.limits.max_depth).map_err(to_napi_error)
+        };
+        let expected = "Invalid option `maxDepth`: expected an integer from 0 to 9007199254740991";
+
+        assert_eq!(limits(0.0).unwrap(), Some(0));
+        assert_eq!(limits(MAX_LIMIT).unwrap(), Some(9_007_199_254_740_991));
+
+        // Negative
+        let err = limits(-1.0).unwrap_err();
+        assert_eq!(err.status, Status::InvalidArg);
+        assert_eq!(err.reason, format!("{expected}, got -1"));
+
+        // Not an integer
+        let err = limits(2.5).unwrap_err();
+        assert_eq!(err.status, Status::InvalidArg);
+        assert_eq!(err.reason, format!("{expected}, got 2.5"));
+        assert_eq!(limits(f64::NAN).unwrap_err().reason, format!("{expected}, got NaN"));
+
+        // Out of range
+        let err = limits(MAX_LIMIT + 1.0).unwrap_err();
+        assert_eq!(err.status, Status::InvalidArg);
+        assert_eq!(err.reason, format!("{expected}, got 9007199254740992"));
+        assert_eq!(limits(f64::INFINITY).unwrap_err().reason, format!("{expected}, got inf"));
+
+        // Every limit is checked
+        let opts = Options {
+            max_nodes: Some(-5.0),
+            ..options()
+        };
+        let err = to_napi_error(CoreOptions::try_from(opts).unwrap_err());
+        assert!(err.reason.starts_with("Invalid option `maxNodes`"));
+    }
+
+    #[test]
+    fn test_unknown_options() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(unknown_option(names(&["headingStyle", "maxDepth", "limitMode"])), None);
+        assert_eq!(unknown_option(names(OPTION_NAMES)), None);
+        assert_eq!(
+            unknown_option(names(&["headingStyle", "maxdepth"])).as_deref(),
+            Some("maxdepth")
+        );
+        assert_eq!(
+            unknown_option(names(&["heading_style"])).as_deref(),
+            Some("heading_style")
+        );
+    }
+}
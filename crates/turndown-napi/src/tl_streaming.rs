@@ -2,304 +2,1194 @@
 //!
 //! Uses tl for fast DOM parsing with DOM traversal for AST building.
 
-use smallvec::SmallVec;
-use tl::{HTMLTag, Node, NodeHandle, Parser, ParserOptions, VDom};
-use turndown_core::{Block, Inline, ListItem, Options};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use smallvec::{smallvec, SmallVec};
+use tl::{HTMLTag, Node, NodeHandle, Parser, ParserOptions};
+use turndown_core::{
+    expand_table_spans, truncate_str, Alignment, Block, CollapsedText, Content, Element, Inline,
+    LimitCounter, Limits, ListItem, MarkupStyle, Options, Replacement, Result, Rule, Rules,
+    SpannedCell, SpannedRow, TableFallback, TableSpans, TurndownError, WhitespaceCollapser,
+    WhitespaceKind, BLOCK_ELEMENTS, VOID_ELEMENTS,
+};
+
+use crate::entities::{decode_attribute, decode_text};
 
 // Most inline elements have few children - avoid heap allocation
 type InlineVec = SmallVec<[Inline; 4]>;
 
-/// Convert HTML string to Markdown AST using tl parser
-pub fn html_to_ast(html: &str, _options: &Options) -> Block {
-    let dom = tl::parse(html, ParserOptions::default()).expect("HTML parse error");
-    let parser = dom.parser();
+/// Table cell content and alignment, before span expansion
+type TableCell = SpannedCell<(Vec<Inline>, Alignment)>;
 
-    let children = dom.children();
-    let blocks = process_nodes(&dom, parser, children);
+/// Table row of [`TableCell`]s, before span expansion
+type TableRow = SpannedRow<(Vec<Inline>, Alignment)>;
 
-    if blocks.is_empty() {
-        Block::Document(vec![])
-    } else if blocks.len() == 1 {
-        blocks.into_iter().next().unwrap()
-    } else {
-        Block::Document(blocks)
+/// Custom rules and element filters registered on the service
+///
+/// Custom rules take precedence over built-in conversion. Like turndown.js,
+/// keep/remove filters only apply to elements that no rule converts
+/// (e.g. `keep("del")` works, `keep("p")` has no effect).
+#[derive(Debug, Default, Clone)]
+pub struct Filters {
+    /// Custom rules, most recently added first
+    pub rules: Rules,
+    /// Lowercase tag names emitted as raw HTML
+    pub keep: Vec<String>,
+    /// Lowercase tag names dropped together with their content
+    pub remove: Vec<String>,
+}
+
+/// Rules and filters that live outside Rust (JavaScript `addRule`, and
+/// predicates passed to `keep()` and `remove()`)
+///
+/// Replacements follow turndown.js: they receive the Markdown of the element's
+/// content and return Markdown that is inserted verbatim. They are consulted
+/// after the Rust rules in [`Filters::rules`], and the predicates along with
+/// the tag names in [`Filters::keep`] and [`Filters::remove`].
+pub trait CallbackRules {
+    /// Cheap pre-check so non-matching elements never leave Rust
+    fn may_match(&self, tag_lower: &str) -> bool;
+
+    /// Index of the first rule matching the element
+    fn find(&self, node: &CallbackNode) -> Option<usize>;
+
+    /// Run the replacement of the rule at `index`
+    fn replace(&self, index: usize, content: &str, node: &CallbackNode) -> String;
+
+    /// Whether keep or remove predicates are registered
+    fn has_filters(&self) -> bool;
+
+    /// Whether a keep predicate matches the element
+    fn keeps(&self, node: &CallbackNode) -> bool;
+
+    /// Whether a remove predicate matches the element
+    fn removes(&self, node: &CallbackNode) -> bool;
+}
+
+/// Element description handed to callback rules
+///
+/// Text content, source markup and ancestors are looked up on demand in a
+/// source index built once per conversion, so describing an element does not
+/// traverse it. The index shares the input instead of copying it; clones stay
+/// valid after the conversion.
+#[derive(Clone)]
+pub struct CallbackNode {
+    /// Lowercase tag name
+    pub tag: String,
+    /// Attributes as (name, value) pairs in source order
+    pub attributes: Vec<(String, String)>,
+    pub is_block: bool,
+    source: Rc<CallbackSource>,
+    key: usize,
+}
+
+impl CallbackNode {
+    /// Get an attribute value by name (case-insensitive)
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Text of the element after whitespace collapsing
+    pub fn text_content(&self) -> String {
+        let source = &self.source;
+        let element = source.element(self.key);
+        let mut text = String::new();
+        for node in &source.texts[element.text.clone()] {
+            match source.whitespace.get(node.key) {
+                Some(collapsed) => text.push_str(collapsed),
+                // Inside `pre`: decoded verbatim
+                None => {
+                    let raw = source.html.get(node.raw.clone()).unwrap_or("");
+                    text.push_str(&decode_text(raw));
+                }
+            }
+        }
+        text
+    }
+
+    /// Original source markup of the element
+    pub fn outer_html(&self) -> &str {
+        let element = self.source.element(self.key);
+        &self.source.html[element.outer.clone()]
+    }
+
+    /// Lowercase tag names of the enclosing elements, outermost first
+    pub fn ancestors(&self) -> Vec<String> {
+        let source = &self.source;
+        let mut ancestors = Vec::new();
+        let mut parent = source.element(self.key).parent;
+        while let Some(key) = parent {
+            let element = source.element(key);
+            ancestors.push(element.tag.clone());
+            parent = element.parent;
+        }
+        ancestors.reverse();
+        ancestors
     }
 }
 
-fn process_nodes(dom: &VDom, parser: &Parser, handles: &[NodeHandle]) -> Vec<Block> {
-    let mut blocks = Vec::new();
+/// Document text and markup shared by the callback nodes of one conversion
+///
+/// Built in a single pass before converting, so callback rules cost the same
+/// for every element however deep the document is. Elements refer to ranges
+/// of the input and of its text nodes, which are only read when a callback
+/// asks for them.
+struct CallbackSource {
+    /// The converted input
+    html: Rc<String>,
+    /// Text nodes after whitespace collapsing (see [`Context::text`])
+    whitespace: Rc<CollapsedText>,
+    /// Text nodes in document order
+    texts: Vec<SourceText>,
+    /// Elements by node index
+    elements: Vec<Option<SourceElement>>,
+}
+
+struct SourceText {
+    /// Node index, for [`CollapsedText`] lookups
+    key: usize,
+    /// Byte range of the raw text in `html`
+    raw: Range<usize>,
+}
+
+struct SourceElement {
+    tag: String,
+    parent: Option<usize>,
+    /// Byte range of the element in `html`
+    outer: Range<usize>,
+    /// Range of the element's text nodes in `texts`
+    text: Range<usize>,
+    /// Blank in the turndown.js sense (see [`is_blank`])
+    blank: bool,
+}
 
-    for handle in handles {
-        if let Some(node) = handle.get(parser) {
-            match node {
-                Node::Tag(tag) => {
-                    if let Some(block) = process_element(dom, parser, tag) {
-                        blocks.push(block);
+impl CallbackSource {
+    /// Index the elements of `roots`, parsed from `html`, a prefix of `input`
+    fn new(ctx: &Context, roots: &[NodeHandle], html: &str, input: Rc<String>) -> Self {
+        let mut texts = Vec::new();
+        let mut elements = Vec::new();
+        // Non-blank text nodes and meaningful elements seen so far
+        let mut filled = 0;
+        // Open elements: key, text start, `filled` on entry
+        let mut open: Vec<(usize, usize, usize)> = Vec::new();
+        // Elements are visited again when leaving them
+        let mut stack: Vec<(NodeHandle, bool)> = roots.iter().rev().map(|&h| (h, false)).collect();
+
+        while let Some((handle, leaving)) = stack.pop() {
+            match handle.get(ctx.parser) {
+                Some(node @ Node::Raw(bytes)) => {
+                    if !ctx.text(handle).trim().is_empty() {
+                        filled += 1;
                     }
+                    let start = source_offset(node, html);
+                    texts.push(SourceText {
+                        key: node_index(handle),
+                        raw: start..start + bytes.as_bytes().len(),
+                    });
                 }
-                Node::Raw(text) => {
-                    let text_str = text.as_utf8_str();
-                    if !text_str.trim().is_empty() {
-                        // Text at root level becomes paragraph
-                        let processed = collapse_and_escape(&text_str);
-                        if !processed.trim().is_empty() {
-                            blocks.push(Block::Paragraph(vec![Inline::Text(processed)]));
+                Some(node @ Node::Tag(tag)) => {
+                    let name = tag.name().as_utf8_str().to_ascii_lowercase();
+                    if !leaving {
+                        open.push((node_index(handle), texts.len(), filled));
+                        if is_meaningful_when_blank(&name) {
+                            filled += 1;
                         }
+                        stack.push((handle, true));
+                        let children = tag.children();
+                        stack.extend(children.top().as_slice().iter().rev().map(|&h| (h, false)));
+                        continue;
                     }
+                    let Some((key, text_start, filled_start)) = open.pop() else {
+                        continue;
+                    };
+                    let start = source_offset(node, html);
+                    let end = (start + tag.raw().as_bytes().len()).min(html.len());
+                    if elements.len() <= key {
+                        elements.resize_with(key + 1, || None);
+                    }
+                    elements[key] = Some(SourceElement {
+                        blank: filled == filled_start,
+                        tag: name,
+                        parent: open.last().map(|&(key, ..)| key),
+                        outer: start..end,
+                        text: text_start..texts.len(),
+                    });
                 }
                 _ => {}
             }
         }
+
+        Self {
+            html: input,
+            whitespace: Rc::clone(&ctx.whitespace),
+            texts,
+            elements,
+        }
     }
 
-    blocks
+    /// An indexed element; callback nodes only refer to indexed elements
+    fn element(&self, key: usize) -> &SourceElement {
+        self.elements[key].as_ref().expect("callback nodes refer to indexed elements")
+    }
 }
 
-fn process_element(dom: &VDom, parser: &Parser, tag: &HTMLTag) -> Option<Block> {
-    let tag_name = tag.name().as_utf8_str();
-    let tag_lower = tag_name.to_ascii_lowercase();
+/// Conversion state shared by the traversal functions
+struct Context<'p, 'a> {
+    parser: &'p Parser<'a>,
+    options: &'p Options,
+    filters: &'p Filters,
+    callbacks: Option<&'p dyn CallbackRules>,
+    /// Source index for callback nodes (only built for callback rules)
+    source: Option<Rc<CallbackSource>>,
+    /// Text nodes after cross-node whitespace collapsing
+    whitespace: Rc<CollapsedText>,
+    /// Tag names of the elements being converted (only tracked for custom rules)
+    ancestors: RefCell<Vec<String>>,
+    /// Set from another thread to stop the conversion
+    abort: Option<&'p AtomicBool>,
+}
 
-    match tag_lower.as_str() {
-        "p" => {
-            let inlines = collect_inlines(dom, parser, tag);
-            if inlines.is_empty() {
-                None
-            } else {
-                Some(Block::Paragraph(inlines))
-            }
+impl Context<'_, '_> {
+    /// Content of a text node: collapsed, or decoded verbatim inside `pre`
+    fn text(&self, handle: NodeHandle) -> Cow<'_, str> {
+        if let Some(text) = self.whitespace.get(node_index(handle)) {
+            return Cow::Borrowed(text);
         }
-        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-            let level = tag_lower.chars().nth(1).and_then(|c| c.to_digit(10)).unwrap_or(1) as u8;
-            let inlines = collect_inlines(dom, parser, tag);
-            if inlines.is_empty() {
-                None
-            } else {
-                Some(Block::Heading { level, content: inlines })
-            }
+        match handle.get(self.parser) {
+            Some(Node::Raw(bytes)) => match bytes.as_utf8_str() {
+                Cow::Borrowed(raw) => decode_text(raw),
+                Cow::Owned(raw) => Cow::Owned(decode_text(&raw).into_owned()),
+            },
+            _ => Cow::Borrowed(""),
         }
-        "blockquote" => {
-            let children = tag.children();
-            let inner_blocks = process_nodes(dom, parser, children.top().as_slice());
-            if inner_blocks.is_empty() {
-                // Try to get text content directly
-                let inlines = collect_inlines(dom, parser, tag);
-                if inlines.is_empty() {
-                    None
-                } else {
-                    Some(Block::BlockQuote(vec![Block::Paragraph(inlines)]))
-                }
-            } else {
-                Some(Block::BlockQuote(inner_blocks))
+    }
+
+    fn aborted(&self) -> bool {
+        self.abort.is_some_and(|abort| abort.load(Ordering::Relaxed))
+    }
+
+    fn has_rules(&self) -> bool {
+        !self.filters.rules.is_empty() || self.callbacks.is_some()
+    }
+}
+
+/// Convert HTML string to Markdown AST using tl parser
+///
+/// The input is checked against `options.limits` first; when truncating, the
+/// HTML is cut in front of the first node over a limit and parsed again.
+pub fn html_to_ast(html: &str, options: &Options, filters: &Filters) -> Result<Block> {
+    convert_html(html, options, filters, None, None)
+}
+
+/// Convert HTML string to Markdown AST, consulting callback rules
+///
+/// The [`CallbackNode`]s handed to `callbacks` share `html` to look up the
+/// markup and text of elements when asked for them.
+pub fn html_to_ast_with_callbacks(
+    html: Rc<String>,
+    options: &Options,
+    filters: &Filters,
+    callbacks: &dyn CallbackRules,
+) -> Result<Block> {
+    convert_html(&html, options, filters, Some((callbacks, &html)), None)
+}
+
+/// Convert HTML string to Markdown AST until `abort` is set
+///
+/// The flag is checked between subtrees, so a conversion running on another
+/// thread stops with [`TurndownError::Aborted`] soon after it is set.
+pub fn html_to_ast_abortable(
+    html: &str,
+    options: &Options,
+    filters: &Filters,
+    abort: &AtomicBool,
+) -> Result<Block> {
+    convert_html(html, options, filters, None, Some(abort))
+}
+
+/// Convert `html`; callback rules come with the input they describe elements of
+fn convert_html(
+    html: &str,
+    options: &Options,
+    filters: &Filters,
+    callbacks: Option<(&dyn CallbackRules, &Rc<String>)>,
+    abort: Option<&AtomicBool>,
+) -> Result<Block> {
+    let limits = &options.limits;
+    let mut html = html;
+    if let Some(max) = limits.max_input_bytes.filter(|&max| html.len() > max) {
+        limits.exceeded(TurndownError::LimitExceeded {
+            limit: "input size",
+            max,
+        })?;
+        html = truncate_html(html, max);
+    }
+
+    loop {
+        let dom = tl::parse(html, ParserOptions::default())
+            .map_err(|err| TurndownError::Parse(err.to_string()))?;
+        if limits.limits_tree() {
+            if let Some(end) = find_limit(dom.parser(), dom.children(), html, limits)? {
+                html = &html[..end];
+                continue;
             }
         }
-        "ul" | "ol" => {
-            let ordered = tag_lower == "ol";
-            let start = tag.attributes()
-                .get("start")
-                .flatten()
-                .and_then(|s| s.as_utf8_str().parse().ok())
-                .unwrap_or(1);
+        return dom_to_ast(&dom, html, options, filters, callbacks, abort);
+    }
+}
 
-            let items = collect_list_items(dom, parser, tag);
-            if items.is_empty() {
-                None
-            } else {
-                Some(Block::List { ordered, start, items })
-            }
+/// Cut HTML to at most `max` bytes, dropping a tag left open by the cut
+fn truncate_html(html: &str, max: usize) -> &str {
+    let html = truncate_str(html, max);
+    match (html.rfind('<'), html.rfind('>')) {
+        (Some(open), close) if close.is_none_or(|close| close < open) => &html[..open],
+        _ => html,
+    }
+}
+
+/// Walk the DOM in document order and return the source offset of the first
+/// node over a limit, or the limit error when not truncating
+fn find_limit(
+    parser: &Parser,
+    roots: &[NodeHandle],
+    html: &str,
+    limits: &Limits,
+) -> Result<Option<usize>> {
+    let mut counter = LimitCounter::new(limits);
+    let mut stack: Vec<(NodeHandle, usize)> = roots.iter().rev().map(|&h| (h, 1)).collect();
+
+    while let Some((handle, depth)) = stack.pop() {
+        let Some(node) = handle.get(parser) else {
+            continue;
+        };
+        if let Some(err) = counter.visit(depth, 0) {
+            limits.exceeded(err)?;
+            return Ok(Some(source_offset(node, html)));
         }
-        "pre" => {
-            // Look for code element inside
-            let (code, lang) = extract_code_content(dom, parser, tag);
-            Some(Block::CodeBlock {
-                language: lang,
-                code,
-                fenced: false, // Let options.code_block_style decide
-            })
+        if let Node::Tag(tag) = node {
+            stack.extend(tag.children().top().as_slice().iter().rev().map(|&h| (h, depth + 1)));
         }
-        "hr" => Some(Block::ThematicBreak),
-        // Tables: turndown JS extracts text content (table-to-markdown is a GFM plugin)
-        "table" | "thead" | "tbody" | "tfoot" | "tr" => {
-            let children = tag.children();
-            let inner_blocks = process_nodes(dom, parser, children.top().as_slice());
-            match inner_blocks.len() {
-                0 => {
-                    let inlines = collect_inlines(dom, parser, tag);
-                    if inlines.is_empty() { None } else { Some(Block::Paragraph(inlines)) }
+    }
+    Ok(None)
+}
+
+/// Byte offset at which a node starts in the parsed HTML
+fn source_offset(node: &Node, html: &str) -> usize {
+    let raw = match node {
+        Node::Tag(tag) => tag.raw(),
+        Node::Raw(bytes) | Node::Comment(bytes) => bytes,
+    };
+    raw.as_bytes_borrowed()
+        .map(|bytes| (bytes.as_ptr() as usize).wrapping_sub(html.as_ptr() as usize))
+        .filter(|&offset| offset < html.len())
+        .unwrap_or(0)
+}
+
+/// Convert a parsed document to Markdown AST
+fn dom_to_ast(
+    dom: &tl::VDom,
+    html: &str,
+    options: &Options,
+    filters: &Filters,
+    callbacks: Option<(&dyn CallbackRules, &Rc<String>)>,
+    abort: Option<&AtomicBool>,
+) -> Result<Block> {
+    let children = dom.children();
+    let mut ctx = Context {
+        parser: dom.parser(),
+        options,
+        filters,
+        callbacks: callbacks.map(|(callbacks, _)| callbacks),
+        source: None,
+        whitespace: Rc::new(collapse_whitespace(dom, children)),
+        ancestors: RefCell::new(Vec::new()),
+        abort,
+    };
+    if let Some((_, input)) = callbacks {
+        let source = CallbackSource::new(&ctx, children, html, Rc::clone(input));
+        ctx.source = Some(Rc::new(source));
+    }
+
+    let roots = Children::new(Parent::Roots(children), false, Finish::Document);
+    let root = Frame::new(&ctx, &[], Work::Children(roots));
+    Ok(run(&ctx, root)?.unwrap_or(Block::Document(vec![])))
+}
+
+/// Collapse whitespace across text nodes in document order, like turndown's
+/// `collapseWhitespace` (see [`WhitespaceCollapser`])
+fn collapse_whitespace(dom: &tl::VDom, roots: &[NodeHandle]) -> CollapsedText {
+    let parser = dom.parser();
+    let mut collapser = WhitespaceCollapser::with_capacity(dom.nodes().len());
+    // Elements are visited again when leaving them
+    let mut stack: Vec<(NodeHandle, bool)> = roots.iter().rev().map(|&h| (h, false)).collect();
+
+    while let Some((handle, leaving)) = stack.pop() {
+        match handle.get(parser) {
+            Some(Node::Raw(text)) => {
+                collapser.text(node_index(handle), &decode_text(&text.as_utf8_str()));
+            }
+            Some(Node::Tag(tag)) => {
+                let name = tag.name().as_utf8_str().to_ascii_lowercase();
+                collapser.element(WhitespaceKind::of(&name));
+                if !leaving && name != "pre" {
+                    stack.push((handle, true));
+                    stack.extend(tag.children().top().as_slice().iter().rev().map(|&h| (h, false)));
                 }
-                1 => Some(inner_blocks.into_iter().next().unwrap()),
-                _ => Some(Block::Document(inner_blocks)),
             }
+            _ => {}
         }
-        "th" | "td" => {
-            let inlines = collect_inlines(dom, parser, tag);
-            if inlines.is_empty() {
-                None
-            } else {
-                Some(Block::Paragraph(inlines))
+    }
+
+    collapser.finish()
+}
+
+/// Index of a node in the parser, for [`CollapsedText`] lookups
+fn node_index(handle: NodeHandle) -> usize {
+    handle.get_inner() as usize
+}
+
+/// Sibling nodes converted together: the top-level nodes of the document or
+/// the children of an element
+#[derive(Clone, Copy)]
+enum Parent<'c> {
+    Roots(&'c [NodeHandle]),
+    Tag(&'c HTMLTag<'c>),
+}
+
+impl Parent<'_> {
+    fn child(&self, index: usize) -> Option<NodeHandle> {
+        match self {
+            Parent::Roots(handles) => handles.get(index).copied(),
+            Parent::Tag(tag) => tag.children().top().get(index).copied(),
+        }
+    }
+}
+
+/// Converted children: blocks, or inlines in inline context
+#[derive(Default)]
+struct Output {
+    blocks: Vec<Block>,
+    inlines: Vec<Inline>,
+}
+
+/// Conversion of a node, possibly waiting for children to be converted
+///
+/// Rather than recursing into the children, a conversion returns a
+/// [`Step::Visit`] with a [`Frame`] that converts them and builds the result.
+/// [`run`] keeps the frames on an explicit stack, so the nesting depth of a
+/// document is not limited by the call stack.
+enum Step<'c, T> {
+    Done(T),
+    Visit(Frame<'c>),
+}
+
+/// Result of a frame, handed to the frame below it
+enum Converted {
+    Block(Option<Block>),
+    Inline(Option<Inline>),
+    Inlines(Vec<Inline>),
+    Item(ListItem),
+    Cell(TableCell),
+    /// A pipe table row, and whether it qualifies as a heading row
+    Row(TableRow, bool),
+    Caption(Option<Vec<Inline>>),
+}
+
+/// Conversion waiting for children on the stack of [`run`]
+struct Frame<'c> {
+    work: Work<'c>,
+    /// Tags to record as ancestors while the frame is on the stack (only
+    /// tracked for custom rules)
+    ancestors: Vec<String>,
+    /// Length of the ancestor stack below the frame
+    depth: usize,
+}
+
+impl<'c> Frame<'c> {
+    /// A frame within the elements of `scope`, which are ancestors for the
+    /// rules matched while it is converted
+    fn new(ctx: &Context, scope: &[&str], work: Work<'c>) -> Self {
+        let ancestors = if ctx.has_rules() {
+            scope.iter().map(|tag| tag.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+        Frame {
+            work,
+            ancestors,
+            depth: 0,
+        }
+    }
+
+    /// Record the ancestors of the frame as it goes on the stack
+    fn enter(mut self, ctx: &Context) -> Self {
+        let mut ancestors = ctx.ancestors.borrow_mut();
+        self.depth = ancestors.len();
+        ancestors.append(&mut self.ancestors);
+        self
+    }
+}
+
+/// What a frame converts
+enum Work<'c> {
+    Children(Children<'c>),
+    List(List<'c>),
+    Table(Table<'c>),
+    Row(Row<'c>),
+}
+
+impl<'c> Work<'c> {
+    /// Convert until a child has to be visited, or return `None` when done
+    fn advance(&mut self, ctx: &'c Context<'c, 'c>) -> Option<Frame<'c>> {
+        match self {
+            Work::Children(children) => children.advance(ctx),
+            Work::List(list) => list.advance(ctx),
+            Work::Table(table) => table.advance(ctx),
+            Work::Row(row) => row.advance(ctx),
+        }
+    }
+
+    /// Take the result of a visited child
+    fn accept(&mut self, converted: Converted) {
+        match (self, converted) {
+            (Work::Children(children), Converted::Block(block)) => {
+                children.output.blocks.extend(block)
+            }
+            (Work::Children(children), Converted::Inline(inline)) => {
+                children.output.inlines.extend(inline)
             }
+            (Work::Children(children), Converted::Inlines(inlines)) => {
+                children.output.inlines.extend(inlines)
+            }
+            (Work::List(list), Converted::Item(item)) => list.items.push(item),
+            (Work::Table(table), Converted::Caption(caption)) => table.converted_caption = caption,
+            (Work::Table(table), Converted::Row(row, heading)) => {
+                table.pipe_rows.push((row, heading))
+            }
+            (Work::Table(table), Converted::Item(row)) => table.list_rows.push(row),
+            (Work::Row(row), Converted::Cell(cell)) => row.pipe_cells.push(cell),
+            (Work::Row(row), Converted::Item(cell)) => row.list_cells.push(cell),
+            _ => unreachable!("frames only visit children whose results they take"),
         }
-        "div" | "section" | "article" | "main" | "aside" | "header" | "footer" | "nav" | "figure" | "body" | "html" => {
-            // Container elements - process children
-            let children = tag.children();
-            let inner_blocks = process_nodes(dom, parser, children.top().as_slice());
-            match inner_blocks.len() {
-                0 => {
-                    // Maybe just text content?
-                    let inlines = collect_inlines(dom, parser, tag);
-                    if inlines.is_empty() {
-                        None
-                    } else {
-                        Some(Block::Paragraph(inlines))
-                    }
-                }
-                1 => Some(inner_blocks.into_iter().next().unwrap()),
-                _ => Some(Block::Document(inner_blocks)),
+    }
+
+    fn finish(self, ctx: &Context) -> Converted {
+        match self {
+            Work::Children(children) => children.finish(ctx),
+            Work::List(list) => list.finish(),
+            Work::Table(table) => table.finish(ctx),
+            Work::Row(row) => row.finish(),
+        }
+    }
+
+    /// Drop what was converted so far without recursion
+    fn discard(self) {
+        let (mut blocks, inlines, items) = match self {
+            Work::Children(children) => (children.output.blocks, children.output.inlines, vec![]),
+            Work::List(list) => (vec![], vec![], list.items),
+            Work::Table(table) => {
+                let cells = table.pipe_rows.into_iter().flat_map(|(row, _)| row.cells);
+                let caption = table.converted_caption.into_iter().flatten();
+                let inlines = cells.flat_map(|cell| cell.value.0).chain(caption);
+                (vec![], inlines.collect(), table.list_rows)
+            }
+            Work::Row(row) => {
+                let inlines = row.pipe_cells.into_iter().flat_map(|cell| cell.value.0);
+                (vec![], inlines.collect(), row.list_cells)
             }
+        };
+        blocks.push(Block::Paragraph(inlines));
+        blocks.push(Block::List {
+            ordered: false,
+            start: 1,
+            items,
+        });
+        Block::Document(blocks).drop_iteratively();
+    }
+}
+
+/// Children of an element (or the top-level nodes of the document)
+struct Children<'c> {
+    parent: Parent<'c>,
+    inline: bool,
+    next: usize,
+    output: Output,
+    finish: Finish<'c>,
+}
+
+/// What an element builds from its converted children
+enum Finish<'c> {
+    /// The document: its single block, or all of them
+    Document,
+    Paragraph,
+    Heading(u8),
+    BlockQuote,
+    /// An element holding blocks and paragraphs
+    Container,
+    /// `head` or `title`: falls back to the text content
+    Head(&'c HTMLTag<'c>),
+    ListItem(Option<bool>),
+    /// Inline element wrapping its content, dropped if it is empty
+    Wrap(fn(Vec<Inline>) -> Inline),
+    Link {
+        url: String,
+        title: Option<String>,
+    },
+    /// Inline element passing its content through
+    Inlines,
+    Cell {
+        alignment: Alignment,
+        colspan: usize,
+        rowspan: usize,
+    },
+    /// Cell of a table laid out as a list
+    CellItem,
+    Caption,
+    Rule {
+        rule: &'c Rule,
+        element: Box<Element>,
+        target: Target,
+    },
+    Callback {
+        callbacks: &'c dyn CallbackRules,
+        index: usize,
+        node: Box<CallbackNode>,
+        target: Target,
+    },
+}
+
+/// Where the replacement of a rule goes
+#[derive(Clone, Copy)]
+enum Target {
+    Block,
+    Inlines,
+    ListItem,
+}
+
+impl Target {
+    fn convert(self, replacement: Replacement, options: &Options) -> Converted {
+        match self {
+            Target::Block => Converted::Block(replacement.into_block()),
+            Target::Inlines => Converted::Inlines(replacement.into_inlines(options)),
+            Target::ListItem => Converted::Item(ListItem::new(replacement.into_blocks())),
         }
-        "a" => {
-            // Standalone link at block level
-            let inlines = vec![process_link(dom, parser, tag)];
-            Some(Block::Paragraph(inlines))
-        }
-        "img" => {
-            // Standalone image at block level
-            if let Some(inline) = process_image(tag) {
-                Some(Block::Paragraph(vec![inline]))
-            } else {
-                None
+    }
+}
+
+impl<'c> Children<'c> {
+    fn new(parent: Parent<'c>, inline: bool, finish: Finish<'c>) -> Self {
+        Children {
+            parent,
+            inline,
+            next: 0,
+            output: Output::default(),
+            finish,
+        }
+    }
+
+    fn next_child(&mut self, parser: &'c Parser<'c>) -> Option<(NodeHandle, &'c Node<'c>)> {
+        while let Some(handle) = self.parent.child(self.next) {
+            self.next += 1;
+            if let Some(node) = handle.get(parser) {
+                return Some((handle, node));
             }
         }
-        "script" | "style" | "noscript" | "template" | "meta" | "link" => None,
-        "head" | "title" => {
-            // Extract text content (turndown JS converts <title> to text)
-            let children = tag.children();
-            let inner_blocks = process_nodes(dom, parser, children.top().as_slice());
-            if !inner_blocks.is_empty() {
-                match inner_blocks.len() {
-                    1 => Some(inner_blocks.into_iter().next().unwrap()),
-                    _ => Some(Block::Document(inner_blocks)),
+        None
+    }
+
+    /// Convert children until one has to visit children of its own
+    ///
+    /// In block context, consecutive text and phrasing elements are collected
+    /// into one paragraph, which ends at the next block.
+    fn advance(&mut self, ctx: &'c Context<'c, 'c>) -> Option<Frame<'c>> {
+        while let Some((handle, node)) = self.next_child(ctx.parser) {
+            if self.inline || is_inline_node(node) {
+                match collect_inline_node(ctx, handle, node) {
+                    Step::Done(inlines) => self.output.inlines.extend(inlines),
+                    Step::Visit(frame) => return Some(frame),
                 }
             } else {
-                let text = get_text_content(dom, parser, tag);
-                let trimmed = text.trim().to_string();
-                if trimmed.is_empty() {
-                    None
-                } else {
-                    Some(Block::Paragraph(vec![Inline::Text(trimmed)]))
+                self.end_paragraph();
+                match process_node(ctx, handle, node) {
+                    Step::Done(block) => self.output.blocks.extend(block),
+                    Step::Visit(frame) => return Some(frame),
                 }
             }
         }
-        // Handle inline elements at root level by wrapping in paragraph
-        "strong" | "b" | "em" | "i" | "code" | "span" | "small" | "sub" | "sup" | "mark" | "del" | "ins" | "u" => {
-            let mut inlines = InlineVec::new();
-            collect_inline_from_tag(dom, parser, tag, &mut inlines);
-            if inlines.is_empty() {
-                None
-            } else {
-                Some(Block::Paragraph(inlines.into_vec()))
+        None
+    }
+
+    /// Add the inlines collected in block context as a paragraph
+    fn end_paragraph(&mut self) {
+        if self.output.inlines.is_empty() {
+            return;
+        }
+        let mut inlines = std::mem::take(&mut self.output.inlines);
+        trim_inlines(&mut inlines);
+        self.output.blocks.extend(paragraph(inlines));
+    }
+
+    fn finish(mut self, ctx: &Context) -> Converted {
+        if self.inline {
+            // Trim leading/trailing whitespace from inline content of blocks
+            // (matches browser innerText behavior); inline elements keep it,
+            // so it can be moved outside their delimiters when serializing
+            if !matches!(self.finish, Finish::Wrap(_) | Finish::Link { .. } | Finish::Inlines) {
+                trim_inlines(&mut self.output.inlines);
             }
+        } else {
+            self.end_paragraph();
         }
-        _ => {
-            // Unknown element - try to extract content
-            let children = tag.children();
-            let inner_blocks = process_nodes(dom, parser, children.top().as_slice());
-            if !inner_blocks.is_empty() {
-                if inner_blocks.len() == 1 {
-                    Some(inner_blocks.into_iter().next().unwrap())
-                } else {
-                    Some(Block::Document(inner_blocks))
-                }
+        let Output { mut blocks, inlines } = self.output;
+
+        match self.finish {
+            Finish::Document => Converted::Block(Some(if blocks.len() == 1 {
+                blocks.pop().unwrap()
+            } else {
+                Block::Document(blocks)
+            })),
+            Finish::Paragraph => Converted::Block(paragraph(inlines)),
+            Finish::Heading(level) => Converted::Block(if inlines.is_empty() {
+                None
             } else {
-                let inlines = collect_inlines(dom, parser, tag);
-                if inlines.is_empty() {
-                    None
-                } else {
-                    Some(Block::Paragraph(inlines))
+                Some(Block::Heading { level, content: inlines })
+            }),
+            Finish::BlockQuote => {
+                Converted::Block((!blocks.is_empty()).then_some(Block::BlockQuote(blocks)))
+            }
+            Finish::Container => Converted::Block(match blocks.len() {
+                0 => None,
+                1 => blocks.pop(),
+                _ => Some(Block::Document(blocks)),
+            }),
+            Finish::Head(tag) => Converted::Block(match blocks.len() {
+                0 => {
+                    let text = get_text_content(ctx, tag);
+                    let trimmed = text.trim();
+                    (!trimmed.is_empty())
+                        .then(|| Block::Paragraph(vec![Inline::Text(trimmed.to_string())]))
                 }
+                1 => blocks.pop(),
+                _ => Some(Block::Document(blocks)),
+            }),
+            Finish::ListItem(checked) => {
+                Converted::Item(ListItem::new(blocks).with_checked(checked))
+            }
+            Finish::Wrap(wrap) => Converted::Inline((!inlines.is_empty()).then(|| wrap(inlines))),
+            Finish::Link { url, title } => Converted::Inline(Some(Inline::Link {
+                content: inlines,
+                url,
+                title,
+            })),
+            Finish::Inlines => Converted::Inlines(inlines),
+            Finish::Cell { alignment, colspan, rowspan } => Converted::Cell(SpannedCell {
+                value: (inlines, alignment),
+                colspan,
+                rowspan,
+            }),
+            Finish::CellItem => Converted::Item(ListItem::new(blocks)),
+            Finish::Caption => Converted::Caption((!inlines.is_empty()).then_some(inlines)),
+            Finish::Rule { rule, element, target } => {
+                let content = rule_content(self.inline, blocks, inlines);
+                target.convert(rule.replace(&element, content), ctx.options)
+            }
+            Finish::Callback { callbacks, index, node, target } => {
+                let content = match rule_content(self.inline, blocks, inlines) {
+                    Content::Blocks(blocks) => Block::Document(blocks),
+                    Content::Inlines(inlines) => Block::Paragraph(inlines),
+                };
+                let markdown = turndown_core::serialize_fragment(&content, ctx.options);
+                content.drop_iteratively();
+                let markdown = callbacks.replace(index, &markdown, &node);
+
+                let replacement = match target {
+                    Target::Inlines => Replacement::Inlines(vec![Inline::HtmlInline(markdown)]),
+                    // The Markdown stands for the whole item, marker included
+                    Target::ListItem => {
+                        let markdown = markdown.trim_matches('\n').to_string();
+                        return Converted::Item(ListItem::verbatim(markdown));
+                    }
+                    Target::Block => {
+                        let markdown = markdown.trim_matches('\n');
+                        if markdown.trim().is_empty() {
+                            Replacement::remove()
+                        } else {
+                            Replacement::Blocks(vec![Block::HtmlBlock(markdown.to_string())])
+                        }
+                    }
+                };
+                target.convert(replacement, ctx.options)
             }
         }
     }
 }
 
-/// Process a single inline tag and add to inlines vector
-fn collect_inline_from_tag(dom: &VDom, parser: &Parser, tag: &HTMLTag, inlines: &mut InlineVec) {
-    let tag_name = tag.name().as_utf8_str();
-    let tag_lower = tag_name.to_ascii_lowercase();
+/// Items of a list, converted one after another
+struct List<'c> {
+    lis: Vec<(NodeHandle, &'c HTMLTag<'c>)>,
+    next: usize,
+    ordered: bool,
+    start: u32,
+    items: Vec<ListItem>,
+}
 
-    match tag_lower.as_str() {
-        "strong" | "b" => {
-            let inner = collect_inlines(dom, parser, tag);
-            if !inner.is_empty() {
-                inlines.push(Inline::Strong(inner));
-            }
+impl<'c> List<'c> {
+    fn new(ctx: &Context<'c, 'c>, tag: &'c HTMLTag<'c>, ordered: bool, start: u32) -> Self {
+        let lis = tag
+            .children()
+            .top()
+            .iter()
+            .filter_map(|&handle| match handle.get(ctx.parser)? {
+                Node::Tag(li) if li.name().as_utf8_str().eq_ignore_ascii_case("li") => {
+                    Some((handle, li))
+                }
+                _ => None,
+            })
+            .collect();
+        List {
+            lis,
+            next: 0,
+            ordered,
+            start,
+            items: Vec::new(),
         }
-        "em" | "i" => {
-            let inner = collect_inlines(dom, parser, tag);
-            if !inner.is_empty() {
-                inlines.push(Inline::Emphasis(inner));
-            }
+    }
+
+    fn advance(&mut self, ctx: &'c Context<'c, 'c>) -> Option<Frame<'c>> {
+        let (handle, li) = *self.lis.get(self.next)?;
+        self.next += 1;
+        Some(list_item(ctx, handle, li))
+    }
+
+    fn finish(self) -> Converted {
+        Converted::Block((!self.items.is_empty()).then_some(Block::List {
+            ordered: self.ordered,
+            start: self.start,
+            items: self.items,
+        }))
+    }
+}
+
+/// Caption and rows of a table, converted as a pipe table or laid out as a
+/// list
+struct Table<'c> {
+    as_list: bool,
+    /// Caption element still to convert
+    caption: Option<&'c HTMLTag<'c>>,
+    /// Rows with the lowercase name of the section holding them and the
+    /// index of their row group
+    rows: Vec<(Option<String>, usize, &'c HTMLTag<'c>)>,
+    next: usize,
+    converted_caption: Option<Vec<Inline>>,
+    /// Caption taken from the `summary` attribute when there is no caption
+    summary: Option<Vec<Inline>>,
+    pipe_rows: Vec<(TableRow, bool)>,
+    list_rows: Vec<ListItem>,
+}
+
+impl<'c> Table<'c> {
+    fn new(ctx: &Context<'c, 'c>, tag: &'c HTMLTag<'c>, as_list: bool) -> Self {
+        Table {
+            as_list,
+            caption: table_caption(ctx, tag),
+            rows: table_rows(ctx, tag),
+            next: 0,
+            converted_caption: None,
+            summary: table_summary(get_attr(tag, "summary")),
+            pipe_rows: Vec::new(),
+            list_rows: Vec::new(),
         }
-        "code" => {
-            let code = get_text_content(dom, parser, tag);
-            if !code.is_empty() {
-                inlines.push(Inline::Code(code));
+    }
+
+    /// Convert the caption (after the rows for a list) and the rows
+    fn advance(&mut self, ctx: &'c Context<'c, 'c>) -> Option<Frame<'c>> {
+        if !self.as_list {
+            if let Some(caption) = self.caption.take() {
+                return Some(caption_frame(ctx, caption));
             }
         }
-        "a" => {
-            inlines.push(process_link(dom, parser, tag));
+        if let Some(&(ref section, group, tr)) = self.rows.get(self.next) {
+            self.next += 1;
+            return Some(row_frame(ctx, section.as_deref(), group, tr, self.as_list));
         }
-        "img" => {
-            if let Some(img) = process_image(tag) {
-                inlines.push(img);
-            }
+        self.caption.take().map(|caption| caption_frame(ctx, caption))
+    }
+
+    fn finish(self, ctx: &Context) -> Converted {
+        let caption = self.converted_caption.or(self.summary);
+        if !self.as_list {
+            return Converted::Block(pipe_table(ctx, self.pipe_rows, caption));
+        }
+        let list = Block::List {
+            ordered: false,
+            start: 1,
+            items: self.list_rows,
+        };
+        Converted::Block(Some(match caption {
+            Some(caption) => Block::Document(vec![Block::Paragraph(caption), list]),
+            None => list,
+        }))
+    }
+}
+
+/// Cells of a table row
+struct Row<'c> {
+    as_list: bool,
+    /// The th/td cells with their lowercase tag names
+    cells: Vec<(String, &'c HTMLTag<'c>)>,
+    next: usize,
+    /// Whether the row sits in thead or consists of th cells only
+    heading: bool,
+    /// Index of the row group holding the row
+    group: usize,
+    pipe_cells: Vec<TableCell>,
+    list_cells: Vec<ListItem>,
+}
+
+impl<'c> Row<'c> {
+    fn advance(&mut self, ctx: &'c Context<'c, 'c>) -> Option<Frame<'c>> {
+        let (name, cell) = self.cells.get(self.next)?;
+        self.next += 1;
+        let (inline, finish) = if self.as_list {
+            (false, Finish::CellItem)
+        } else {
+            let alignment = Alignment::from_html(
+                get_attr(cell, "align").as_deref(),
+                get_attr(cell, "style").as_deref(),
+            );
+            let colspan = cell_span(cell, "colspan");
+            let rowspan = cell_span(cell, "rowspan");
+            (true, Finish::Cell { alignment, colspan, rowspan })
+        };
+        Some(visit_children(ctx, name, Parent::Tag(cell), inline, finish))
+    }
+
+    fn finish(self) -> Converted {
+        if !self.as_list {
+            let row = SpannedRow {
+                cells: self.pipe_cells,
+                group: self.group,
+            };
+            return Converted::Row(row, self.heading);
         }
-        "br" => {
-            inlines.push(Inline::LineBreak);
+        Converted::Item(ListItem::new(vec![Block::List {
+            ordered: false,
+            start: 1,
+            items: self.list_cells,
+        }]))
+    }
+}
+
+/// Drive a conversion to completion
+///
+/// Frames go on an explicit stack. The top frame converts its children until
+/// one has to visit children of its own, which pushes a frame for them. Once
+/// done, a frame builds its result and hands it to the frame below, or
+/// returns it for the root.
+fn run<'c>(ctx: &'c Context<'c, 'c>, root: Frame<'c>) -> Result<Option<Block>> {
+    let mut stack = vec![root.enter(ctx)];
+
+    while let Some(frame) = stack.last_mut() {
+        if ctx.aborted() {
+            stack.into_iter().for_each(|frame| frame.work.discard());
+            return Err(TurndownError::Aborted);
         }
-        _ => {
-            // Pass through content for other inline elements
-            let children = tag.children();
-            for handle in children.top().iter() {
-                if let Some(child) = handle.get(parser) {
-                    collect_inline_node(dom, parser, child, inlines);
-                }
+        if let Some(child) = frame.work.advance(ctx) {
+            stack.push(child.enter(ctx));
+            continue;
+        }
+
+        let frame = stack.pop().unwrap();
+        ctx.ancestors.borrow_mut().truncate(frame.depth);
+        let converted = frame.work.finish(ctx);
+        match stack.last_mut() {
+            Some(parent) => parent.work.accept(converted),
+            None => {
+                return Ok(match converted {
+                    Converted::Block(block) => block,
+                    _ => None,
+                })
             }
         }
     }
+
+    Ok(None)
 }
 
-fn collect_inlines(dom: &VDom, parser: &Parser, tag: &HTMLTag) -> Vec<Inline> {
-    let mut inlines = InlineVec::new();
-    let children = tag.children();
+/// Convert the children of an element, which is an ancestor for the rules
+/// matched meanwhile
+fn visit_children<'c>(
+    ctx: &Context,
+    tag_lower: &str,
+    parent: Parent<'c>,
+    inline: bool,
+    finish: Finish<'c>,
+) -> Frame<'c> {
+    Frame::new(ctx, &[tag_lower], Work::Children(Children::new(parent, inline, finish)))
+}
 
-    for handle in children.top().iter() {
-        if let Some(node) = handle.get(parser) {
-            collect_inline_node(dom, parser, node, &mut inlines);
+/// Convert a node in block context (see [`is_inline_node`] for the nodes
+/// converted as part of a paragraph instead)
+fn process_node<'c>(
+    ctx: &'c Context<'c, 'c>,
+    handle: NodeHandle,
+    node: &'c Node<'c>,
+) -> Step<'c, Option<Block>> {
+    match node {
+        Node::Tag(tag) => process_element(ctx, handle, tag),
+        _ => Step::Done(None),
+    }
+}
+
+/// Elements converted as part of the surrounding paragraph in block context
+const PHRASING_ELEMENTS: &[&str] = &[
+    "a", "b", "br", "code", "del", "em", "i", "img", "ins", "mark", "s", "small", "span", "strike",
+    "strong", "sub", "sup", "u",
+];
+
+/// Whether a node in block context belongs to a paragraph with its
+/// neighbouring text and phrasing elements
+fn is_inline_node(node: &Node) -> bool {
+    match node {
+        Node::Tag(tag) => {
+            let name = tag.name().as_utf8_str();
+            PHRASING_ELEMENTS.iter().any(|phrasing| phrasing.eq_ignore_ascii_case(&name))
         }
+        _ => true,
+    }
+}
+
+fn process_element<'c>(
+    ctx: &'c Context<'c, 'c>,
+    handle: NodeHandle,
+    tag: &'c HTMLTag<'c>,
+) -> Step<'c, Option<Block>> {
+    let tag_name = tag.name().as_utf8_str();
+    let tag_lower = tag_name.to_ascii_lowercase();
+
+    if let Some(frame) = apply_rule(ctx, handle, &tag_lower, tag, Target::Block) {
+        return Step::Visit(frame);
     }
 
-    // Trim leading/trailing whitespace from inline content (matches browser innerText behavior)
-    trim_inlines(&mut inlines);
+    match filter_action(ctx, handle, &tag_lower, tag) {
+        Some(FilterAction::Keep) => return Step::Done(Some(Block::HtmlBlock(outer_html(tag)))),
+        Some(FilterAction::Remove) => return Step::Done(None),
+        None => {}
+    }
 
-    inlines.into_vec()
+    let children = |inline, finish| {
+        Step::Visit(visit_children(ctx, &tag_lower, Parent::Tag(tag), inline, finish))
+    };
+    match tag_lower.as_str() {
+        "p" => children(true, Finish::Paragraph),
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = tag_lower.chars().nth(1).and_then(|c| c.to_digit(10)).unwrap_or(1) as u8;
+            children(true, Finish::Heading(level))
+        }
+        "blockquote" => children(false, Finish::BlockQuote),
+        "ul" | "ol" => {
+            let ordered = tag_lower == "ol";
+            let start = get_attr(tag, "start")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1);
+
+            let list = Work::List(List::new(ctx, tag, ordered, start));
+            Step::Visit(Frame::new(ctx, &[&tag_lower], list))
+        }
+        "pre" => {
+            // Look for code element inside
+            let (code, lang) = extract_code_content(ctx, tag);
+            Step::Done(Some(Block::CodeBlock {
+                language: lang,
+                code,
+                fenced: false, // Let options.code_block_style decide
+            }))
+        }
+        "hr" => Step::Done(Some(Block::ThematicBreak)),
+        "table" if ctx.options.tables => convert_table(ctx, tag),
+        // Tables: turndown JS extracts text content (table-to-markdown is a GFM plugin)
+        "table" | "thead" | "tbody" | "tfoot" | "tr" => children(false, Finish::Container),
+        "th" | "td" => children(true, Finish::Paragraph),
+        "div" | "section" | "article" | "main" | "aside" | "header" | "footer" | "nav" | "figure" | "body" | "html" => {
+            // Container elements - process children
+            children(false, Finish::Container)
+        }
+        "script" | "style" | "noscript" | "template" | "meta" | "link" => Step::Done(None),
+        // Extract text content (turndown JS converts <title> to text)
+        "head" | "title" => children(false, Finish::Head(tag)),
+        _ => {
+            // Unknown element - try to extract content
+            children(false, Finish::Container)
+        }
+    }
+}
+
+/// A paragraph of the inlines, unless there are none
+fn paragraph(inlines: Vec<Inline>) -> Option<Block> {
+    (!inlines.is_empty()).then_some(Block::Paragraph(inlines))
+}
+
+/// Convert an inline element that no rule or filter applies to
+fn inline_element<'c>(
+    ctx: &'c Context<'c, 'c>,
+    tag_lower: &str,
+    tag: &'c HTMLTag<'c>,
+) -> Step<'c, InlineVec> {
+    // The content of inline elements is kept untrimmed, so flanking
+    // whitespace can be moved outside their delimiters during serialization
+    let children = |finish| {
+        Step::Visit(visit_children(ctx, tag_lower, Parent::Tag(tag), true, finish))
+    };
+    match tag_lower {
+        "strong" | "b" => children(Finish::Wrap(Inline::Strong)),
+        "em" | "i" => children(Finish::Wrap(Inline::Emphasis)),
+        "code" => {
+            let code = get_text_content(ctx, tag);
+            Step::Done(if code.is_empty() {
+                InlineVec::new()
+            } else {
+                smallvec![Inline::Code(code)]
+            })
+        }
+        "a" => {
+            let url = get_attr(tag, "href").unwrap_or_default();
+            let title = get_attr(tag, "title");
+            children(Finish::Link { url, title })
+        }
+        "img" => Step::Done(process_image(tag).into_iter().collect()),
+        "br" => Step::Done(smallvec![Inline::LineBreak]),
+        "del" | "s" | "strike" => children(Finish::Wrap(Inline::Strikethrough)),
+        "mark" => children(Finish::Wrap(Inline::Highlight)),
+        "sup" => children(Finish::Wrap(Inline::Superscript)),
+        "sub" => children(Finish::Wrap(Inline::Subscript)),
+        _ => {
+            // Pass through content for other inline elements
+            children(Finish::Inlines)
+        }
+    }
 }
 
 /// Trim leading whitespace from first text node and trailing whitespace from last text node
-fn trim_inlines(inlines: &mut InlineVec) {
-    // Trim leading
+fn trim_inlines(inlines: &mut Vec<Inline>) {
+    // Trim leading, dropping text nodes that are only whitespace
+    let blank = inlines
+        .iter()
+        .take_while(|inline| matches!(inline, Inline::Text(t) if t.trim_ascii_start().is_empty()))
+        .count();
+    inlines.drain(..blank);
     if let Some(Inline::Text(text)) = inlines.first_mut() {
-        let trimmed = text.trim_start().to_string();
-        if trimmed.is_empty() {
-            inlines.remove(0);
-            // Recurse to trim the next element if needed
-            trim_inlines(inlines);
-            return;
-        }
-        *text = trimmed;
+        *text = text.trim_ascii_start().to_string();
     }
     // Trim trailing
     if let Some(Inline::Text(text)) = inlines.last_mut() {
-        let trimmed = text.trim_end().to_string();
+        let trimmed = text.trim_end_matches(|c: char| c.is_ascii_whitespace()).to_string();
         if trimmed.is_empty() {
             inlines.pop();
             return;
@@ -308,151 +1198,282 @@ fn trim_inlines(inlines: &mut InlineVec) {
     }
 }
 
-fn collect_inline_node(dom: &VDom, parser: &Parser, node: &Node, inlines: &mut InlineVec) {
-    match node {
-        Node::Tag(tag) => {
-            let tag_name = tag.name().as_utf8_str();
-            let tag_lower = tag_name.to_ascii_lowercase();
+fn collect_inline_node<'c>(
+    ctx: &'c Context<'c, 'c>,
+    handle: NodeHandle,
+    node: &'c Node<'c>,
+) -> Step<'c, InlineVec> {
+    let inlines = match node {
+        Node::Tag(tag) => {
+            let tag_name = tag.name().as_utf8_str();
+            let tag_lower = tag_name.to_ascii_lowercase();
+
+            if let Some(frame) = apply_rule(ctx, handle, &tag_lower, tag, Target::Inlines) {
+                return Step::Visit(frame);
+            }
+
+            match filter_action(ctx, handle, &tag_lower, tag) {
+                Some(FilterAction::Keep) => smallvec![Inline::HtmlInline(outer_html(tag))],
+                Some(FilterAction::Remove) => InlineVec::new(),
+                None => return inline_element(ctx, &tag_lower, tag),
+            }
+        }
+        Node::Raw(_) => {
+            // Whitespace-only text survives collapsing only where it separates words
+            let text = ctx.text(handle);
+            if text.is_empty() {
+                InlineVec::new()
+            } else {
+                smallvec![Inline::Text(escape_text(&text))]
+            }
+        }
+        _ => InlineVec::new(),
+    };
+    Step::Done(inlines)
+}
+
+/// Run the first custom rule matching an element, if any
+///
+/// The rule receives the element's children as blocks when they contain
+/// block-level elements (and its replacement does not go inline), else as
+/// inlines.
+fn apply_rule<'c>(
+    ctx: &'c Context<'c, 'c>,
+    handle: NodeHandle,
+    tag_lower: &str,
+    tag: &'c HTMLTag<'c>,
+    target: Target,
+) -> Option<Frame<'c>> {
+    if !ctx.has_rules() {
+        return None;
+    }
+
+    let inline = matches!(target, Target::Inlines) || !has_block_children(ctx, tag);
+    let content = |finish| visit_children(ctx, tag_lower, Parent::Tag(tag), inline, finish);
+
+    if let Some((rule, element)) = ctx.filters.rules.find(tag_lower, || rule_element(ctx, tag_lower, tag)) {
+        return Some(content(Finish::Rule {
+            rule,
+            element: Box::new(element),
+            target,
+        }));
+    }
+
+    let callbacks = ctx.callbacks.filter(|callbacks| callbacks.may_match(tag_lower))?;
+    let node = callback_node(ctx, handle, tag_lower, tag)?;
+    let index = callbacks.find(&node)?;
+    Some(content(Finish::Callback {
+        callbacks,
+        index,
+        node: Box::new(node),
+        target,
+    }))
+}
+
+/// Describe an element for callback rules and predicates
+///
+/// Blank elements never reach custom rules or keep/remove filters in
+/// turndown.js, so they get no description.
+fn callback_node(
+    ctx: &Context,
+    handle: NodeHandle,
+    tag_lower: &str,
+    tag: &HTMLTag,
+) -> Option<CallbackNode> {
+    let source = ctx.source.as_ref()?;
+    let key = node_index(handle);
+    if source.elements.get(key)?.as_ref()?.blank {
+        return None;
+    }
+    Some(CallbackNode {
+        tag: tag_lower.to_string(),
+        attributes: element_attributes(tag),
+        is_block: BLOCK_ELEMENTS.contains(&tag_lower),
+        source: Rc::clone(source),
+        key,
+    })
+}
+
+/// Build the element view handed to rule filters and replacements
+fn rule_element(ctx: &Context, tag_lower: &str, tag: &HTMLTag) -> Element {
+    Element {
+        tag: tag_lower.to_string(),
+        attributes: element_attributes(tag),
+        ancestors: ctx.ancestors.borrow().clone(),
+    }
+}
+
+/// Attributes of an element with character references decoded
+fn element_attributes(tag: &HTMLTag) -> Vec<(String, String)> {
+    tag.attributes()
+        .iter()
+        .map(|(k, v)| {
+            let value = v.map(|v| decode_attribute(&v).into_owned()).unwrap_or_default();
+            (k.into_owned(), value)
+        })
+        .collect()
+}
+
+/// Content handed to a rule: the converted children of the element
+fn rule_content(inline: bool, blocks: Vec<Block>, inlines: Vec<Inline>) -> Content {
+    if inline {
+        Content::Inlines(inlines)
+    } else {
+        Content::Blocks(blocks)
+    }
+}
+
+/// What the keep/remove filters decided for an element
+enum FilterAction {
+    Keep,
+    Remove,
+}
+
+/// Match an element against the keep/remove filters (turndown.js precedence:
+/// blank elements first, then built-in rules, then keep, then remove)
+fn filter_action(
+    ctx: &Context,
+    handle: NodeHandle,
+    tag_lower: &str,
+    tag: &HTMLTag,
+) -> Option<FilterAction> {
+    let filters = ctx.filters;
+    let callbacks = ctx.callbacks.filter(|callbacks| callbacks.has_filters());
+    if filters.keep.is_empty() && filters.remove.is_empty() && callbacks.is_none() {
+        return None;
+    }
+    if has_builtin_rule(ctx, tag_lower, tag) {
+        return None;
+    }
+
+    let node = callbacks.and_then(|_| callback_node(ctx, handle, tag_lower, tag));
+    let predicate = callbacks.zip(node.as_ref());
+    if filters.keep.iter().any(|f| f == tag_lower)
+        || predicate.is_some_and(|(callbacks, node)| callbacks.keeps(node))
+    {
+        // Blank elements are dropped before keep is consulted
+        if is_blank(ctx, tag_lower, tag) {
+            Some(FilterAction::Remove)
+        } else {
+            Some(FilterAction::Keep)
+        }
+    } else if filters.remove.iter().any(|f| f == tag_lower)
+        || predicate.is_some_and(|(callbacks, node)| callbacks.removes(node))
+    {
+        Some(FilterAction::Remove)
+    } else {
+        None
+    }
+}
+
+/// Whether one of turndown's CommonMark rules (or an enabled extension)
+/// converts this element
+fn has_builtin_rule(ctx: &Context, tag_lower: &str, tag: &HTMLTag) -> bool {
+    let options = ctx.options;
+    match tag_lower {
+        "p" | "br" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "ul" | "ol"
+        | "li" | "hr" | "em" | "i" | "strong" | "b" | "code" | "img" => true,
+        "table" => options.tables,
+        "del" | "s" | "strike" => options.strikethrough_style != MarkupStyle::Plain,
+        "mark" => options.highlight_style != MarkupStyle::Plain,
+        "sup" | "sub" => options.script_style != MarkupStyle::Plain,
+        "a" => tag
+            .attributes()
+            .get("href")
+            .flatten()
+            .is_some_and(|href| !href.as_bytes().is_empty()),
+        "pre" => matches!(
+            tag.children().top().iter().next().and_then(|h| h.get(ctx.parser)),
+            Some(Node::Tag(child)) if child.name().as_utf8_str().eq_ignore_ascii_case("code")
+        ),
+        _ => false,
+    }
+}
+
+const MEANINGFUL_WHEN_BLANK: &[&str] = &[
+    "a", "table", "thead", "tbody", "tfoot", "th", "td", "iframe", "script", "audio", "video",
+];
+
+/// Void elements and elements that are kept even without content
+fn is_meaningful_when_blank(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name) || MEANINGFUL_WHEN_BLANK.contains(&name)
+}
+
+/// Check if an element is blank in the turndown.js sense: no text and no
+/// void or meaningful-when-blank descendants
+fn is_blank(ctx: &Context, tag_lower: &str, tag: &HTMLTag) -> bool {
+    let meaningful = is_meaningful_when_blank;
+    if meaningful(tag_lower) {
+        return false;
+    }
 
-            match tag_lower.as_str() {
-                "strong" | "b" => {
-                    let inner = collect_inlines(dom, parser, tag);
-                    if !inner.is_empty() {
-                        inlines.push(Inline::Strong(inner));
-                    }
-                }
-                "em" | "i" => {
-                    let inner = collect_inlines(dom, parser, tag);
-                    if !inner.is_empty() {
-                        inlines.push(Inline::Emphasis(inner));
-                    }
-                }
-                "code" => {
-                    let code = get_text_content(dom, parser, tag);
-                    if !code.is_empty() {
-                        inlines.push(Inline::Code(code));
-                    }
-                }
-                "a" => {
-                    inlines.push(process_link(dom, parser, tag));
-                }
-                "img" => {
-                    if let Some(img) = process_image(tag) {
-                        inlines.push(img);
-                    }
-                }
-                "br" => {
-                    inlines.push(Inline::LineBreak);
-                }
-                "span" | "small" | "sub" | "sup" | "mark" | "del" | "ins" | "u" => {
-                    // Pass through content
-                    let children = tag.children();
-                    for handle in children.top().iter() {
-                        if let Some(child) = handle.get(parser) {
-                            collect_inline_node(dom, parser, child, inlines);
-                        }
-                    }
-                }
-                _ => {
-                    // Unknown inline - try to get content
-                    let children = tag.children();
-                    for handle in children.top().iter() {
-                        if let Some(child) = handle.get(parser) {
-                            collect_inline_node(dom, parser, child, inlines);
-                        }
-                    }
+    let mut stack: Vec<NodeHandle> = tag.children().top().to_vec();
+    while let Some(handle) = stack.pop() {
+        match handle.get(ctx.parser) {
+            Some(Node::Tag(child)) => {
+                if meaningful(&child.name().as_utf8_str().to_ascii_lowercase()) {
+                    return false;
                 }
+                stack.extend(child.children().top().iter().copied());
             }
+            Some(Node::Raw(_)) if !ctx.text(handle).trim().is_empty() => return false,
+            _ => {}
         }
-        Node::Raw(text) => {
-            let text_str = text.as_utf8_str();
-            let processed = collapse_and_escape(&text_str);
-            if !processed.trim().is_empty() {
-                inlines.push(Inline::Text(processed));
-            }
-        }
-        _ => {}
     }
+    true
 }
 
-fn process_link(dom: &VDom, parser: &Parser, tag: &HTMLTag) -> Inline {
-    let href = tag.attributes()
-        .get("href")
-        .flatten()
-        .map(|s| s.as_utf8_str().to_string())
-        .unwrap_or_default();
-    let title = tag.attributes()
-        .get("title")
-        .flatten()
-        .map(|s| s.as_utf8_str().to_string());
-    let content = collect_inlines(dom, parser, tag);
+/// Original source markup of an element
+fn outer_html(tag: &HTMLTag) -> String {
+    tag.raw().as_utf8_str().into_owned()
+}
 
-    Inline::Link {
-        content,
-        url: href,
-        title,
-    }
+/// Get an attribute value with character references decoded
+fn get_attr(tag: &HTMLTag, name: &str) -> Option<String> {
+    tag.attributes()
+        .get(name)
+        .flatten()
+        .map(|s| decode_attribute(&s.as_utf8_str()).into_owned())
 }
 
 fn process_image(tag: &HTMLTag) -> Option<Inline> {
-    let src = tag.attributes()
-        .get("src")
-        .flatten()
-        .map(|s| s.as_utf8_str().to_string())
-        .unwrap_or_default();
+    let src = get_attr(tag, "src").unwrap_or_default();
 
     if src.is_empty() {
         return None;
     }
 
-    let alt = tag.attributes()
-        .get("alt")
-        .flatten()
-        .map(|s| s.as_utf8_str().to_string())
-        .unwrap_or_default();
-    let title = tag.attributes()
-        .get("title")
-        .flatten()
-        .map(|s| s.as_utf8_str().to_string());
+    let alt = get_attr(tag, "alt").unwrap_or_default();
+    let title = get_attr(tag, "title");
 
     Some(Inline::Image { url: src, alt, title })
 }
 
-fn get_text_content(dom: &VDom, parser: &Parser, tag: &HTMLTag) -> String {
+fn get_text_content(ctx: &Context, tag: &HTMLTag) -> String {
     let mut result = String::new();
     let children = tag.children();
+    let mut stack: Vec<NodeHandle> = children.top().as_slice().iter().rev().copied().collect();
 
-    for handle in children.top().iter() {
-        if let Some(node) = handle.get(parser) {
-            collect_text_recursive(dom, parser, node, &mut result);
+    while let Some(handle) = stack.pop() {
+        match handle.get(ctx.parser) {
+            Some(Node::Tag(tag)) => {
+                stack.extend(tag.children().top().as_slice().iter().rev());
+            }
+            Some(Node::Raw(_)) => {
+                result.push_str(&ctx.text(handle));
+            }
+            _ => {}
         }
     }
 
     result
 }
 
-fn collect_text_recursive(dom: &VDom, parser: &Parser, node: &Node, result: &mut String) {
-    match node {
-        Node::Tag(tag) => {
-            let children = tag.children();
-            for handle in children.top().iter() {
-                if let Some(child) = handle.get(parser) {
-                    collect_text_recursive(dom, parser, child, result);
-                }
-            }
-        }
-        Node::Raw(text) => {
-            result.push_str(&text.as_utf8_str());
-        }
-        _ => {}
-    }
-}
-
-/// Check if an li element has any block-level child elements
-fn li_has_block_children(parser: &Parser, tag: &HTMLTag) -> bool {
+/// Check if an element has any block-level child elements
+fn has_block_children(ctx: &Context, tag: &HTMLTag) -> bool {
     let children = tag.children();
     for handle in children.top().iter() {
-        if let Some(Node::Tag(child_tag)) = handle.get(parser) {
+        if let Some(Node::Tag(child_tag)) = handle.get(ctx.parser) {
             let name = child_tag.name().as_utf8_str();
             let lower = name.to_ascii_lowercase();
             match lower.as_str() {
@@ -467,53 +1488,260 @@ fn li_has_block_children(parser: &Parser, tag: &HTMLTag) -> bool {
     false
 }
 
-fn collect_list_items(dom: &VDom, parser: &Parser, tag: &HTMLTag) -> Vec<ListItem> {
-    let mut items = Vec::new();
-    let children = tag.children();
+fn list_item<'c>(ctx: &'c Context<'c, 'c>, handle: NodeHandle, li: &'c HTMLTag<'c>) -> Frame<'c> {
+    if let Some(frame) = apply_rule(ctx, handle, "li", li, Target::ListItem) {
+        return frame;
+    }
 
-    for handle in children.top().iter() {
-        if let Some(Node::Tag(li_tag)) = handle.get(parser) {
-            let tag_name = li_tag.name().as_utf8_str();
-            if tag_name.eq_ignore_ascii_case("li") {
-                let content = if li_has_block_children(parser, li_tag) {
-                    // Has block children - process as blocks
-                    let li_children = li_tag.children();
-                    let inner_blocks = process_nodes(dom, parser, li_children.top().as_slice());
-                    if inner_blocks.is_empty() {
-                        let inlines = collect_inlines(dom, parser, li_tag);
-                        if inlines.is_empty() { vec![] } else { vec![Block::Paragraph(inlines)] }
-                    } else {
-                        inner_blocks
-                    }
-                } else {
-                    // Only inline children - collect as single paragraph
-                    let inlines = collect_inlines(dom, parser, li_tag);
-                    if inlines.is_empty() {
-                        vec![]
-                    } else {
-                        vec![Block::Paragraph(inlines)]
+    let checked = leading_checkbox(ctx, li);
+    visit_children(ctx, "li", Parent::Tag(li), false, Finish::ListItem(checked))
+}
+
+/// Checked state of a checkbox input leading a list item
+///
+/// The input may be wrapped in a leading `<p>` or `<label>`, as produced
+/// for loose task lists and by many Markdown renderers.
+fn leading_checkbox<'p, 'a>(ctx: &Context<'p, 'a>, mut tag: &'p HTMLTag<'a>) -> Option<bool> {
+    loop {
+        let first = tag.children().top().iter().find_map(|handle| match handle.get(ctx.parser)? {
+            Node::Raw(text) if text.as_utf8_str().trim().is_empty() => None,
+            node => Some(node),
+        })?;
+        let Node::Tag(first) = first else {
+            return None;
+        };
+
+        let name = first.name().as_utf8_str().to_ascii_lowercase();
+        match name.as_str() {
+            "input" => {
+                let attrs = first.attributes();
+                let is_checkbox = attrs
+                    .get("type")
+                    .flatten()
+                    .is_some_and(|t| t.as_utf8_str().eq_ignore_ascii_case("checkbox"));
+                return is_checkbox.then(|| attrs.get("checked").is_some());
+            }
+            "p" | "label" => tag = first,
+            _ => return None,
+        }
+    }
+}
+
+/// Convert a table into a GFM pipe table (the turndown-plugin-gfm `tables` rule)
+///
+/// Rows are taken from thead/tbody/tfoot and direct tr children in source
+/// order. The first row is the header when it sits in thead or consists of th
+/// cells only; otherwise the table gets an empty header row, since pipe tables
+/// cannot exist without one. The first caption, or else the `summary`
+/// attribute, is kept on the table.
+///
+/// Tables with spanning cells or block content in cells take the configured
+/// [`TableFallback`] instead.
+fn convert_table<'c>(ctx: &'c Context<'c, 'c>, tag: &'c HTMLTag<'c>) -> Step<'c, Option<Block>> {
+    let as_list = is_complex_table(ctx, tag);
+    if as_list && ctx.options.table_fallback == TableFallback::Html {
+        return Step::Done(Some(Block::HtmlBlock(outer_html(tag))));
+    }
+    Step::Visit(Frame::new(ctx, &["table"], Work::Table(Table::new(ctx, tag, as_list))))
+}
+
+/// Assemble a pipe table from its rows and caption
+fn pipe_table(
+    ctx: &Context,
+    table_rows: Vec<(TableRow, bool)>,
+    caption: Option<Vec<Inline>>,
+) -> Option<Block> {
+    let mut rows = Vec::new();
+    let mut has_heading_row = false;
+    for (row, heading) in table_rows {
+        if row.cells.is_empty() {
+            continue;
+        }
+        if rows.is_empty() && heading {
+            has_heading_row = true;
+        }
+        rows.push(row);
+    }
+
+    if rows.is_empty() {
+        return caption.map(Block::Paragraph);
+    }
+
+    let duplicate = ctx.options.table_spans == TableSpans::Duplicate;
+    let mut cells = Vec::new();
+    let mut alignments = Vec::new();
+    for row in expand_table_spans(rows, duplicate) {
+        let (row_cells, row_alignments): (Vec<_>, Vec<_>) = row.into_iter().unzip();
+        cells.push(row_cells);
+        alignments.push(row_alignments);
+    }
+
+    let headers = if has_heading_row {
+        cells.remove(0)
+    } else {
+        vec![Vec::new(); cells[0].len()]
+    };
+    Some(Block::Table {
+        headers,
+        rows: cells,
+        alignments: Alignment::columns(&alignments),
+        caption,
+    })
+}
+
+/// The first `caption` of a table
+fn table_caption<'p, 'a>(ctx: &Context<'p, 'a>, table: &HTMLTag) -> Option<&'p HTMLTag<'a>> {
+    table.children().top().iter().find_map(|handle| match handle.get(ctx.parser)? {
+        Node::Tag(child) if child.name().as_utf8_str().eq_ignore_ascii_case("caption") => Some(child),
+        _ => None,
+    })
+}
+
+/// Caption text from the obsolete `summary` attribute of a table, with its
+/// whitespace collapsed
+fn table_summary(summary: Option<String>) -> Option<Vec<Inline>> {
+    let summary = summary?.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!summary.is_empty()).then(|| vec![Inline::Text(summary)])
+}
+
+/// Convert the inline content of a table caption
+fn caption_frame<'c>(ctx: &Context, caption: &'c HTMLTag<'c>) -> Frame<'c> {
+    visit_children(ctx, "caption", Parent::Tag(caption), true, Finish::Caption)
+}
+
+/// Rows of a table, from its sections and direct `tr` children, with the
+/// lowercase name of the section holding them and the index of their row
+/// group (a section, or a run of direct `tr` children)
+fn table_rows<'p, 'a>(
+    ctx: &Context<'p, 'a>,
+    table: &HTMLTag,
+) -> Vec<(Option<String>, usize, &'p HTMLTag<'a>)> {
+    let mut rows = Vec::new();
+    let mut group = 0;
+    let mut in_direct_rows = false;
+    for handle in table.children().top().iter() {
+        let Some(Node::Tag(child)) = handle.get(ctx.parser) else {
+            continue;
+        };
+        let name = child.name().as_utf8_str().to_ascii_lowercase();
+        match name.as_str() {
+            "thead" | "tbody" | "tfoot" => {
+                group += 1;
+                in_direct_rows = false;
+                for handle in child.children().top().iter() {
+                    if let Some(Node::Tag(tr)) = handle.get(ctx.parser) {
+                        if tr.name().as_utf8_str().eq_ignore_ascii_case("tr") {
+                            rows.push((Some(name.clone()), group, tr));
+                        }
                     }
-                };
+                }
+            }
+            "tr" => {
+                if !in_direct_rows {
+                    group += 1;
+                    in_direct_rows = true;
+                }
+                rows.push((None, group, child));
+            }
+            _ => {}
+        }
+    }
+    rows
+}
 
-                items.push(ListItem::new(content));
+/// The th/td cells of a table row with their lowercase tag names
+fn row_cells<'p, 'a>(ctx: &Context<'p, 'a>, tr: &HTMLTag) -> Vec<(String, &'p HTMLTag<'a>)> {
+    tr.children()
+        .top()
+        .iter()
+        .filter_map(|handle| match handle.get(ctx.parser)? {
+            Node::Tag(cell) => {
+                let name = cell.name().as_utf8_str().to_ascii_lowercase();
+                (name == "th" || name == "td").then_some((name, cell))
             }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a table cannot be a pipe table: a cell holds block content beyond
+/// a single paragraph, or spans several rows or columns while span expansion
+/// is off
+fn is_complex_table(ctx: &Context, table: &HTMLTag) -> bool {
+    let check_spans = ctx.options.table_spans == TableSpans::Fallback;
+    table_rows(ctx, table).into_iter().any(|(_, _, tr)| {
+        row_cells(ctx, tr).into_iter().any(|(_, cell)| {
+            let spans = cell_span(cell, "colspan") > 1 || cell_span(cell, "rowspan") != 1;
+            (check_spans && spans) || has_block_content(ctx, cell)
+        })
+    })
+}
+
+/// Value of a `colspan` or `rowspan` attribute, 1 when missing or invalid
+fn cell_span(cell: &HTMLTag, name: &str) -> usize {
+    get_attr(cell, name)
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// Whether a cell holds block elements other than one paragraph or div
+fn has_block_content(ctx: &Context, cell: &HTMLTag) -> bool {
+    let mut paragraphs = 0;
+    let mut stack: Vec<NodeHandle> = cell.children().top().to_vec();
+    while let Some(handle) = stack.pop() {
+        let Some(Node::Tag(tag)) = handle.get(ctx.parser) else {
+            continue;
+        };
+        let name = tag.name().as_utf8_str().to_ascii_lowercase();
+        if name == "p" || name == "div" {
+            paragraphs += 1;
+            if paragraphs > 1 {
+                return true;
+            }
+        } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            return true;
         }
+        stack.extend(tag.children().top().iter().copied());
     }
+    false
+}
 
-    items
+/// Convert the th/td cells of a table row along with their alignments and
+/// spans, or as list items for a table laid out as a list
+fn row_frame<'c>(
+    ctx: &Context<'c, 'c>,
+    section: Option<&str>,
+    group: usize,
+    tr: &'c HTMLTag<'c>,
+    as_list: bool,
+) -> Frame<'c> {
+    let cells = row_cells(ctx, tr);
+    let heading = section == Some("thead") || cells.iter().all(|(name, _)| name == "th");
+    let row = Row {
+        as_list,
+        cells,
+        next: 0,
+        heading,
+        group,
+        pipe_cells: Vec::new(),
+        list_cells: Vec::new(),
+    };
+    // Section names only scope the rows of pipe tables
+    let scope = match section {
+        Some(section) if !as_list => vec![section, "tr"],
+        _ => vec!["tr"],
+    };
+    Frame::new(ctx, &scope, Work::Row(row))
 }
 
-fn extract_code_content(dom: &VDom, parser: &Parser, pre_tag: &HTMLTag) -> (String, Option<String>) {
+fn extract_code_content(ctx: &Context, pre_tag: &HTMLTag) -> (String, Option<String>) {
     let children = pre_tag.children();
 
     for handle in children.top().iter() {
-        if let Some(Node::Tag(code_tag)) = handle.get(parser) {
+        if let Some(Node::Tag(code_tag)) = handle.get(ctx.parser) {
             let tag_name = code_tag.name().as_utf8_str();
             if tag_name.eq_ignore_ascii_case("code") {
-                let class = code_tag.attributes()
-                    .get("class")
-                    .flatten()
-                    .map(|s| s.as_utf8_str().to_string());
+                let class = get_attr(code_tag, "class");
 
                 let lang = class.and_then(|c| {
                     c.split_whitespace()
@@ -521,25 +1749,25 @@ fn extract_code_content(dom: &VDom, parser: &Parser, pre_tag: &HTMLTag) -> (Stri
                         .map(|s| s[9..].to_string())
                 });
 
-                let code = get_text_content(dom, parser, code_tag);
+                let code = get_text_content(ctx, code_tag);
                 return (code, lang);
             }
         }
     }
 
     // No code element, get text directly
-    let code = get_text_content(dom, parser, pre_tag);
+    let code = get_text_content(ctx, pre_tag);
     (code, None)
 }
 
-/// Combined whitespace collapsing and markdown escaping in single pass
+/// Markdown escaping of (already collapsed) text in a single pass
 ///
 /// Matches turndown JS escaping rules:
 /// - Always escape: \ * _ [ ] `
 /// - Start-of-text only: - (alone), + (before space), # (1-6 before space),
 ///   > (alone), = (series), ~ (series of 3+), digit+.
 #[inline]
-fn collapse_and_escape(s: &str) -> String {
+fn escape_text(s: &str) -> String {
     const NEEDS_ESCAPE: [bool; 128] = {
         let mut table = [false; 128];
         table[b'\\' as usize] = true;
@@ -552,22 +1780,13 @@ fn collapse_and_escape(s: &str) -> String {
     };
 
     let mut result = String::with_capacity(s.len());
-    let mut prev_ws = false;
 
     for c in s.chars() {
-        if c.is_whitespace() {
-            if !prev_ws {
-                result.push(' ');
-                prev_ws = true;
-            }
-        } else {
-            prev_ws = false;
-            let b = c as u32;
-            if b < 128 && NEEDS_ESCAPE[b as usize] {
-                result.push('\\');
-            }
-            result.push(c);
+        let b = c as u32;
+        if b < 128 && NEEDS_ESCAPE[b as usize] {
+            result.push('\\');
         }
+        result.push(c);
     }
 
     // Handle start-of-text escaping (matches turndown JS behavior)
@@ -621,11 +1840,18 @@ fn escape_line_start(s: &mut String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use turndown_core::{CaptionPosition, CaptionStyle, LimitMode, LinkStyle};
 
     fn convert(html: &str) -> String {
+        convert_with_filters(html, &Filters::default())
+    }
+
+    fn convert_with_filters(html: &str, filters: &Filters) -> String {
         let options = Options::default();
-        let ast = html_to_ast(html, &options);
-        turndown_core::serialize(&ast, &options)
+        let ast = html_to_ast(html, &options, filters).unwrap();
+        let result = turndown_core::serialize(&ast, &options);
+        ast.drop_iteratively();
+        result
     }
 
     #[test]
@@ -684,6 +1910,683 @@ mod tests {
         assert!(result.contains("Item 2"), "Expected Item 2, got: {}", result);
         assert!(result.contains("*") || result.contains("-"), "Expected list marker, got: {}", result);
     }
+
+    fn convert_tables(html: &str) -> String {
+        let options = Options {
+            tables: true,
+            ..Options::default()
+        };
+        let ast = html_to_ast(html, &options, &Filters::default()).unwrap();
+        turndown_core::serialize(&ast, &options)
+    }
+
+    #[test]
+    fn test_table_disabled_by_default() {
+        let result = convert("<table><tr><th>A</th></tr><tr><td>1</td></tr></table>");
+        assert_eq!(result, "A\n\n1");
+    }
+
+    #[test]
+    fn test_table_sections() {
+        let result = convert_tables(
+            "<table><thead><tr><th>Name</th><th>Qty</th></tr></thead>\
+             <tbody><tr><td>Apple</td><td>3</td></tr></tbody>\
+             <tfoot><tr><td>Total</td><td>3</td></tr></tfoot></table>",
+        );
+        assert_eq!(
+            result,
+            "| Name  | Qty |\n| ----- | --- |\n| Apple | 3   |\n| Total | 3   |"
+        );
+    }
+
+    #[test]
+    fn test_table_th_row_is_header() {
+        let result = convert_tables(
+            "<table><tr><th>A</th><th><b>B</b></th></tr><tr><td>1</td><td>2</td></tr></table>",
+        );
+        assert_eq!(result, "| A   | **B** |\n| --- | ----- |\n| 1   | 2     |");
+    }
+
+    #[test]
+    fn test_table_without_heading_row() {
+        let result = convert_tables("<table><tr><td>1</td><td>2</td></tr><tr><td>3</td></tr></table>");
+        assert_eq!(result, "|     |     |\n| --- | --- |\n| 1   | 2   |\n| 3   |     |");
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let result = convert_tables(
+            "<table><tr><th align=\"center\">A</th><th style=\"text-align: right\">B</th><th>C</th></tr>\
+             <tr><td>1</td><td>2</td><td align=\"left\">3</td></tr></table>",
+        );
+        assert_eq!(result, "| A   | B   | C   |\n| :-: | --: | :-- |\n| 1   | 2   | 3   |");
+    }
+
+    #[test]
+    fn test_table_spans() {
+        let html = "<table><thead><tr><th colspan=\"2\">Name</th><th>Qty</th></tr></thead>\
+                    <tbody><tr><td rowspan=\"2\">Fruit</td><td>Apple</td><td>3</td></tr>\
+                    <tr><td>Pear</td><td>1</td></tr></tbody></table>";
+        let convert_spans = |html, spans| {
+            let options = Options {
+                tables: true,
+                table_spans: spans,
+                ..Options::default()
+            };
+            let ast = html_to_ast(html, &options, &Filters::default()).unwrap();
+            turndown_core::serialize(&ast, &options)
+        };
+
+        assert_eq!(
+            convert_spans(html, TableSpans::Blank),
+            "| Name  |       | Qty |\n| ----- | ----- | --- |\n| Fruit | Apple | 3   |\n|       | Pear  | 1   |"
+        );
+        assert_eq!(
+            convert_spans(html, TableSpans::Duplicate),
+            "| Name  | Name  | Qty |\n| ----- | ----- | --- |\n| Fruit | Apple | 3   |\n| Fruit | Pear  | 1   |"
+        );
+        assert_eq!(convert_spans(html, TableSpans::Fallback), html);
+
+        // A rowspan of 0 ends with its row group
+        let html = "<table><tr><th>A</th><th>B</th></tr>\
+                    <tbody><tr><td rowspan=\"0\">x</td><td>1</td></tr><tr><td>2</td></tr></tbody>\
+                    <tfoot><tr><td>f</td><td>g</td></tr></tfoot></table>";
+        assert_eq!(
+            convert_spans(html, TableSpans::Duplicate),
+            "| A   | B   |\n| --- | --- |\n| x   | 1   |\n| x   | 2   |\n| f   | g   |"
+        );
+    }
+
+    #[test]
+    fn test_complex_table_fallback() {
+        let html = "<table><tr><th colspan=\"2\">Wide</th></tr><tr><td>1</td><td>2</td></tr></table>";
+        assert_eq!(convert_tables(html), html);
+
+        let html = "<table><tr><th>A</th></tr><tr><td><p>one</p><p>two</p></td></tr></table>";
+        assert_eq!(convert_tables(html), html);
+
+        // A single paragraph per cell still makes a pipe table
+        let result = convert_tables("<table><tr><th>A</th></tr><tr><td><p>one</p></td></tr></table>");
+        assert_eq!(result, "| A   |\n| --- |\n| one |");
+
+        let options = Options {
+            tables: true,
+            table_fallback: TableFallback::List,
+            ..Options::default()
+        };
+        let ast = html_to_ast(
+            "<table><tr><th>A</th><th>B</th></tr><tr><td>1</td><td><ul><li>x</li></ul></td></tr></table>",
+            &options,
+            &Filters::default(),
+        ).unwrap();
+        assert_eq!(
+            turndown_core::serialize(&ast, &options),
+            "*   \n    *   A\n    *   B\n*   \n    *   1\n    *   \n        *   x"
+        );
+    }
+
+    #[test]
+    fn test_table_caption() {
+        let result = convert_tables(
+            "<table><caption>Prices</caption><tr><th>A</th></tr><tr><td>1</td><td>2</td></tr></table>",
+        );
+        assert_eq!(result, "Prices\n\n| A   |     |\n| --- | --- |\n| 1   | 2   |");
+
+        let options = Options {
+            tables: true,
+            caption_position: CaptionPosition::Below,
+            caption_style: CaptionStyle::Pandoc,
+            ..Options::default()
+        };
+        let ast = html_to_ast(
+            "<table><tr><td>1</td></tr><caption> <b>Prices</b> </caption></table>",
+            &options,
+            &Filters::default(),
+        ).unwrap();
+        assert_eq!(
+            turndown_core::serialize(&ast, &options),
+            "|     |\n| --- |\n| 1   |\n\nTable: **Prices**"
+        );
+
+        let options = Options {
+            tables: true,
+            caption_style: CaptionStyle::Bold,
+            ..Options::default()
+        };
+        let ast = html_to_ast(
+            "<table><caption>Prices</caption><tr><th>A</th></tr></table>",
+            &options,
+            &Filters::default(),
+        ).unwrap();
+        assert_eq!(turndown_core::serialize(&ast, &options), "**Prices**\n\n| A   |\n| --- |");
+
+        // The summary attribute stands in for a missing caption
+        let result = convert_tables(
+            "<table summary=\" Unit  prices \"><tr><th>A</th></tr><tr><td>1</td></tr></table>",
+        );
+        assert_eq!(result, "Unit prices\n\n| A   |\n| --- |\n| 1   |");
+        let result = convert_tables(
+            "<table summary=\"Unit prices\"><caption>Prices</caption><tr><th>A</th></tr></table>",
+        );
+        assert_eq!(result, "Prices\n\n| A   |\n| --- |");
+    }
+
+    #[test]
+    fn test_markup_elements() {
+        let html = "<p><s>old</s> or <mark>new</mark> x<sup>2</sup> H<sub>2</sub>O</p>";
+        assert_eq!(convert(html), "old or new x2 H2O");
+
+        let options = Options {
+            strikethrough_style: MarkupStyle::Markdown,
+            highlight_style: MarkupStyle::Markdown,
+            script_style: MarkupStyle::Html,
+            ..Options::default()
+        };
+        let ast = html_to_ast(html, &options, &Filters::default()).unwrap();
+        assert_eq!(
+            turndown_core::serialize(&ast, &options),
+            "~~old~~ or ==new== x<sup>2</sup> H<sub>2</sub>O"
+        );
+    }
+
+    #[test]
+    fn test_task_list_items() {
+        let options = Options {
+            task_lists: true,
+            ..Options::default()
+        };
+        let html = "<ul><li><input type=\"checkbox\" checked> Done</li>\
+                    <li><label><input type=\"checkbox\"> Todo</label></li>\
+                    <li>Plain <input type=\"checkbox\"></li></ul>";
+        let ast = html_to_ast(html, &options, &Filters::default()).unwrap();
+        assert_eq!(
+            turndown_core::serialize(&ast, &options),
+            "*   [x] Done\n*   [ ] Todo\n*   Plain"
+        );
+        assert_eq!(convert(html), "*   Done\n*   Todo\n*   Plain");
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(
+            convert("<p>Tom &amp; Jerry&rsquo;s&nbsp;show &#8211; &lt;live&gt; &copy2024</p>"),
+            "Tom & Jerry’s\u{a0}show – <live> ©2024"
+        );
+        assert_eq!(
+            convert("<a href=\"/s?a=1&amp;b=2&copy=3\" title=\"Q&amp;A\">x</a>"),
+            "[x](/s?a=1&b=2&copy=3 \"Q&A\")"
+        );
+        assert_eq!(convert("<img src=\"a.png\" alt=\"A &amp; B\">"), "![A & B](a.png)");
+        assert_eq!(
+            convert("<pre><code>if a &lt; b &amp;&amp; c</code></pre>"),
+            "    if a < b && c"
+        );
+    }
+
+    #[test]
+    fn test_whitespace_between_inline_elements() {
+        assert_eq!(convert("<p><b>a</b> <i>b</i></p>"), "**a** _b_");
+        assert_eq!(convert("<p>\n  one\n  <em>two</em>\n  three\n</p>"), "one _two_ three");
+        assert_eq!(convert("<p>a <img src=\"x.png\"> b</p>"), "a ![](x.png) b");
+    }
+
+    #[test]
+    fn test_loose_inline_content() {
+        assert_eq!(convert("<div>text <b>a</b> more</div>"), "text **a** more");
+        assert_eq!(convert("<b>a</b> <i>b</i>"), "**a** _b_");
+        assert_eq!(
+            convert("<div>one <a href=\"/x\">x</a><p>two</p>three <br> four</div>"),
+            "one [x](/x)\n\ntwo\n\nthree  \nfour"
+        );
+        assert_eq!(
+            convert("<ul><li>a <em>b</em><ul><li>c</li></ul></li></ul>"),
+            "*   a _b_\n    *   c"
+        );
+    }
+
+    #[test]
+    fn test_flanking_whitespace() {
+        assert_eq!(convert("<p>a<b> bold </b>b</p>"), "a **bold** b");
+        assert_eq!(convert("<p>see<a href=\"/x\"> link</a></p>"), "see [link](/x)");
+    }
+
+    #[test]
+    fn test_whitespace_at_block_boundaries() {
+        assert_eq!(convert("<div> <p> a </p> <p>b <br> c</p> </div>"), "a\n\nb  \nc");
+        assert_eq!(convert("<pre>  x    y</pre>"), "      x    y");
+    }
+
+    #[test]
+    fn test_keep_inline() {
+        let filters = Filters {
+            keep: vec!["del".to_string()],
+            ..Default::default()
+        };
+        let result = convert_with_filters("<p>Hello <del class=\"x\">World</del></p>", &filters);
+        assert_eq!(result, "Hello <del class=\"x\">World</del>");
+    }
+
+    #[test]
+    fn test_keep_block() {
+        let filters = Filters {
+            keep: vec!["figure".to_string()],
+            ..Default::default()
+        };
+        let result = convert_with_filters(
+            "<p>Before</p><figure><img src=\"a.png\"></figure><p>After</p>",
+            &filters,
+        );
+        assert_eq!(result, "Before\n\n<figure><img src=\"a.png\"></figure>\n\nAfter");
+    }
+
+    #[test]
+    fn test_keep_ignores_builtin_and_blank() {
+        let filters = Filters {
+            keep: vec!["strong".to_string(), "span".to_string()],
+            ..Default::default()
+        };
+        let result = convert_with_filters("<p><strong>bold</strong><span> </span></p>", &filters);
+        assert_eq!(result, "**bold**");
+    }
+
+    #[test]
+    fn test_remove() {
+        let filters = Filters {
+            remove: vec!["aside".to_string(), "span".to_string()],
+            ..Default::default()
+        };
+        let result = convert_with_filters(
+            "<p>Hello<span> there</span></p><aside><p>Ad</p></aside><p>World</p>",
+            &filters,
+        );
+        assert_eq!(result, "Hello\n\nWorld");
+    }
+
+    fn convert_with_rules(html: &str, rules: Rules) -> String {
+        let filters = Filters {
+            rules,
+            ..Default::default()
+        };
+        convert_with_filters(html, &filters)
+    }
+
+    #[test]
+    fn test_rule_by_tag() {
+        use turndown_core::Rule;
+
+        let mut rules = Rules::new();
+        rules.add(
+            "callout",
+            Rule::new("aside", |_, content| {
+                Replacement::Blocks(vec![Block::BlockQuote(content.into_blocks())])
+            }),
+        );
+        let result = convert_with_rules("<aside><p>Note</p><p>More</p></aside>", rules);
+        assert_eq!(result, "> Note\n>\n> More");
+    }
+
+    #[test]
+    fn test_rule_overrides_builtin() {
+        use turndown_core::Rule;
+
+        let mut rules = Rules::new();
+        rules.add("plain-strong", Rule::new("strong", |_, c| Replacement::Inlines(c.into_inlines())));
+        let result = convert_with_rules("<p>a <strong>b</strong></p>", rules);
+        assert_eq!(result, "a b");
+    }
+
+    #[test]
+    fn test_rule_predicate_with_attributes_and_ancestors() {
+        use turndown_core::{Filter, Rule};
+
+        let mut rules = Rules::new();
+        rules.add(
+            "embed",
+            Rule::new(
+                Filter::predicate(|el| el.attr("data-embed").is_some() && el.has_ancestor("section")),
+                |el, _| {
+                    Replacement::Inlines(vec![Inline::Link {
+                        content: vec![Inline::Text("Embed".to_string())],
+                        url: el.attr("data-embed").unwrap_or_default().to_string(),
+                        title: None,
+                    }])
+                },
+            ),
+        );
+        let result = convert_with_rules(
+            "<section><p>See <span data-embed=\"https://v.example/1\">video</span></p></section>\
+             <p><span data-embed=\"https://v.example/2\">x</span></p>",
+            rules,
+        );
+        assert_eq!(result, "See [Embed](https://v.example/1)\n\nx");
+    }
+
+    #[test]
+    fn test_rule_precedes_keep() {
+        use turndown_core::Rule;
+
+        let mut rules = Rules::new();
+        rules.add("drop-del", Rule::new("del", |_, _| Replacement::remove()));
+        let filters = Filters {
+            rules,
+            keep: vec!["del".to_string()],
+            ..Default::default()
+        };
+        let result = convert_with_filters("<p>a<del>b</del></p>", &filters);
+        assert_eq!(result, "a");
+    }
+
+    /// Content, outer HTML, ancestors and text content of a replacement call
+    type Call = (String, String, Vec<String>, String);
+
+    /// Callback rules recording what they were called with
+    struct Recorder {
+        tags: Vec<&'static str>,
+        calls: RefCell<Vec<Call>>,
+    }
+
+    impl CallbackRules for Recorder {
+        fn may_match(&self, tag_lower: &str) -> bool {
+            self.tags.contains(&tag_lower)
+        }
+
+        fn find(&self, node: &CallbackNode) -> Option<usize> {
+            node.attr("data-skip").is_none().then_some(0)
+        }
+
+        fn replace(&self, _index: usize, content: &str, node: &CallbackNode) -> String {
+            self.calls.borrow_mut().push((
+                content.to_string(),
+                node.outer_html().to_string(),
+                node.ancestors(),
+                node.text_content(),
+            ));
+            if node.is_block {
+                format!("\n\n:::note\n{}\n:::\n\n", content)
+            } else {
+                format!("=={}==", content)
+            }
+        }
+
+        fn has_filters(&self) -> bool {
+            false
+        }
+
+        fn keeps(&self, _node: &CallbackNode) -> bool {
+            false
+        }
+
+        fn removes(&self, _node: &CallbackNode) -> bool {
+            false
+        }
+    }
+
+    /// Keep and remove predicates matching on an attribute
+    struct AttributeFilters;
+
+    impl CallbackRules for AttributeFilters {
+        fn may_match(&self, _tag_lower: &str) -> bool {
+            false
+        }
+
+        fn find(&self, _node: &CallbackNode) -> Option<usize> {
+            None
+        }
+
+        fn replace(&self, _index: usize, _content: &str, _node: &CallbackNode) -> String {
+            String::new()
+        }
+
+        fn has_filters(&self) -> bool {
+            true
+        }
+
+        fn keeps(&self, node: &CallbackNode) -> bool {
+            node.attr("data-keep").is_some()
+        }
+
+        fn removes(&self, node: &CallbackNode) -> bool {
+            node.ancestors().iter().any(|tag| tag == "aside")
+        }
+    }
+
+    #[test]
+    fn test_predicate_filters() {
+        let options = Options::default();
+        let ast = html_to_ast_with_callbacks(
+            Rc::new("<p>a <span data-keep>b</span> <em data-keep>c</em></p>\
+             <aside><div data-keep> </div><p>x <span>y</span></p></aside>".to_string()),
+            &options,
+            &Filters::default(),
+            &AttributeFilters,
+        ).unwrap();
+        assert_eq!(
+            turndown_core::serialize(&ast, &options),
+            "a <span data-keep>b</span> _c_\n\nx"
+        );
+    }
+
+    #[test]
+    fn test_callback_rules() {
+        let recorder = Recorder {
+            tags: vec!["aside", "mark"],
+            calls: RefCell::new(Vec::new()),
+        };
+        let options = Options::default();
+        let html = "<div><aside><p>Hi <mark>there</mark></p><pre>a  &lt;b</pre></aside></div>\
+                    <p><mark data-skip>x</mark></p><aside> <mark></mark> </aside>";
+        let ast = html_to_ast_with_callbacks(
+            Rc::new(html.to_string()),
+            &options,
+            &Filters::default(),
+            &recorder,
+        ).unwrap();
+        let result = turndown_core::serialize(&ast, &options);
+
+        assert_eq!(result, ":::note\nHi ==there==\n\n    a  <b\n:::\n\nx");
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, "there");
+        assert_eq!(calls[0].1, "<mark>there</mark>");
+        assert_eq!(calls[0].2, ["div", "aside", "p"]);
+        assert_eq!(calls[0].3, "there");
+        assert_eq!(calls[1].0, "Hi ==there==\n\n    a  <b");
+        assert_eq!(calls[1].3, "Hi therea  <b");
+    }
+
+    /// A turndown.js style `li` rule writing its own list marker
+    struct DashItems;
+
+    impl CallbackRules for DashItems {
+        fn may_match(&self, tag_lower: &str) -> bool {
+            tag_lower == "li"
+        }
+
+        fn find(&self, _node: &CallbackNode) -> Option<usize> {
+            Some(0)
+        }
+
+        fn replace(&self, _index: usize, content: &str, _node: &CallbackNode) -> String {
+            format!("- {}", content)
+        }
+
+        fn has_filters(&self) -> bool {
+            false
+        }
+
+        fn keeps(&self, _node: &CallbackNode) -> bool {
+            false
+        }
+
+        fn removes(&self, _node: &CallbackNode) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_callback_rule_replaces_list_item() {
+        let options = Options::default();
+        let ast = html_to_ast_with_callbacks(
+            Rc::new("<ul><li>a</li><li>b</li></ul><p>c</p>".to_string()),
+            &options,
+            &Filters::default(),
+            &DashItems,
+        ).unwrap();
+        assert_eq!(turndown_core::serialize(&ast, &options), "- a\n- b\n\nc");
+    }
+
+    #[test]
+    fn test_callback_content_inlines_links() {
+        let recorder = Recorder {
+            tags: vec!["mark"],
+            calls: RefCell::new(Vec::new()),
+        };
+        let options = Options {
+            link_style: LinkStyle::Referenced,
+            ..Options::default()
+        };
+        let html = r#"<p><a href="/a">A</a> <mark><a href="/b">B</a></mark> <a href="/c">C</a></p>"#;
+        let ast = html_to_ast_with_callbacks(
+            Rc::new(html.to_string()),
+            &options,
+            &Filters::default(),
+            &recorder,
+        ).unwrap();
+        let result = turndown_core::serialize(&ast, &options);
+
+        assert_eq!(result, "[A][1] ==[B](/b)== [C][2]\n\n[1]: /a\n[2]: /c");
+    }
+
+    fn convert_limited(html: &str, limits: Limits) -> Result<String> {
+        let options = Options {
+            limits,
+            ..Options::default()
+        };
+        let ast = html_to_ast(html, &options, &Filters::default())?;
+        let result = turndown_core::serialize_limited(&ast, &options);
+        ast.drop_iteratively();
+        result
+    }
+
+    #[test]
+    fn test_limits() {
+        let limit = |limit, max| Err(TurndownError::LimitExceeded { limit, max });
+
+        // Deep enough to overflow the stack of a recursive conversion
+        let nested = format!(
+            "<p>before</p>{}deep{}<p>after</p>",
+            "<div>".repeat(100_000),
+            "</div>".repeat(100_000)
+        );
+        let depth = Limits {
+            max_depth: Some(10),
+            ..Limits::default()
+        };
+        assert_eq!(convert_limited(&nested, depth), limit("depth", 10));
+        let truncate = Limits {
+            mode: LimitMode::Truncate,
+            ..depth
+        };
+        assert_eq!(convert_limited(&nested, truncate).unwrap(), "before");
+
+        let html = "<p>one</p><p>two</p><p>three</p>";
+        let nodes = Limits {
+            max_nodes: Some(4),
+            ..Limits::default()
+        };
+        assert_eq!(convert_limited(html, nodes), limit("node count", 4));
+        let truncate = Limits {
+            mode: LimitMode::Truncate,
+            ..nodes
+        };
+        assert_eq!(convert_limited(html, truncate).unwrap(), "one\n\ntwo");
+
+        let input = Limits {
+            max_input_bytes: Some(18),
+            ..Limits::default()
+        };
+        assert_eq!(convert_limited(html, input), limit("input size", 18));
+        let truncate = Limits {
+            mode: LimitMode::Truncate,
+            ..input
+        };
+        assert_eq!(convert_limited(html, truncate).unwrap(), "one\n\ntwo");
+
+        let output = Limits {
+            max_output_bytes: Some(5),
+            mode: LimitMode::Truncate,
+            ..Limits::default()
+        };
+        assert_eq!(convert_limited(html, output).unwrap(), "one");
+    }
+
+    #[test]
+    fn test_abort() {
+        let html = "<div><p>one</p><p>two</p></div>";
+        let options = Options::default();
+        let filters = Filters::default();
+
+        let abort = AtomicBool::new(false);
+        let ast = html_to_ast_abortable(html, &options, &filters, &abort).unwrap();
+        assert_eq!(turndown_core::serialize(&ast, &options), "one\n\ntwo");
+
+        abort.store(true, Ordering::Relaxed);
+        assert_eq!(
+            html_to_ast_abortable(html, &options, &filters, &abort),
+            Err(TurndownError::Aborted)
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        use turndown_core::Rule;
+
+        let nested = |open: &str, close: &str, depth| {
+            format!("{}deep{}", open.repeat(depth), close.repeat(depth))
+        };
+
+        // Nesting that collapses into a flat AST
+        assert_eq!(convert(&nested("<div>", "</div>", 100_000)), "deep");
+        let spans = nested("<span>", "</span>", 100_000);
+        assert_eq!(convert(&format!("<p>{spans}</p>")), "deep");
+
+        // Nesting kept in the AST
+        let quotes = convert(&nested("<blockquote>", "</blockquote>", 50_000));
+        assert_eq!(quotes, format!("{}deep", "> ".repeat(50_000)));
+        let strong = convert(&format!("<p>{}</p>", nested("<b>", "</b>", 50_000)));
+        assert_eq!(strong, format!("{}deep{}", "**".repeat(50_000), "**".repeat(50_000)));
+
+        // Leading blank text nodes are trimmed without recursing per node
+        let mut inlines = vec![Inline::Text(" ".to_string()); 100_000];
+        inlines.push(Inline::Text(" deep ".to_string()));
+        trim_inlines(&mut inlines);
+        assert!(matches!(inlines.as_slice(), [Inline::Text(text)] if text == "deep"));
+
+        // Every list level is indented further, so the output is cut by the
+        // output limit
+        let lists = nested("<ul><li>a", "</li></ul>", 50_000);
+        let limits = Limits {
+            max_output_bytes: Some(1 << 16),
+            mode: LimitMode::Truncate,
+            ..Limits::default()
+        };
+        let result = convert_limited(&lists, limits).unwrap();
+        assert!(result.starts_with("*   a\n    *   a\n        *   a\n"));
+        assert!(result.len() <= 1 << 16 && result.ends_with('a'));
+
+        // Ancestors are tracked across the whole depth
+        let mut rules = Rules::new();
+        rules.add(
+            "ancestors",
+            Rule::new("em", |el, _| {
+                let depth = el.ancestors.iter().filter(|tag| *tag == "span").count();
+                Replacement::Inlines(vec![Inline::Text(format!("{} {depth}", el.ancestors[0]))])
+            }),
+        );
+        let html = format!("<div>{}</div>", nested("<span>", "</span>", 100_000));
+        let result = convert_with_rules(&html.replace("deep", "<em>deep</em>"), rules);
+        assert_eq!(result, "div 100000");
+    }
 }
 
 #[cfg(test)]
@@ -709,7 +2612,7 @@ mod profiling {
         // Full pipeline
         let start = Instant::now();
         for _ in 0..iterations {
-            let ast = html_to_ast(&html, &options);
+            let ast = html_to_ast(&html, &options, &Filters::default()).unwrap();
             let _ = turndown_core::serialize(&ast, &options);
         }
         let full_pipeline = start.elapsed();
@@ -8,19 +8,56 @@ export interface Options {
   strongDelimiter?: "**" | "__";
   linkStyle?: "inlined" | "referenced";
   linkReferenceStyle?: "full" | "collapsed" | "shortcut";
+  tables?: boolean;
+  taskLists?: boolean;
+  strikethroughStyle?: "plain" | "markdown" | "html";
+  highlightStyle?: "plain" | "markdown" | "html";
+  scriptStyle?: "plain" | "markdown" | "html";
+  tableLineBreak?: string;
+  tableFallback?: "html" | "list";
+  tableSpans?: "fallback" | "blank" | "duplicate";
+  captionPosition?: "above" | "below";
+  captionStyle?: "plain" | "bold" | "pandoc";
+  maxDepth?: number;
+  maxInputBytes?: number;
+  maxOutputBytes?: number;
+  maxNodes?: number;
+  limitMode?: "error" | "truncate";
 }
 
+export interface NodeDescription {
+  readonly nodeName: string;
+  readonly attributes: Record<string, string>;
+  readonly className: string;
+  readonly id: string;
+  readonly textContent: string;
+  readonly outerHTML: string;
+  readonly isBlock: boolean;
+  readonly ancestors: string[];
+}
+
+export type Filter =
+  | string
+  | string[]
+  | ((node: NodeDescription, options: Options) => boolean);
+
 export interface Rule {
-  filter: string | string[] | ((node: Node) => boolean);
-  replacement: (content: string, node: Node, options: Options) => string;
+  filter: Filter;
+  replacement: (
+    content: string,
+    node: NodeDescription,
+    options: Options
+  ) => string;
 }
 
 export class TurndownService {
   constructor(options?: Options);
+  readonly options: Options;
   turndown(html: string): string;
+  turndownAsync(html: string, signal?: AbortSignal): Promise<string>;
   addRule(key: string, rule: Rule): this;
-  keep(filter: string | string[]): this;
-  remove(filter: string | string[]): this;
+  keep(filter: Filter): this;
+  remove(filter: Filter): this;
   use(plugin: (service: TurndownService) => void): this;
   escape(str: string): string;
 }